@@ -1,6 +1,6 @@
 //! [Bevy](https://bevyengine.org/) plugin implementing the [MToon](https://vrm.dev/en/univrm/shaders/shader_mtoon.html) shader.
 
-use bevy::{asset::load_internal_asset, prelude::*};
+use bevy::{asset::load_internal_asset, prelude::*, render::RenderApp};
 
 mod shader;
 
@@ -16,9 +16,15 @@ impl Plugin for MtoonPlugin {
     fn build(&self, app: &mut App) {
         load_internal_asset!(app, SHADER_HANDLE, "mtoon.wgsl", Shader::from_wgsl);
 
+        // Outlines need a render app, which headless apps do not have.
+        if app.get_sub_app(RenderApp).is_ok() {
+            app.add_plugins(OutlinePlugin)
+                .add_systems(Update, (add_outline, sync_outline));
+        }
+
         app.register_type::<OutlineSync>()
-            .add_plugins((OutlinePlugin, MaterialPlugin::<MtoonMaterial>::default()))
-            .add_systems(Update, (update_mtoon_shader, add_outline, sync_outline));
+            .add_plugins(MaterialPlugin::<MtoonMaterial>::default())
+            .add_systems(Update, update_mtoon_shader);
     }
 }
 
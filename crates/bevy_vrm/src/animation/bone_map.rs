@@ -0,0 +1,124 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde_vrm::vrm0::BoneName;
+use thiserror::Error;
+
+/// Maps the bone names of another skeleton to humanoid bones.
+/// Loaded from `.bonemap.json` files, an object of source names to VRM bone names
+/// such as `{ "mixamorig:Hips": "hips" }`.
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct BoneMap(pub HashMap<String, BoneName>);
+
+impl BoneMap {
+    /// The humanoid bones of a Mixamo skeleton.
+    /// Names match with or without a prefix such as `mixamorig:`.
+    pub fn mixamo() -> Self {
+        use BoneName::*;
+
+        let mut map = HashMap::new();
+
+        for (name, bone) in [
+            ("Hips", Hips),
+            ("Spine", Spine),
+            ("Spine1", Chest),
+            ("Spine2", UpperChest),
+            ("Neck", Neck),
+            ("Head", Head),
+            ("LeftShoulder", LeftShoulder),
+            ("LeftArm", LeftUpperArm),
+            ("LeftForeArm", LeftLowerArm),
+            ("LeftHand", LeftHand),
+            ("RightShoulder", RightShoulder),
+            ("RightArm", RightUpperArm),
+            ("RightForeArm", RightLowerArm),
+            ("RightHand", RightHand),
+            ("LeftUpLeg", LeftUpperLeg),
+            ("LeftLeg", LeftLowerLeg),
+            ("LeftFoot", LeftFoot),
+            ("LeftToeBase", LeftToes),
+            ("RightUpLeg", RightUpperLeg),
+            ("RightLeg", RightLowerLeg),
+            ("RightFoot", RightFoot),
+            ("RightToeBase", RightToes),
+            ("LeftHandThumb1", LeftThumbProximal),
+            ("LeftHandThumb2", LeftThumbIntermediate),
+            ("LeftHandThumb3", LeftThumbDistal),
+            ("LeftHandIndex1", LeftIndexProximal),
+            ("LeftHandIndex2", LeftIndexIntermediate),
+            ("LeftHandIndex3", LeftIndexDistal),
+            ("LeftHandMiddle1", LeftMiddleProximal),
+            ("LeftHandMiddle2", LeftMiddleIntermediate),
+            ("LeftHandMiddle3", LeftMiddleDistal),
+            ("LeftHandRing1", LeftRingProximal),
+            ("LeftHandRing2", LeftRingIntermediate),
+            ("LeftHandRing3", LeftRingDistal),
+            ("LeftHandPinky1", LeftLittleProximal),
+            ("LeftHandPinky2", LeftLittleIntermediate),
+            ("LeftHandPinky3", LeftLittleDistal),
+            ("RightHandThumb1", RightThumbProximal),
+            ("RightHandThumb2", RightThumbIntermediate),
+            ("RightHandThumb3", RightThumbDistal),
+            ("RightHandIndex1", RightIndexProximal),
+            ("RightHandIndex2", RightIndexIntermediate),
+            ("RightHandIndex3", RightIndexDistal),
+            ("RightHandMiddle1", RightMiddleProximal),
+            ("RightHandMiddle2", RightMiddleIntermediate),
+            ("RightHandMiddle3", RightMiddleDistal),
+            ("RightHandRing1", RightRingProximal),
+            ("RightHandRing2", RightRingIntermediate),
+            ("RightHandRing3", RightRingDistal),
+            ("RightHandPinky1", RightLittleProximal),
+            ("RightHandPinky2", RightLittleIntermediate),
+            ("RightHandPinky3", RightLittleDistal),
+        ] {
+            map.insert(name.to_string(), bone);
+        }
+
+        Self(map)
+    }
+
+    /// The humanoid bone for `name`, also trying the name without a `prefix:`.
+    pub fn get(&self, name: &str) -> Option<&BoneName> {
+        self.0.get(name).or_else(|| {
+            let (_, unprefixed) = name.rsplit_once(':')?;
+            self.0.get(unprefixed)
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct BoneMapLoader;
+
+#[derive(Debug, Error)]
+pub enum BoneMapError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+impl AssetLoader for BoneMapLoader {
+    type Asset = BoneMap;
+    type Settings = ();
+    type Error = BoneMapError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(BoneMap(serde_json::from_slice(&bytes)?))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bonemap.json"]
+    }
+}
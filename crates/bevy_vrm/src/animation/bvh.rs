@@ -0,0 +1,504 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use serde_vrm::vrm0::BoneName;
+use thiserror::Error;
+
+use super::{leg_length, Interpolation, Track, VrmAnimation};
+
+#[derive(Default)]
+pub struct BvhLoader;
+
+#[derive(Debug, Error)]
+pub enum BvhError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+    #[error("Unexpected token: {0}")]
+    UnexpectedToken(String),
+    #[error("Invalid number: {0}")]
+    InvalidNumber(String),
+    #[error("Unknown channel: {0}")]
+    UnknownChannel(String),
+}
+
+impl AssetLoader for BvhLoader {
+    type Asset = VrmAnimation;
+    type Settings = ();
+    type Error = BvhError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut text = String::new();
+            reader.read_to_string(&mut text).await?;
+            Ok(Bvh::parse(&text)?.to_animation())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bvh"]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BvhChannel {
+    XPosition,
+    YPosition,
+    ZPosition,
+    XRotation,
+    YRotation,
+    ZRotation,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct BvhJoint {
+    pub name: String,
+    /// Index of the parent joint, [`None`] for roots.
+    pub parent: Option<usize>,
+    /// Position relative to the parent joint.
+    pub offset: Vec3,
+    pub channels: Vec<BvhChannel>,
+}
+
+/// A parsed BVH file. Joints have no rotation at rest.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    /// Joints in file order, with parents before their children.
+    pub joints: Vec<BvhJoint>,
+    /// Seconds between frames.
+    pub frame_time: f32,
+    /// Channel values of each frame, in joint and channel order.
+    /// Rotations are in degrees.
+    pub frames: Vec<Vec<f32>>,
+}
+
+impl Bvh {
+    pub fn parse(text: &str) -> Result<Self, BvhError> {
+        let mut tokens = Tokens(text.split_whitespace());
+        let mut bvh = Self::default();
+
+        tokens.expect("HIERARCHY")?;
+
+        loop {
+            match tokens.next()? {
+                "ROOT" => bvh.parse_joint(&mut tokens, None)?,
+                "MOTION" => break,
+                token => return Err(BvhError::UnexpectedToken(token.to_string())),
+            }
+        }
+
+        tokens.expect("Frames:")?;
+        let frame_count = tokens.number()? as usize;
+        tokens.expect("Frame")?;
+        tokens.expect("Time:")?;
+        bvh.frame_time = tokens.number()?;
+
+        let channel_count = bvh.joints.iter().map(|j| j.channels.len()).sum();
+
+        for _ in 0..frame_count {
+            let frame = (0..channel_count)
+                .map(|_| tokens.number())
+                .collect::<Result<_, _>>()?;
+            bvh.frames.push(frame);
+        }
+
+        Ok(bvh)
+    }
+
+    fn parse_joint(&mut self, tokens: &mut Tokens, parent: Option<usize>) -> Result<(), BvhError> {
+        let name = tokens.next()?.to_string();
+        let index = self.joints.len();
+
+        tokens.expect("{")?;
+        tokens.expect("OFFSET")?;
+        let offset = Vec3::new(tokens.number()?, tokens.number()?, tokens.number()?);
+
+        self.joints.push(BvhJoint {
+            name,
+            parent,
+            offset,
+            channels: Vec::new(),
+        });
+
+        loop {
+            match tokens.next()? {
+                "CHANNELS" => {
+                    let count = tokens.number()? as usize;
+
+                    for _ in 0..count {
+                        let channel = match tokens.next()? {
+                            "Xposition" => BvhChannel::XPosition,
+                            "Yposition" => BvhChannel::YPosition,
+                            "Zposition" => BvhChannel::ZPosition,
+                            "Xrotation" => BvhChannel::XRotation,
+                            "Yrotation" => BvhChannel::YRotation,
+                            "Zrotation" => BvhChannel::ZRotation,
+                            token => return Err(BvhError::UnknownChannel(token.to_string())),
+                        };
+                        self.joints[index].channels.push(channel);
+                    }
+                }
+                "JOINT" => self.parse_joint(tokens, Some(index))?,
+                "End" => {
+                    // End sites only mark the tip of the last bone.
+                    tokens.expect("Site")?;
+                    tokens.expect("{")?;
+                    tokens.expect("OFFSET")?;
+                    for _ in 0..3 {
+                        tokens.number()?;
+                    }
+                    tokens.expect("}")?;
+                }
+                "}" => return Ok(()),
+                token => return Err(BvhError::UnexpectedToken(token.to_string())),
+            }
+        }
+    }
+
+    /// Converts the motion into a [`VrmAnimation`], mapping joints to humanoid bones by name.
+    /// The skeleton should be in a T-pose facing +Z at rest.
+    pub fn to_animation(&self) -> VrmAnimation {
+        let mut animation = VrmAnimation::default();
+
+        // The first joint found for each bone, closest to the root.
+        let mut bones = HashMap::<usize, BoneName>::new();
+        for (index, joint) in self.joints.iter().enumerate() {
+            if let Some(bone) = joint_bone_name(&joint.name) {
+                if !bones.values().any(|b| *b == bone) {
+                    bones.insert(index, bone);
+                }
+            }
+        }
+
+        let humanoid_parents = self
+            .joints
+            .iter()
+            .map(|joint| {
+                let mut parent = joint.parent;
+                while let Some(index) = parent {
+                    if bones.contains_key(&index) {
+                        break;
+                    }
+                    parent = self.joints[index].parent;
+                }
+                parent
+            })
+            .collect::<Vec<_>>();
+
+        let mut rest_positions = HashMap::new();
+        let mut rest = vec![Vec3::ZERO; self.joints.len()];
+        for (index, joint) in self.joints.iter().enumerate() {
+            let parent = joint.parent.map(|p| rest[p]).unwrap_or_default();
+            rest[index] = parent + joint.offset;
+
+            if let Some(bone) = bones.get(&index) {
+                rest_positions.insert(bone.clone(), rest[index]);
+            }
+        }
+
+        if let (Some(upper_leg), Some(lower_leg), Some(foot)) = (
+            rest_positions.get(&BoneName::LeftUpperLeg),
+            rest_positions.get(&BoneName::LeftLowerLeg),
+            rest_positions.get(&BoneName::LeftFoot),
+        ) {
+            animation.leg_length = leg_length(*upper_leg, *lower_leg, *foot);
+        }
+
+        let times = (0..self.frames.len())
+            .map(|i| i as f32 * self.frame_time)
+            .collect::<Vec<_>>();
+
+        let mut rotations = HashMap::<BoneName, Vec<Quat>>::new();
+        let mut hips_positions = Vec::new();
+
+        for frame in self.frames.iter() {
+            let globals = self.frame_globals(frame);
+
+            for (index, bone) in bones.iter() {
+                let parent = humanoid_parents[*index]
+                    .map(|p| globals[p].rotation)
+                    .unwrap_or_default();
+                let rotation = (parent.inverse() * globals[*index].rotation).normalize();
+
+                rotations.entry(bone.clone()).or_default().push(rotation);
+
+                if *bone == BoneName::Hips {
+                    hips_positions.push(globals[*index].translation);
+                }
+            }
+        }
+
+        for (bone, values) in rotations {
+            animation.rotations.insert(
+                bone,
+                Track::new(times.clone(), values, Interpolation::Linear),
+            );
+        }
+
+        if !hips_positions.is_empty() {
+            animation.hips_position =
+                Some(Track::new(times, hips_positions, Interpolation::Linear));
+        }
+
+        animation.update_duration();
+        animation
+    }
+
+    /// Pose of every joint relative to the scene for one frame.
+    fn frame_globals(&self, frame: &[f32]) -> Vec<Transform> {
+        let mut globals = Vec::<Transform>::with_capacity(self.joints.len());
+        let mut values = frame.iter().copied();
+
+        for joint in self.joints.iter() {
+            let mut local = Transform::from_translation(joint.offset);
+            let mut position = None::<Vec3>;
+
+            // Rotations apply in channel order, each one relative to the previous.
+            for channel in joint.channels.iter() {
+                let value = values.next().unwrap_or_default();
+                let angle = value.to_radians();
+
+                match channel {
+                    BvhChannel::XPosition => position.get_or_insert(Vec3::ZERO).x = value,
+                    BvhChannel::YPosition => position.get_or_insert(Vec3::ZERO).y = value,
+                    BvhChannel::ZPosition => position.get_or_insert(Vec3::ZERO).z = value,
+                    BvhChannel::XRotation => local.rotation *= Quat::from_rotation_x(angle),
+                    BvhChannel::YRotation => local.rotation *= Quat::from_rotation_y(angle),
+                    BvhChannel::ZRotation => local.rotation *= Quat::from_rotation_z(angle),
+                }
+            }
+
+            if let Some(position) = position {
+                local.translation = position;
+            }
+
+            let global = match joint.parent {
+                Some(parent) => globals[parent] * local,
+                None => local,
+            };
+            globals.push(global);
+        }
+
+        globals
+    }
+}
+
+struct Tokens<'a>(std::str::SplitWhitespace<'a>);
+
+impl<'a> Tokens<'a> {
+    fn next(&mut self) -> Result<&'a str, BvhError> {
+        self.0.next().ok_or(BvhError::UnexpectedEnd)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), BvhError> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(BvhError::UnexpectedToken(token.to_string())),
+        }
+    }
+
+    fn number(&mut self) -> Result<f32, BvhError> {
+        let token = self.next()?;
+        token
+            .parse()
+            .map_err(|_| BvhError::InvalidNumber(token.to_string()))
+    }
+}
+
+/// Guesses the humanoid bone of a joint from common naming schemes,
+/// such as `LeftUpLeg`, `lThigh` or `mixamorig:LeftArm`.
+fn joint_bone_name(name: &str) -> Option<BoneName> {
+    let name = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+
+    let (side, rest) = if let Some(rest) = name.strip_prefix("left") {
+        (Some("left"), rest.to_string())
+    } else if let Some(rest) = name.strip_prefix("right") {
+        (Some("right"), rest.to_string())
+    } else if let Some(rest) = name.strip_suffix("_l").or(name.strip_suffix(".l")) {
+        (Some("left"), rest.to_string())
+    } else if let Some(rest) = name.strip_suffix("_r").or(name.strip_suffix(".r")) {
+        (Some("right"), rest.to_string())
+    } else {
+        (None, name.clone())
+    };
+
+    let rest = rest.replace(['_', '.', ' ', '-'], "");
+
+    let vrm_name = match side {
+        Some(side) => format!("{}{}", side, sided_part(&rest)?),
+        None => match rest.as_str() {
+            "hips" | "hip" | "pelvis" => "hips".to_string(),
+            "spine" | "abdomen" => "spine".to_string(),
+            "spine1" | "chest" => "chest".to_string(),
+            "spine2" | "chest2" | "upperchest" => "upperChest".to_string(),
+            "neck" => "neck".to_string(),
+            "head" => "head".to_string(),
+            "jaw" => "jaw".to_string(),
+            // Single letter sides, as in `lShldr` or `RHand`.
+            _ => {
+                let side = match rest.chars().next()? {
+                    'l' => "left",
+                    'r' => "right",
+                    _ => return None,
+                };
+                format!("{}{}", side, sided_part(&rest[1..])?)
+            }
+        },
+    };
+
+    serde_json::from_value(serde_json::Value::String(vrm_name)).ok()
+}
+
+/// The VRM name of a sided joint, without the side.
+fn sided_part(part: &str) -> Option<String> {
+    let part = match part {
+        "shoulder" | "collar" | "clavicle" => "Shoulder",
+        "arm" | "upperarm" | "shldr" | "humerus" => "UpperArm",
+        "forearm" | "lowerarm" | "elbow" => "LowerArm",
+        "hand" | "wrist" => "Hand",
+        "upleg" | "upperleg" | "thigh" | "femur" | "hip" => "UpperLeg",
+        "leg" | "lowerleg" | "shin" | "knee" | "tibia" => "LowerLeg",
+        "foot" | "ankle" => "Foot",
+        "toebase" | "toe" | "toes" => "Toes",
+        "eye" => "Eye",
+        _ => return finger_part(part.strip_prefix("hand").unwrap_or(part)),
+    };
+
+    Some(part.to_string())
+}
+
+/// Finger joints such as `index1` or `thumbproximal`.
+fn finger_part(part: &str) -> Option<String> {
+    let (finger, segment) = ["thumb", "index", "middle", "ring", "pinky", "little"]
+        .into_iter()
+        .find_map(|finger| Some((finger, part.strip_prefix(finger)?)))?;
+
+    let finger = match finger {
+        "thumb" => "Thumb",
+        "index" => "Index",
+        "middle" => "Middle",
+        "ring" => "Ring",
+        _ => "Little",
+    };
+
+    let segment = match segment {
+        "1" | "proximal" => "Proximal",
+        "2" | "intermediate" => "Intermediate",
+        "3" | "distal" => "Distal",
+        _ => return None,
+    };
+
+    Some(format!("{}{}", finger, segment))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BVH: &str = "
+HIERARCHY
+ROOT Hips
+{
+    OFFSET 0.0 0.0 0.0
+    CHANNELS 6 Xposition Yposition Zposition Zrotation Xrotation Yrotation
+    JOINT LowerBack
+    {
+        OFFSET 0.0 5.0 0.0
+        CHANNELS 3 Zrotation Xrotation Yrotation
+        JOINT Spine
+        {
+            OFFSET 0.0 5.0 0.0
+            CHANNELS 3 Zrotation Xrotation Yrotation
+            End Site
+            {
+                OFFSET 0.0 10.0 0.0
+            }
+        }
+    }
+    JOINT LeftUpLeg
+    {
+        OFFSET 10.0 0.0 0.0
+        CHANNELS 3 Zrotation Xrotation Yrotation
+        JOINT LeftLeg
+        {
+            OFFSET 0.0 -40.0 0.0
+            CHANNELS 3 Zrotation Xrotation Yrotation
+            JOINT LeftFoot
+            {
+                OFFSET 0.0 -40.0 0.0
+                CHANNELS 3 Zrotation Xrotation Yrotation
+                End Site
+                {
+                    OFFSET 0.0 0.0 10.0
+                }
+            }
+        }
+    }
+}
+MOTION
+Frames: 2
+Frame Time: 0.5
+0.0 90.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0
+0.0 80.0 5.0 0.0 0.0 90.0 0.0 30.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0 0.0
+";
+
+    #[test]
+    fn joint_names_map_to_bones() {
+        assert_eq!(joint_bone_name("Hips"), Some(BoneName::Hips));
+        assert_eq!(
+            joint_bone_name("mixamorig:LeftUpLeg"),
+            Some(BoneName::LeftUpperLeg)
+        );
+        assert_eq!(joint_bone_name("rShldr"), Some(BoneName::RightUpperArm));
+        assert_eq!(joint_bone_name("thigh_l"), Some(BoneName::LeftUpperLeg));
+        assert_eq!(
+            joint_bone_name("RightHandIndex2"),
+            Some(BoneName::RightIndexIntermediate)
+        );
+        assert_eq!(joint_bone_name("LowerBack"), None);
+    }
+
+    #[test]
+    fn bvh_motion_becomes_normalized_pose() {
+        let bvh = Bvh::parse(BVH).unwrap();
+
+        assert_eq!(bvh.joints.len(), 6);
+        assert_eq!(bvh.joints[2].parent, Some(1));
+        assert_eq!(bvh.frames.len(), 2);
+
+        let animation = bvh.to_animation();
+
+        assert_eq!(animation.duration, 0.5);
+        assert_eq!(animation.leg_length, 80.0);
+        assert!(!animation.rotations.contains_key(&BoneName::Chest));
+
+        let hips = animation.hips_position.as_ref().unwrap();
+        assert_eq!(hips.sample(0.5), Some(Vec3::new(0.0, 80.0, 5.0)));
+
+        // The unmapped lower back joint is folded into the spine.
+        let spine = &animation.rotations[&BoneName::Spine];
+        assert!(
+            spine
+                .sample(0.5)
+                .unwrap()
+                .angle_between(Quat::from_rotation_x(30f32.to_radians()))
+                < 0.001
+        );
+
+        let hips = &animation.rotations[&BoneName::Hips];
+        assert!(
+            hips.sample(0.5)
+                .unwrap()
+                .angle_between(Quat::from_rotation_y(90f32.to_radians()))
+                < 0.001
+        );
+    }
+}
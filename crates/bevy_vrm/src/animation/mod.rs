@@ -0,0 +1,473 @@
+//! Humanoid animations that play on any avatar, through the normalized [`HumanPose`].
+
+use bevy::{animation::animation_player, prelude::*, transform::TransformSystem, utils::HashMap};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    expressions::{apply_expressions, Expression, VrmExpressions},
+    ik::{HeadLookAt, IkSet, IkTargetSource},
+    pose::{evaluate_pose_stacks, HumanPose, HumanPoses},
+};
+
+mod bone_map;
+mod bvh;
+mod recording;
+mod retarget;
+mod vmd;
+mod vrma;
+
+pub use bone_map::{BoneMap, BoneMapError, BoneMapLoader};
+pub use bvh::{Bvh, BvhChannel, BvhError, BvhJoint, BvhLoader};
+pub(crate) use recording::record_vrm_animations;
+pub use recording::{RecordingError, VrmRecorder, VrmRecordingLoader};
+pub use retarget::{ClipRetargeter, SourceSkeleton};
+pub use vmd::{
+    Vmd, VmdBezier, VmdBoneKeyframe, VmdError, VmdLoader, VmdMorphKeyframe, MMD_ARM_ANGLE,
+};
+pub use vrma::{VrmaError, VrmaLoader};
+
+/// Loads `.vrma`, `.bvh`, `.vmd`, `.vrmrec` and `.bonemap.json` files, plays [`VrmAnimation`]s
+/// with [`VrmAnimationPlayer`] and records them with [`VrmRecorder`].
+pub struct VrmAnimationPlugin;
+
+impl Plugin for VrmAnimationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<VrmAnimation>()
+            .init_asset_loader::<VrmaLoader>()
+            .init_asset_loader::<BvhLoader>()
+            .init_asset_loader::<VmdLoader>()
+            .init_asset_loader::<VrmRecordingLoader>()
+            .init_asset::<BoneMap>()
+            .init_asset_loader::<BoneMapLoader>()
+            .add_systems(
+                PostUpdate,
+                play_vrm_animations
+                    .after(animation_player)
+                    .before(evaluate_pose_stacks)
+                    .before(apply_expressions)
+                    .before(IkSet::Body),
+            )
+            .add_systems(
+                PostUpdate,
+                record_vrm_animations
+                    .after(IkSet::Limbs)
+                    .after(apply_expressions)
+                    .before(TransformSystem::TransformPropagate),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Interpolation {
+    #[default]
+    Linear,
+    /// Holds each value until the next keyframe.
+    Step,
+}
+
+/// A value that can be interpolated between keyframes.
+pub trait Keyframe: Copy {
+    fn interpolate(&self, other: &Self, t: f32) -> Self;
+    /// How far apart two values are, in radians for rotations.
+    fn distance(&self, other: &Self) -> f32;
+}
+
+impl Keyframe for f32 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        (self - other).abs()
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.lerp(*other, t)
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        Vec3::distance(*self, *other)
+    }
+}
+
+impl Keyframe for Quat {
+    fn interpolate(&self, other: &Self, t: f32) -> Self {
+        self.slerp(*other, t)
+    }
+
+    fn distance(&self, other: &Self) -> f32 {
+        self.angle_between(*other)
+    }
+}
+
+/// How far keyframes dropped by [`VrmAnimation::reduce`] can be from their
+/// interpolated value, for each kind of track.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReduceTolerance {
+    /// Bone rotations, in radians.
+    pub rotation: f32,
+    /// The hips position and look at target, in metres.
+    pub position: f32,
+    /// Expression weights, from 0 to 1.
+    pub weight: f32,
+}
+
+impl Default for ReduceTolerance {
+    fn default() -> Self {
+        Self {
+            rotation: 0.5_f32.to_radians(),
+            position: 0.001,
+            weight: 0.01,
+        }
+    }
+}
+
+/// Keyframes of a single value, sorted by time in seconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Track<T> {
+    pub times: Vec<f32>,
+    pub values: Vec<T>,
+    pub interpolation: Interpolation,
+}
+
+impl<T: Keyframe> Track<T> {
+    pub fn new(times: Vec<f32>, values: Vec<T>, interpolation: Interpolation) -> Self {
+        Self {
+            times,
+            values,
+            interpolation,
+        }
+    }
+
+    /// Time of the last keyframe.
+    pub fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or_default()
+    }
+
+    /// The value at `time`, holding the first and last keyframes outside the track.
+    pub fn sample(&self, time: f32) -> Option<T> {
+        let len = self.times.len().min(self.values.len());
+
+        if len == 0 {
+            return None;
+        }
+
+        let next = self.times[..len].partition_point(|t| *t <= time);
+
+        if next == 0 {
+            return Some(self.values[0]);
+        }
+
+        if next >= len {
+            return Some(self.values[len - 1]);
+        }
+
+        let prev = next - 1;
+
+        if self.interpolation == Interpolation::Step {
+            return Some(self.values[prev]);
+        }
+
+        let span = self.times[next] - self.times[prev];
+        let t = if span > 0.0 {
+            (time - self.times[prev]) / span
+        } else {
+            0.0
+        };
+
+        Some(self.values[prev].interpolate(&self.values[next], t))
+    }
+
+    /// Adds a keyframe after the last one.
+    pub fn push(&mut self, time: f32, value: T) {
+        self.times.push(time);
+        self.values.push(value);
+    }
+
+    /// Drops keyframes that can be interpolated from their neighbors to within `tolerance`.
+    /// The first and last keyframes are always kept.
+    pub fn reduce(&mut self, tolerance: f32) {
+        let len = self.times.len().min(self.values.len());
+
+        if len <= 2 {
+            return;
+        }
+
+        let mut kept = vec![0];
+
+        for next in 2..len {
+            let start = *kept.last().unwrap();
+
+            // Keyframes since the last kept one must fit between it and `next`,
+            // otherwise the one before `next` is kept.
+            let fits = (start + 1..next).all(|i| {
+                let expected = match self.interpolation {
+                    Interpolation::Step => self.values[start],
+                    Interpolation::Linear => {
+                        let span = self.times[next] - self.times[start];
+                        let t = if span > 0.0 {
+                            (self.times[i] - self.times[start]) / span
+                        } else {
+                            0.0
+                        };
+                        self.values[start].interpolate(&self.values[next], t)
+                    }
+                };
+
+                expected.distance(&self.values[i]) <= tolerance
+            });
+
+            if !fits {
+                kept.push(next - 1);
+            }
+        }
+
+        kept.push(len - 1);
+
+        self.times = kept.iter().map(|i| self.times[*i]).collect();
+        self.values = kept.iter().map(|i| self.values[*i]).collect();
+    }
+}
+
+/// A humanoid animation, independent of any avatar's skeleton.
+/// Loaded from `.vrma`, `.bvh` and `.vmd` files by [`VrmaLoader`], [`BvhLoader`] and [`VmdLoader`].
+#[derive(Asset, TypePath, Clone, Debug, Default)]
+pub struct VrmAnimation {
+    /// Length of the animation in seconds.
+    pub duration: f32,
+    /// Rotation of each bone, as in [`HumanPose::rotations`].
+    pub rotations: HashMap<BoneName, Track<Quat>>,
+    /// Position of the hips relative to the avatar root.
+    pub hips_position: Option<Track<Vec3>>,
+    /// Length of the legs in the rest pose, from the upper leg to the foot.
+    /// Used to scale the hips position to other avatars.
+    pub leg_length: f32,
+    /// Weight of each expression, from 0 to 1.
+    pub expressions: HashMap<Expression, Track<f32>>,
+    /// Position of the look-at target relative to the avatar root.
+    pub look_at: Option<Track<Vec3>>,
+}
+
+impl VrmAnimation {
+    /// Sets the duration to the end of the longest track.
+    pub fn update_duration(&mut self) {
+        self.duration = self
+            .rotations
+            .values()
+            .map(Track::duration)
+            .chain(self.hips_position.iter().map(Track::duration))
+            .chain(self.expressions.values().map(Track::duration))
+            .chain(self.look_at.iter().map(Track::duration))
+            .fold(0.0, f32::max);
+    }
+
+    /// The pose at `time`, for an avatar whose hips are at `rest_hips` in the rest pose
+    /// and whose legs are `leg_length` long.
+    pub fn sample_pose(&self, time: f32, rest_hips: Vec3, leg_length: f32) -> HumanPose {
+        let hips_position = match &self.hips_position {
+            Some(track) if self.leg_length > 0.0 => track
+                .sample(time)
+                .map(|position| position * leg_length / self.leg_length)
+                .unwrap_or(rest_hips),
+            _ => rest_hips,
+        };
+
+        HumanPose {
+            hips_position,
+            rotations: self
+                .rotations
+                .iter()
+                .filter_map(|(bone, track)| Some((bone.clone(), track.sample(time)?)))
+                .collect(),
+        }
+    }
+
+    /// Drops keyframes within `tolerance` of their interpolated value in every track,
+    /// see [`Track::reduce`].
+    pub fn reduce(&mut self, tolerance: ReduceTolerance) {
+        for track in self.rotations.values_mut() {
+            track.reduce(tolerance.rotation);
+        }
+        for track in self.expressions.values_mut() {
+            track.reduce(tolerance.weight);
+        }
+        for track in self.hips_position.iter_mut().chain(self.look_at.iter_mut()) {
+            track.reduce(tolerance.position);
+        }
+    }
+
+    pub fn sample_expressions(&self, time: f32) -> impl Iterator<Item = (&Expression, f32)> {
+        self.expressions
+            .iter()
+            .filter_map(move |(expression, track)| Some((expression, track.sample(time)?)))
+    }
+
+    pub fn sample_look_at(&self, time: f32) -> Option<Vec3> {
+        self.look_at.as_ref()?.sample(time)
+    }
+}
+
+/// Plays a [`VrmAnimation`] on the avatar, see [`VrmAnimationPlugin`].
+/// Also sets the avatar's [`VrmExpressions`], and the target of its [`HeadLookAt`] if it has one.
+#[derive(Component, Clone, Debug)]
+pub struct VrmAnimationPlayer {
+    pub animation: Handle<VrmAnimation>,
+    /// Current time in seconds.
+    pub elapsed: f32,
+    pub speed: f32,
+    pub repeat: bool,
+    pub paused: bool,
+}
+
+impl VrmAnimationPlayer {
+    pub fn new(animation: Handle<VrmAnimation>) -> Self {
+        Self {
+            animation,
+            elapsed: 0.0,
+            speed: 1.0,
+            repeat: false,
+            paused: false,
+        }
+    }
+
+    pub fn repeat(mut self) -> Self {
+        self.repeat = true;
+        self
+    }
+}
+
+pub(crate) fn play_vrm_animations(
+    time: Res<Time>,
+    animations: Res<Assets<VrmAnimation>>,
+    mut poses: HumanPoses,
+    mut players: Query<(
+        Entity,
+        &mut VrmAnimationPlayer,
+        &GlobalTransform,
+        Option<&mut VrmExpressions>,
+        Option<&mut HeadLookAt>,
+    )>,
+) {
+    for (entity, mut player, global_transform, expressions, look_at) in players.iter_mut() {
+        let Some(animation) = animations.get(&player.animation) else {
+            continue;
+        };
+
+        if !player.paused {
+            player.elapsed += time.delta_seconds() * player.speed;
+        }
+
+        player.elapsed = if player.repeat && animation.duration > 0.0 {
+            player.elapsed.rem_euclid(animation.duration)
+        } else {
+            player.elapsed.clamp(0.0, animation.duration)
+        };
+
+        let (Some(rest_hips), Some(leg_length)) = (
+            poses.rest_hips_position(entity),
+            poses.rest_leg_length(entity),
+        ) else {
+            continue;
+        };
+
+        let pose = animation.sample_pose(player.elapsed, rest_hips, leg_length);
+        poses.apply_pose(entity, &pose);
+
+        if let Some(mut expressions) = expressions {
+            for (expression, weight) in animation.sample_expressions(player.elapsed) {
+                expressions.set(expression.clone(), weight);
+            }
+        }
+
+        if let (Some(mut look_at), Some(target)) =
+            (look_at, animation.sample_look_at(player.elapsed))
+        {
+            let target = global_transform.transform_point(target);
+            look_at.target = Some(IkTargetSource::Transform(Transform::from_translation(
+                target,
+            )));
+        }
+    }
+}
+
+/// Length of a leg from the positions of its joints.
+pub(crate) fn leg_length(upper_leg: Vec3, lower_leg: Vec3, foot: Vec3) -> f32 {
+    upper_leg.distance(lower_leg) + lower_leg.distance(foot)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_interpolates_between_keyframes() {
+        let track = Track::new(
+            vec![0.0, 1.0, 3.0],
+            vec![0.0, 1.0, 0.0],
+            Interpolation::Linear,
+        );
+
+        assert_eq!(track.sample(-1.0), Some(0.0));
+        assert_eq!(track.sample(0.5), Some(0.5));
+        assert_eq!(track.sample(2.0), Some(0.5));
+        assert_eq!(track.sample(4.0), Some(0.0));
+
+        let step = Track {
+            interpolation: Interpolation::Step,
+            ..track
+        };
+        assert_eq!(step.sample(0.5), Some(0.0));
+        assert_eq!(step.sample(1.5), Some(1.0));
+    }
+
+    #[test]
+    fn track_reduction_keeps_corners() {
+        let mut track = Track::new(
+            vec![0.0, 1.0, 2.0, 3.0, 4.0],
+            vec![0.0, 1.0, 2.01, 3.0, 0.0],
+            Interpolation::Linear,
+        );
+
+        track.reduce(0.05);
+
+        assert_eq!(track.times, vec![0.0, 3.0, 4.0]);
+        assert_eq!(track.values, vec![0.0, 3.0, 0.0]);
+    }
+
+    #[test]
+    fn animation_reduction_uses_the_tolerance_of_each_track() {
+        let times = vec![0.0, 1.0, 2.0];
+        let mut animation = VrmAnimation::default();
+
+        // Each middle keyframe is 0.005 from its interpolated value.
+        animation.rotations.insert(
+            BoneName::Head,
+            Track::new(
+                times.clone(),
+                vec![
+                    Quat::IDENTITY,
+                    Quat::from_rotation_y(0.505),
+                    Quat::from_rotation_y(1.0),
+                ],
+                Interpolation::Linear,
+            ),
+        );
+        animation.expressions.insert(
+            Expression::A,
+            Track::new(times.clone(), vec![0.0, 0.505, 1.0], Interpolation::Linear),
+        );
+        animation.hips_position = Some(Track::new(
+            times,
+            vec![Vec3::ZERO, Vec3::new(0.0, 0.505, 0.0), Vec3::Y],
+            Interpolation::Linear,
+        ));
+
+        animation.reduce(ReduceTolerance::default());
+
+        assert_eq!(animation.rotations[&BoneName::Head].times.len(), 2);
+        assert_eq!(animation.expressions[&Expression::A].times.len(), 2);
+        assert_eq!(animation.hips_position.unwrap().times.len(), 3);
+    }
+}
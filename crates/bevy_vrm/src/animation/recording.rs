@@ -0,0 +1,435 @@
+use std::io::{Read, Write};
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::BoxedFuture,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+use crate::{
+    expressions::{Expression, VrmExpressions},
+    ik::{HeadLookAt, IkTargetSource},
+    pose::HumanPoses,
+};
+
+use super::{Interpolation, Keyframe, ReduceTolerance, Track, VrmAnimation};
+
+const MAGIC: &[u8; 4] = b"VRMR";
+const VERSION: u32 = 1;
+
+/// Records the avatar's [`HumanPose`](crate::pose::HumanPose), [`VrmExpressions`]
+/// and [`HeadLookAt`] target every frame while started.
+/// Recordings are [`VrmAnimation`]s, so they can be played back on any avatar.
+#[derive(Component, Clone, Debug, Default)]
+pub struct VrmRecorder {
+    recording: bool,
+    elapsed: f32,
+    animation: VrmAnimation,
+}
+
+impl VrmRecorder {
+    /// Starts a new recording, discarding any current one.
+    pub fn start(&mut self) {
+        *self = Self {
+            recording: true,
+            ..default()
+        };
+    }
+
+    /// Stops recording and returns the recording, with keyframes within `tolerance`
+    /// of their interpolated value dropped. See [`VrmAnimation::reduce`].
+    pub fn stop(&mut self, tolerance: ReduceTolerance) -> VrmAnimation {
+        self.recording = false;
+
+        let mut animation = std::mem::take(&mut self.animation);
+        animation.reduce(tolerance);
+        animation.update_duration();
+        animation
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Seconds since the recording started.
+    pub fn elapsed(&self) -> f32 {
+        self.elapsed
+    }
+}
+
+/// Runs in [`PostUpdate`], after the pose is final and before it is propagated.
+pub(crate) fn record_vrm_animations(
+    time: Res<Time>,
+    poses: HumanPoses,
+    global_transforms: Query<&GlobalTransform>,
+    mut recorders: Query<(
+        Entity,
+        &mut VrmRecorder,
+        Option<&VrmExpressions>,
+        Option<&HeadLookAt>,
+    )>,
+) {
+    for (entity, mut recorder, expressions, look_at) in recorders.iter_mut() {
+        if !recorder.recording {
+            continue;
+        }
+
+        let Some(pose) = poses.read_pose(entity) else {
+            continue;
+        };
+
+        let recorder = recorder.as_mut();
+        let time_stamp = recorder.elapsed;
+        let animation = &mut recorder.animation;
+
+        if animation.leg_length == 0.0 {
+            animation.leg_length = poses.rest_leg_length(entity).unwrap_or_default();
+        }
+
+        for (bone, rotation) in pose.rotations {
+            animation
+                .rotations
+                .entry(bone)
+                .or_default()
+                .push(time_stamp, rotation);
+        }
+
+        animation
+            .hips_position
+            .get_or_insert_with(default)
+            .push(time_stamp, pose.hips_position);
+
+        if let Some(expressions) = expressions {
+            for (expression, weight) in expressions.0.iter() {
+                animation
+                    .expressions
+                    .entry(expression.clone())
+                    .or_default()
+                    .push(time_stamp, *weight);
+            }
+        }
+
+        let target = match look_at.and_then(|look_at| look_at.target) {
+            Some(IkTargetSource::Entity(target)) => global_transforms
+                .get(target)
+                .ok()
+                .map(|target| target.translation()),
+            Some(IkTargetSource::Transform(target)) => Some(target.translation),
+            None => None,
+        };
+
+        if let (Some(target), Ok(global)) = (target, global_transforms.get(entity)) {
+            let target = global.affine().inverse().transform_point3(target);
+            animation
+                .look_at
+                .get_or_insert_with(default)
+                .push(time_stamp, target);
+        }
+
+        recorder.elapsed += time.delta_seconds();
+    }
+}
+
+#[derive(Default)]
+pub struct VrmRecordingLoader;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Invalid recording header")]
+    InvalidHeader,
+    #[error("Unsupported recording version: {0}")]
+    UnsupportedVersion(u32),
+    #[error("Invalid name: {0}")]
+    InvalidName(String),
+    #[error("Recording ends before the end of a {0} byte name")]
+    TruncatedName(u32),
+}
+
+impl AssetLoader for VrmRecordingLoader {
+    type Asset = VrmAnimation;
+    type Settings = ();
+    type Error = RecordingError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            VrmAnimation::read_recording(&mut bytes.as_slice())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vrmrec"]
+    }
+}
+
+impl VrmAnimation {
+    /// Writes the animation in the compact binary format of `.vrmrec` files.
+    pub fn write_recording(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writer.write_all(MAGIC)?;
+        write_u32(writer, VERSION)?;
+        write_f32(writer, self.leg_length)?;
+
+        write_u32(writer, self.rotations.len() as u32)?;
+        for (bone, track) in self.rotations.iter() {
+            write_name(writer, bone)?;
+            write_track(writer, track, |w, v| write_floats(w, &v.to_array()))?;
+        }
+
+        write_u32(writer, self.expressions.len() as u32)?;
+        for (expression, track) in self.expressions.iter() {
+            match expression {
+                Expression::Preset(preset) => {
+                    writer.write_all(&[0])?;
+                    write_name(writer, preset)?;
+                }
+                Expression::Custom(name) => {
+                    writer.write_all(&[1])?;
+                    write_string(writer, name)?;
+                }
+            }
+            write_track(writer, track, |w, v| write_f32(w, *v))?;
+        }
+
+        for track in [&self.hips_position, &self.look_at] {
+            writer.write_all(&[track.is_some() as u8])?;
+            if let Some(track) = track {
+                write_track(writer, track, |w, v| write_floats(w, &v.to_array()))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads an animation written by [`VrmAnimation::write_recording`].
+    pub fn read_recording(reader: &mut impl Read) -> Result<Self, RecordingError> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(RecordingError::InvalidHeader);
+        }
+
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+
+        let mut animation = Self {
+            leg_length: read_f32(reader)?,
+            ..default()
+        };
+
+        for _ in 0..read_u32(reader)? {
+            let bone = read_name(reader)?;
+            let track = read_track(reader, |r| Ok(Quat::from_array(read_floats(r)?)))?;
+            animation.rotations.insert(bone, track);
+        }
+
+        for _ in 0..read_u32(reader)? {
+            let expression = match read_u8(reader)? {
+                0 => Expression::Preset(read_name(reader)?),
+                _ => Expression::Custom(read_string(reader)?),
+            };
+            let track = read_track(reader, read_f32)?;
+            animation.expressions.insert(expression, track);
+        }
+
+        animation.hips_position = read_optional_track(reader)?;
+        animation.look_at = read_optional_track(reader)?;
+
+        animation.update_duration();
+        Ok(animation)
+    }
+}
+
+fn write_u32(writer: &mut impl Write, value: u32) -> std::io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_f32(writer: &mut impl Write, value: f32) -> std::io::Result<()> {
+    writer.write_all(&value.to_le_bytes())
+}
+
+fn write_floats(writer: &mut impl Write, values: &[f32]) -> std::io::Result<()> {
+    values
+        .iter()
+        .try_for_each(|value| write_f32(writer, *value))
+}
+
+fn write_string(writer: &mut impl Write, value: &str) -> std::io::Result<()> {
+    write_u32(writer, value.len() as u32)?;
+    writer.write_all(value.as_bytes())
+}
+
+/// Writes a bone or preset by its name in the VRM file.
+fn write_name(writer: &mut impl Write, value: &impl Serialize) -> std::io::Result<()> {
+    let name = match serde_json::to_value(value)? {
+        serde_json::Value::String(name) => name,
+        _ => String::new(),
+    };
+    write_string(writer, &name)
+}
+
+fn write_track<W: Write, T: Keyframe>(
+    writer: &mut W,
+    track: &Track<T>,
+    write_value: impl Fn(&mut W, &T) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let len = track.times.len().min(track.values.len());
+
+    writer.write_all(&[(track.interpolation == Interpolation::Step) as u8])?;
+    write_u32(writer, len as u32)?;
+    write_floats(writer, &track.times[..len])?;
+    track.values[..len]
+        .iter()
+        .try_for_each(|value| write_value(writer, value))
+}
+
+fn read_u8(reader: &mut impl Read) -> std::io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> std::io::Result<f32> {
+    Ok(f32::from_bits(read_u32(reader)?))
+}
+
+fn read_floats<const N: usize>(reader: &mut impl Read) -> std::io::Result<[f32; N]> {
+    let mut values = [0.0; N];
+    for value in values.iter_mut() {
+        *value = read_f32(reader)?;
+    }
+    Ok(values)
+}
+
+fn read_string(reader: &mut impl Read) -> Result<String, RecordingError> {
+    let len = read_u32(reader)?;
+
+    // The length is only trusted up to the bytes actually left in the recording.
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(RecordingError::TruncatedName(len));
+    }
+
+    String::from_utf8(bytes).map_err(|e| RecordingError::InvalidName(e.to_string()))
+}
+
+fn read_name<T: DeserializeOwned>(reader: &mut impl Read) -> Result<T, RecordingError> {
+    let name = read_string(reader)?;
+    serde_json::from_value(serde_json::Value::String(name.clone()))
+        .map_err(|_| RecordingError::InvalidName(name))
+}
+
+fn read_track<R: Read, T: Keyframe>(
+    reader: &mut R,
+    read_value: impl Fn(&mut R) -> std::io::Result<T>,
+) -> Result<Track<T>, RecordingError> {
+    let interpolation = match read_u8(reader)? {
+        0 => Interpolation::Linear,
+        _ => Interpolation::Step,
+    };
+
+    let len = read_u32(reader)? as usize;
+    let times = (0..len)
+        .map(|_| read_f32(reader))
+        .collect::<Result<_, _>>()?;
+    let values = (0..len)
+        .map(|_| read_value(reader))
+        .collect::<Result<_, _>>()?;
+
+    Ok(Track::new(times, values, interpolation))
+}
+
+fn read_optional_track(reader: &mut impl Read) -> Result<Option<Track<Vec3>>, RecordingError> {
+    if read_u8(reader)? == 0 {
+        return Ok(None);
+    }
+
+    let track = read_track(reader, |r| Ok(Vec3::from_array(read_floats(r)?)))?;
+    Ok(Some(track))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_vrm::vrm0::BoneName;
+
+    use super::*;
+
+    #[test]
+    fn recording_round_trips_through_bytes() {
+        let mut animation = VrmAnimation {
+            leg_length: 0.8,
+            ..default()
+        };
+        animation.rotations.insert(
+            BoneName::LeftUpperArm,
+            Track::new(
+                vec![0.0, 0.5],
+                vec![Quat::IDENTITY, Quat::from_rotation_z(1.0)],
+                Interpolation::Linear,
+            ),
+        );
+        animation.expressions.insert(
+            Expression::Custom("smirk".to_string()),
+            Track::new(vec![0.25], vec![0.5], Interpolation::Step),
+        );
+        animation.expressions.insert(
+            Expression::BLINK,
+            Track::new(vec![0.0, 1.0], vec![0.0, 1.0], Interpolation::Linear),
+        );
+        animation.look_at = Some(Track::new(
+            vec![0.0],
+            vec![Vec3::new(0.0, 1.5, 1.0)],
+            Interpolation::Linear,
+        ));
+        animation.update_duration();
+
+        let mut bytes = Vec::new();
+        animation.write_recording(&mut bytes).unwrap();
+        let read = VrmAnimation::read_recording(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(read.duration, 1.0);
+        assert_eq!(read.leg_length, animation.leg_length);
+        assert_eq!(read.rotations, animation.rotations);
+        assert_eq!(read.expressions, animation.expressions);
+        assert_eq!(read.hips_position, None);
+        assert_eq!(read.look_at, animation.look_at);
+
+        assert!(VrmAnimation::read_recording(&mut &bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn name_length_is_bounded_by_the_recording() {
+        let mut bytes = Vec::new();
+        VrmAnimation::default().write_recording(&mut bytes).unwrap();
+
+        // A single bone whose name claims to be 4 GB long.
+        let header = MAGIC.len() + 8;
+        bytes.truncate(header);
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(b"\"Hips\"");
+
+        assert!(matches!(
+            VrmAnimation::read_recording(&mut bytes.as_slice()),
+            Err(RecordingError::TruncatedName(u32::MAX))
+        ));
+    }
+}
@@ -0,0 +1,344 @@
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_gltf_kun::import::gltf::{node::node_name, GltfKun};
+use gltf_kun::graph::{
+    gltf::{GltfDocument, GltfWeight},
+    GraphNodeWeight, Weight,
+};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    ik::{rest_pose, BoneRest},
+    pose::rest_leg_length,
+    retargeting::VrmRetargetingInitialized,
+    HumanoidBones,
+};
+
+use super::{leg_length, BoneMap, Interpolation, Track, VrmAnimation};
+
+/// Rest pose of the skeleton an [`AnimationClip`] was made for, by node name.
+/// The skeleton should be in a T-pose facing +Z, like a VRM 1.0 avatar.
+#[derive(Clone, Debug, Default)]
+pub struct SourceSkeleton {
+    /// Rest transform of each node, relative to its parent.
+    pub rests: HashMap<String, Transform>,
+    /// Name of each node's parent.
+    pub parents: HashMap<String, String>,
+}
+
+impl SourceSkeleton {
+    /// Reads the nodes of a glTF file, named as in its imported clips.
+    pub fn from_gltf(gltf: &GltfKun) -> Self {
+        let mut skeleton = Self::default();
+        let graph = &gltf.graph;
+
+        let Some(doc) = graph.node_indices().find(|n| {
+            let weight = graph.node_weight(*n);
+            matches!(weight, Some(Weight::Gltf(GltfWeight::Document)))
+        }) else {
+            return skeleton;
+        };
+        let doc = GltfDocument(doc);
+
+        for node in doc.nodes(graph) {
+            let name = node_name(&doc, graph, node);
+            let weight = node.get(graph);
+
+            skeleton.rests.insert(
+                name.clone(),
+                Transform {
+                    translation: weight.translation,
+                    rotation: weight.rotation,
+                    scale: weight.scale,
+                },
+            );
+
+            for child in node.children(graph) {
+                skeleton
+                    .parents
+                    .insert(node_name(&doc, graph, child), name.clone());
+            }
+        }
+
+        skeleton
+    }
+
+    /// Rest pose of the parent of `name`, relative to the scene.
+    fn parent_rest(&self, name: &str) -> Transform {
+        let mut rest = Transform::IDENTITY;
+        let mut current = self.parents.get(name);
+
+        while let Some(parent) = current {
+            rest = self.rests.get(parent).copied().unwrap_or_default() * rest;
+            current = self.parents.get(parent);
+        }
+
+        rest
+    }
+
+    /// Names from the root node down to `name`, as in the imported clips.
+    fn path(&self, name: &str) -> EntityPath {
+        let mut parts = vec![Name::new(name.to_string())];
+        let mut current = self.parents.get(name);
+
+        while let Some(parent) = current {
+            parts.push(Name::new(parent.clone()));
+            current = self.parents.get(parent);
+        }
+
+        parts.reverse();
+        EntityPath { parts }
+    }
+
+    /// Rest pose of `name`, relative to the scene.
+    fn rest(&self, name: &str) -> Option<Transform> {
+        Some(self.parent_rest(name) * *self.rests.get(name)?)
+    }
+}
+
+impl VrmAnimation {
+    /// Converts a clip made for `skeleton`, removing its rest pose so it can play on any avatar.
+    /// Curves of nodes missing from `bones` are dropped.
+    pub fn from_clip(clip: &AnimationClip, skeleton: &SourceSkeleton, bones: &BoneMap) -> Self {
+        let mut animation = Self::default();
+        let mut rest_positions = HashMap::new();
+
+        for name in skeleton.rests.keys() {
+            let (Some(bone), Some(rest)) = (bones.get(name), skeleton.rest(name)) else {
+                continue;
+            };
+
+            rest_positions.insert(bone.clone(), rest.translation);
+
+            let Some(curves) = clip.get_curves_by_path(&skeleton.path(name)) else {
+                continue;
+            };
+
+            let parent_rest = skeleton.parent_rest(name);
+
+            for curve in curves {
+                let (interpolation, cubic) = match curve.interpolation {
+                    bevy::animation::Interpolation::Step => (Interpolation::Step, false),
+                    bevy::animation::Interpolation::Linear => (Interpolation::Linear, false),
+                    bevy::animation::Interpolation::CubicSpline => (Interpolation::Linear, true),
+                };
+
+                let times = curve.keyframe_timestamps.clone();
+
+                match &curve.keyframes {
+                    Keyframes::Rotation(rotations) => {
+                        let values = spline_values(rotations, cubic)
+                            .map(|rotation| {
+                                (parent_rest.rotation * rotation * rest.rotation.inverse())
+                                    .normalize()
+                            })
+                            .collect();
+
+                        animation
+                            .rotations
+                            .insert(bone.clone(), Track::new(times, values, interpolation));
+                    }
+                    Keyframes::Translation(translations) if *bone == BoneName::Hips => {
+                        let values = spline_values(translations, cubic)
+                            .map(|translation| parent_rest.transform_point(translation))
+                            .collect();
+
+                        animation.hips_position = Some(Track::new(times, values, interpolation));
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let (Some(upper_leg), Some(lower_leg), Some(foot)) = (
+            rest_positions.get(&BoneName::LeftUpperLeg),
+            rest_positions.get(&BoneName::LeftLowerLeg),
+            rest_positions.get(&BoneName::LeftFoot),
+        ) {
+            animation.leg_length = leg_length(*upper_leg, *lower_leg, *foot);
+        }
+
+        animation.update_duration();
+        animation
+    }
+}
+
+/// Cubic spline keyframes store an in-tangent, value and out-tangent.
+fn spline_values<T: Copy>(values: &[T], cubic: bool) -> impl Iterator<Item = T> + '_ {
+    let (step, offset) = if cubic { (3, 1) } else { (1, 0) };
+    values.iter().skip(offset).step_by(step).copied()
+}
+
+/// Converts animations into [`AnimationClip`]s for a specific avatar.
+/// Avatars must be retargeted and have their [`BoneRest`]s.
+#[derive(SystemParam)]
+pub struct ClipRetargeter<'w, 's> {
+    avatars: Query<'w, 's, &'static HumanoidBones, With<VrmRetargetingInitialized>>,
+    parents: Query<'w, 's, &'static Parent>,
+    bone_rests: Query<'w, 's, &'static BoneRest>,
+    names: Query<'w, 's, &'static Name>,
+}
+
+impl ClipRetargeter<'_, '_> {
+    /// Converts a clip made for `skeleton` into one for `avatar`.
+    /// See [`ClipRetargeter::retarget_animation`].
+    pub fn retarget_clip(
+        &self,
+        avatar: Entity,
+        root: Entity,
+        clip: &AnimationClip,
+        skeleton: &SourceSkeleton,
+        bones: &BoneMap,
+    ) -> Option<AnimationClip> {
+        let animation = VrmAnimation::from_clip(clip, skeleton, bones);
+        self.retarget_animation(avatar, root, &animation)
+    }
+
+    /// Converts `animation` into a clip for `avatar`, to be played by an [`AnimationPlayer`]
+    /// on `root`. The root must be a named ancestor of the bones, such as the scene's root node.
+    /// The hips translation is scaled by the avatar's leg length.
+    pub fn retarget_animation(
+        &self,
+        avatar: Entity,
+        root: Entity,
+        animation: &VrmAnimation,
+    ) -> Option<AnimationClip> {
+        let bones = self.avatars.get(avatar).ok()?;
+
+        let scale = match rest_leg_length(avatar, bones, &self.parents, &self.bone_rests) {
+            Some(leg_length) if animation.leg_length > 0.0 => leg_length / animation.leg_length,
+            _ => 1.0,
+        };
+
+        let mut clip = AnimationClip::default();
+
+        for (bone, entity) in bones.0.iter() {
+            let Some(path) = self.path(root, *entity) else {
+                continue;
+            };
+
+            let (Some(rest), Ok(parent)) = (
+                rest_pose(*entity, avatar, &self.parents, &self.bone_rests),
+                self.parents.get(*entity),
+            ) else {
+                continue;
+            };
+
+            let parent_rest = if parent.get() == avatar {
+                Transform::IDENTITY
+            } else {
+                rest_pose(parent.get(), avatar, &self.parents, &self.bone_rests)?
+            };
+
+            if let Some(track) = animation.rotations.get(bone) {
+                // Other bones keep their rest pose, so the parent's rotation only adds the rotation of its bone.
+                let values = track
+                    .values
+                    .iter()
+                    .map(|rotation| {
+                        (parent_rest.rotation.inverse() * *rotation * rest.rotation).normalize()
+                    })
+                    .collect();
+
+                clip.add_curve_to_path(
+                    path.clone(),
+                    VariableCurve {
+                        keyframe_timestamps: track.times.clone(),
+                        keyframes: Keyframes::Rotation(values),
+                        interpolation: interpolation(track.interpolation),
+                    },
+                );
+            }
+
+            if *bone == BoneName::Hips {
+                if let Some(track) = &animation.hips_position {
+                    let to_local = parent_rest.compute_matrix().inverse();
+                    let values = track
+                        .values
+                        .iter()
+                        .map(|position| to_local.transform_point3(*position * scale))
+                        .collect();
+
+                    clip.add_curve_to_path(
+                        path,
+                        VariableCurve {
+                            keyframe_timestamps: track.times.clone(),
+                            keyframes: Keyframes::Translation(values),
+                            interpolation: interpolation(track.interpolation),
+                        },
+                    );
+                }
+            }
+        }
+
+        Some(clip)
+    }
+
+    /// Names from `root` down to `entity`.
+    fn path(&self, root: Entity, entity: Entity) -> Option<EntityPath> {
+        let mut parts = vec![self.names.get(entity).ok()?.clone()];
+        let mut current = entity;
+
+        while current != root {
+            current = self.parents.get(current).ok()?.get();
+            parts.push(self.names.get(current).ok()?.clone());
+        }
+
+        parts.reverse();
+        Some(EntityPath { parts })
+    }
+}
+
+fn interpolation(interpolation: Interpolation) -> bevy::animation::Interpolation {
+    match interpolation {
+        Interpolation::Linear => bevy::animation::Interpolation::Linear,
+        Interpolation::Step => bevy::animation::Interpolation::Step,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_rest_pose_is_removed() {
+        let spine_rest = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let bend = Quat::from_rotation_x(0.5);
+
+        let mut skeleton = SourceSkeleton::default();
+        skeleton.rests.insert(
+            "mixamorig:Hips".to_string(),
+            Transform::from_xyz(0.0, 1.0, 0.0),
+        );
+        skeleton.rests.insert(
+            "mixamorig:Spine".to_string(),
+            Transform::from_xyz(0.0, 0.1, 0.0).with_rotation(spine_rest),
+        );
+        skeleton
+            .parents
+            .insert("mixamorig:Spine".to_string(), "mixamorig:Hips".to_string());
+
+        let mut clip = AnimationClip::default();
+        clip.add_curve_to_path(
+            EntityPath {
+                parts: vec![Name::new("mixamorig:Hips"), Name::new("mixamorig:Spine")],
+            },
+            VariableCurve {
+                keyframe_timestamps: vec![0.0, 1.0],
+                keyframes: Keyframes::Rotation(vec![spine_rest, spine_rest * bend]),
+                interpolation: bevy::animation::Interpolation::Linear,
+            },
+        );
+
+        let animation = VrmAnimation::from_clip(&clip, &skeleton, &BoneMap::mixamo());
+
+        let spine = &animation.rotations[&BoneName::Spine];
+        assert!(spine.sample(0.0).unwrap().angle_between(Quat::IDENTITY) < 0.001);
+        assert!(
+            spine
+                .sample(1.0)
+                .unwrap()
+                .angle_between(Quat::from_rotation_z(-0.5))
+                < 0.001
+        );
+    }
+}
@@ -0,0 +1,680 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use encoding_rs::SHIFT_JIS;
+use serde_vrm::vrm0::BoneName;
+use thiserror::Error;
+
+use crate::expressions::Expression;
+
+use super::{Interpolation, Keyframe, Track, VrmAnimation};
+
+/// VMD motions run at 30 frames per second.
+const FRAME_RATE: f32 = 30.0;
+
+/// Angle of the arms below horizontal in the A-pose of MMD models, in degrees.
+pub const MMD_ARM_ANGLE: f32 = 37.0;
+
+/// Hips position and leg length of a typical MMD model, as VMD files store no skeleton.
+const REST_HIPS: Vec3 = Vec3::new(0.0, 12.0, 0.0);
+const LEG_LENGTH: f32 = 10.5;
+
+#[derive(Default)]
+pub struct VmdLoader;
+
+#[derive(Debug, Error)]
+pub enum VmdError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("Invalid VMD header")]
+    InvalidHeader,
+    #[error("Unexpected end of file")]
+    UnexpectedEnd,
+}
+
+impl AssetLoader for VmdLoader {
+    type Asset = VrmAnimation;
+    type Settings = ();
+    type Error = VmdError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(Vmd::parse(&bytes)?.to_animation(MMD_ARM_ANGLE))
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vmd"]
+    }
+}
+
+/// A cubic Bézier easing curve from (0, 0) to (1, 1).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VmdBezier {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+impl VmdBezier {
+    pub const LINEAR: Self = Self {
+        x1: 0.25,
+        y1: 0.25,
+        x2: 0.75,
+        y2: 0.75,
+    };
+
+    /// Eased progress at progress `x`, both from 0 to 1.
+    pub fn sample(&self, x: f32) -> f32 {
+        let curve = |s: f32, p1: f32, p2: f32| {
+            let r = 1.0 - s;
+            3.0 * r * r * s * p1 + 3.0 * r * s * s * p2 + s * s * s
+        };
+
+        // The curve is monotonic in x, so the parameter is found by bisection.
+        let (mut low, mut high) = (0.0, 1.0);
+        for _ in 0..24 {
+            let mid = (low + high) / 2.0;
+            if curve(mid, self.x1, self.x2) < x {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        curve((low + high) / 2.0, self.y1, self.y2)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmdBoneKeyframe {
+    pub name: String,
+    pub frame: u32,
+    /// Offset from the bone's rest position, converted to glTF space.
+    pub position: Vec3,
+    /// Rotation in model axes, converted to glTF space.
+    pub rotation: Quat,
+    /// Easing into this keyframe of the x, y and z position and the rotation.
+    pub interpolation: [VmdBezier; 4],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VmdMorphKeyframe {
+    pub name: String,
+    pub frame: u32,
+    pub weight: f32,
+}
+
+/// A parsed MikuMikuDance motion.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Vmd {
+    pub model_name: String,
+    pub bones: Vec<VmdBoneKeyframe>,
+    pub morphs: Vec<VmdMorphKeyframe>,
+}
+
+impl Vmd {
+    pub fn parse(bytes: &[u8]) -> Result<Self, VmdError> {
+        let mut reader = Bytes(bytes);
+
+        let model_name_len = match reader.take(30)? {
+            header if header.starts_with(b"Vocaloid Motion Data 0002") => 20,
+            header if header.starts_with(b"Vocaloid Motion Data file") => 10,
+            _ => return Err(VmdError::InvalidHeader),
+        };
+
+        let mut vmd = Self {
+            model_name: reader.name(model_name_len)?,
+            ..default()
+        };
+
+        for _ in 0..reader.u32()? {
+            let name = reader.name(15)?;
+            let frame = reader.u32()?;
+            let [x, y, z] = [reader.f32()?, reader.f32()?, reader.f32()?];
+            let [qx, qy, qz, qw] = [reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?];
+            let curves = reader.take(64)?;
+
+            let interpolation = [0, 1, 2, 3].map(|i| VmdBezier {
+                x1: curves[i] as f32 / 127.0,
+                y1: curves[i + 4] as f32 / 127.0,
+                x2: curves[i + 8] as f32 / 127.0,
+                y2: curves[i + 12] as f32 / 127.0,
+            });
+
+            // MMD is left-handed, so the Z axis is mirrored.
+            vmd.bones.push(VmdBoneKeyframe {
+                name,
+                frame,
+                position: Vec3::new(x, y, -z),
+                rotation: Quat::from_xyzw(-qx, -qy, qz, qw).normalize(),
+                interpolation,
+            });
+        }
+
+        // Older files may end after the bone keyframes.
+        let Ok(morph_count) = reader.u32() else {
+            return Ok(vmd);
+        };
+
+        for _ in 0..morph_count {
+            vmd.morphs.push(VmdMorphKeyframe {
+                name: reader.name(15)?,
+                frame: reader.u32()?,
+                weight: reader.f32()?,
+            });
+        }
+
+        Ok(vmd)
+    }
+
+    /// Converts the motion into a [`VrmAnimation`], for a model whose arms are `arm_angle`
+    /// degrees below horizontal at rest. Bones are sampled every frame.
+    ///
+    /// Leg IK bones are not solved, so legs only move with keyframes on the leg bones themselves.
+    pub fn to_animation(&self, arm_angle: f32) -> VrmAnimation {
+        let mut animation = VrmAnimation {
+            leg_length: LEG_LENGTH,
+            ..default()
+        };
+
+        let mut keyframes = HashMap::<usize, Vec<&VmdBoneKeyframe>>::new();
+        for keyframe in self.bones.iter() {
+            let name = normalize_name(&keyframe.name);
+            if let Some(index) = MMD_BONES.iter().position(|(n, ..)| *n == name) {
+                keyframes.entry(index).or_default().push(keyframe);
+            }
+        }
+
+        for keys in keyframes.values_mut() {
+            keys.sort_by_key(|k| k.frame);
+        }
+
+        // Rotation from the T-pose to the rest pose, in model space.
+        let arm_angle = arm_angle.to_radians();
+        let rests = MMD_BONES
+            .iter()
+            .map(|(name, ..)| {
+                if has_ancestor(name, "左腕") {
+                    Quat::from_rotation_z(-arm_angle)
+                } else if has_ancestor(name, "右腕") {
+                    Quat::from_rotation_z(arm_angle)
+                } else {
+                    Quat::IDENTITY
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let parents = MMD_BONES
+            .iter()
+            .map(|(_, parent, _)| {
+                parent.and_then(|parent| MMD_BONES.iter().position(|(n, ..)| *n == parent))
+            })
+            .collect::<Vec<_>>();
+
+        // The nearest ancestor mapped to a humanoid bone. 上半身 (Spine) and 下半身 (Hips)
+        // are siblings under 腰 in MMD, but Hips is the parent of Spine in VRM.
+        let humanoid_parents = MMD_BONES
+            .iter()
+            .enumerate()
+            .map(|(index, (.., bone))| {
+                if *bone == Some(BoneName::Spine) {
+                    return MMD_BONES
+                        .iter()
+                        .position(|(.., b)| *b == Some(BoneName::Hips));
+                }
+
+                let mut parent = parents[index];
+                while let Some(p) = parent {
+                    if MMD_BONES[p].2.is_some() {
+                        break;
+                    }
+                    parent = parents[p];
+                }
+                parent
+            })
+            .collect::<Vec<_>>();
+
+        let (first, last) = keyframes
+            .values()
+            .flatten()
+            .fold((u32::MAX, 0), |(first, last), k| {
+                (first.min(k.frame), last.max(k.frame))
+            });
+
+        if first <= last {
+            let frames = first..=last;
+            let times = frames
+                .clone()
+                .map(|f| f as f32 / FRAME_RATE)
+                .collect::<Vec<_>>();
+
+            let mut rotations = HashMap::<BoneName, Vec<Quat>>::new();
+            let mut hips_positions = Vec::new();
+
+            for frame in frames {
+                let mut globals = Vec::<Transform>::with_capacity(MMD_BONES.len());
+
+                for (index, parent) in parents.iter().enumerate() {
+                    let local = keyframes
+                        .get(&index)
+                        .map(|keys| sample_bone(keys, frame as f32))
+                        .unwrap_or_default();
+
+                    let global = match parent {
+                        Some(parent) => globals[*parent] * local,
+                        None => local,
+                    };
+                    globals.push(global);
+                }
+
+                for (index, (_, _, bone)) in MMD_BONES.iter().enumerate() {
+                    let Some(bone) = bone else {
+                        continue;
+                    };
+
+                    let parent = humanoid_parents[index]
+                        .map(|p| globals[p].rotation * rests[p])
+                        .unwrap_or_default();
+                    let global = globals[index].rotation * rests[index];
+
+                    rotations
+                        .entry(bone.clone())
+                        .or_default()
+                        .push((parent.inverse() * global).normalize());
+
+                    if *bone == BoneName::Hips {
+                        hips_positions.push(REST_HIPS + globals[index].translation);
+                    }
+                }
+            }
+
+            for (bone, values) in rotations {
+                animation.rotations.insert(
+                    bone,
+                    Track::new(times.clone(), values, Interpolation::Linear),
+                );
+            }
+
+            animation.hips_position =
+                Some(Track::new(times, hips_positions, Interpolation::Linear));
+        }
+
+        let mut morphs = HashMap::<Expression, Vec<&VmdMorphKeyframe>>::new();
+        for keyframe in self.morphs.iter() {
+            morphs
+                .entry(morph_expression(&keyframe.name))
+                .or_default()
+                .push(keyframe);
+        }
+
+        for (expression, mut keys) in morphs {
+            keys.sort_by_key(|k| k.frame);
+            animation.expressions.insert(
+                expression,
+                Track::new(
+                    keys.iter().map(|k| k.frame as f32 / FRAME_RATE).collect(),
+                    keys.iter().map(|k| k.weight).collect(),
+                    Interpolation::Linear,
+                ),
+            );
+        }
+
+        animation.update_duration();
+        animation
+    }
+}
+
+/// Local offset and rotation of a bone at `frame`, eased between its keyframes.
+fn sample_bone(keys: &[&VmdBoneKeyframe], frame: f32) -> Transform {
+    let next = keys.partition_point(|k| k.frame as f32 <= frame);
+
+    let (from, to, t) = match next {
+        0 => (keys[0], keys[0], 0.0),
+        next if next >= keys.len() => (keys[next - 1], keys[next - 1], 0.0),
+        next => {
+            let (from, to) = (keys[next - 1], keys[next]);
+            let span = (to.frame - from.frame) as f32;
+            (from, to, (frame - from.frame as f32) / span)
+        }
+    };
+
+    let [x, y, z, rotation] = to.interpolation.map(|curve| curve.sample(t));
+
+    Transform {
+        translation: Vec3::new(
+            from.position.x.interpolate(&to.position.x, x),
+            from.position.y.interpolate(&to.position.y, y),
+            from.position.z.interpolate(&to.position.z, z),
+        ),
+        rotation: from.rotation.slerp(to.rotation, rotation),
+        scale: Vec3::ONE,
+    }
+}
+
+/// Replaces full-width digits, which names use inconsistently.
+fn normalize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '０'..='９' => char::from(b'0' + (c as u32 - '０' as u32) as u8),
+            c => c,
+        })
+        .collect()
+}
+
+fn has_ancestor(name: &str, ancestor: &str) -> bool {
+    let mut current = Some(name);
+
+    while let Some(name) = current {
+        if name == ancestor {
+            return true;
+        }
+        current = MMD_BONES
+            .iter()
+            .find(|(n, ..)| *n == name)
+            .and_then(|(_, parent, _)| *parent);
+    }
+
+    false
+}
+
+/// Maps the standard MMD facial morphs to expression presets.
+/// Other morphs become [`Expression::Custom`].
+fn morph_expression(name: &str) -> Expression {
+    match name {
+        "あ" => Expression::A,
+        "い" => Expression::I,
+        "う" => Expression::U,
+        "え" => Expression::E,
+        "お" => Expression::O,
+        "まばたき" => Expression::BLINK,
+        "ウィンク" | "ウィンク２" => Expression::BLINK_LEFT,
+        "ウィンク右" | "ウィンク２右" => Expression::BLINK_RIGHT,
+        "笑い" | "にこり" => Expression::JOY,
+        "怒り" => Expression::ANGRY,
+        "困る" => Expression::SORROW,
+        "なごみ" => Expression::FUN,
+        name => Expression::Custom(name.to_string()),
+    }
+}
+
+/// The standard MMD skeleton, with parents before their children.
+const MMD_BONES: &[(&str, Option<&str>, Option<BoneName>)] = &[
+    ("全ての親", None, None),
+    ("センター", Some("全ての親"), None),
+    ("グルーブ", Some("センター"), None),
+    ("腰", Some("グルーブ"), None),
+    ("下半身", Some("腰"), Some(BoneName::Hips)),
+    ("上半身", Some("腰"), Some(BoneName::Spine)),
+    ("上半身2", Some("上半身"), Some(BoneName::Chest)),
+    ("首", Some("上半身2"), Some(BoneName::Neck)),
+    ("頭", Some("首"), Some(BoneName::Head)),
+    ("左目", Some("頭"), Some(BoneName::LeftEye)),
+    ("右目", Some("頭"), Some(BoneName::RightEye)),
+    ("左肩", Some("上半身2"), Some(BoneName::LeftShoulder)),
+    ("左腕", Some("左肩"), Some(BoneName::LeftUpperArm)),
+    ("左腕捩", Some("左腕"), None),
+    ("左ひじ", Some("左腕捩"), Some(BoneName::LeftLowerArm)),
+    ("左手捩", Some("左ひじ"), None),
+    ("左手首", Some("左手捩"), Some(BoneName::LeftHand)),
+    ("左親指0", Some("左手首"), Some(BoneName::LeftThumbProximal)),
+    (
+        "左親指1",
+        Some("左親指0"),
+        Some(BoneName::LeftThumbIntermediate),
+    ),
+    ("左親指2", Some("左親指1"), Some(BoneName::LeftThumbDistal)),
+    ("左人指1", Some("左手首"), Some(BoneName::LeftIndexProximal)),
+    (
+        "左人指2",
+        Some("左人指1"),
+        Some(BoneName::LeftIndexIntermediate),
+    ),
+    ("左人指3", Some("左人指2"), Some(BoneName::LeftIndexDistal)),
+    (
+        "左中指1",
+        Some("左手首"),
+        Some(BoneName::LeftMiddleProximal),
+    ),
+    (
+        "左中指2",
+        Some("左中指1"),
+        Some(BoneName::LeftMiddleIntermediate),
+    ),
+    ("左中指3", Some("左中指2"), Some(BoneName::LeftMiddleDistal)),
+    ("左薬指1", Some("左手首"), Some(BoneName::LeftRingProximal)),
+    (
+        "左薬指2",
+        Some("左薬指1"),
+        Some(BoneName::LeftRingIntermediate),
+    ),
+    ("左薬指3", Some("左薬指2"), Some(BoneName::LeftRingDistal)),
+    (
+        "左小指1",
+        Some("左手首"),
+        Some(BoneName::LeftLittleProximal),
+    ),
+    (
+        "左小指2",
+        Some("左小指1"),
+        Some(BoneName::LeftLittleIntermediate),
+    ),
+    ("左小指3", Some("左小指2"), Some(BoneName::LeftLittleDistal)),
+    ("右肩", Some("上半身2"), Some(BoneName::RightShoulder)),
+    ("右腕", Some("右肩"), Some(BoneName::RightUpperArm)),
+    ("右腕捩", Some("右腕"), None),
+    ("右ひじ", Some("右腕捩"), Some(BoneName::RightLowerArm)),
+    ("右手捩", Some("右ひじ"), None),
+    ("右手首", Some("右手捩"), Some(BoneName::RightHand)),
+    (
+        "右親指0",
+        Some("右手首"),
+        Some(BoneName::RightThumbProximal),
+    ),
+    (
+        "右親指1",
+        Some("右親指0"),
+        Some(BoneName::RightThumbIntermediate),
+    ),
+    ("右親指2", Some("右親指1"), Some(BoneName::RightThumbDistal)),
+    (
+        "右人指1",
+        Some("右手首"),
+        Some(BoneName::RightIndexProximal),
+    ),
+    (
+        "右人指2",
+        Some("右人指1"),
+        Some(BoneName::RightIndexIntermediate),
+    ),
+    ("右人指3", Some("右人指2"), Some(BoneName::RightIndexDistal)),
+    (
+        "右中指1",
+        Some("右手首"),
+        Some(BoneName::RightMiddleProximal),
+    ),
+    (
+        "右中指2",
+        Some("右中指1"),
+        Some(BoneName::RightMiddleIntermediate),
+    ),
+    (
+        "右中指3",
+        Some("右中指2"),
+        Some(BoneName::RightMiddleDistal),
+    ),
+    ("右薬指1", Some("右手首"), Some(BoneName::RightRingProximal)),
+    (
+        "右薬指2",
+        Some("右薬指1"),
+        Some(BoneName::RightRingIntermediate),
+    ),
+    ("右薬指3", Some("右薬指2"), Some(BoneName::RightRingDistal)),
+    (
+        "右小指1",
+        Some("右手首"),
+        Some(BoneName::RightLittleProximal),
+    ),
+    (
+        "右小指2",
+        Some("右小指1"),
+        Some(BoneName::RightLittleIntermediate),
+    ),
+    (
+        "右小指3",
+        Some("右小指2"),
+        Some(BoneName::RightLittleDistal),
+    ),
+    ("左足", Some("下半身"), Some(BoneName::LeftUpperLeg)),
+    ("左ひざ", Some("左足"), Some(BoneName::LeftLowerLeg)),
+    ("左足首", Some("左ひざ"), Some(BoneName::LeftFoot)),
+    ("左つま先", Some("左足首"), Some(BoneName::LeftToes)),
+    ("右足", Some("下半身"), Some(BoneName::RightUpperLeg)),
+    ("右ひざ", Some("右足"), Some(BoneName::RightLowerLeg)),
+    ("右足首", Some("右ひざ"), Some(BoneName::RightFoot)),
+    ("右つま先", Some("右足首"), Some(BoneName::RightToes)),
+];
+
+struct Bytes<'a>(&'a [u8]);
+
+impl<'a> Bytes<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], VmdError> {
+        if self.0.len() < len {
+            return Err(VmdError::UnexpectedEnd);
+        }
+
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, VmdError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn f32(&mut self) -> Result<f32, VmdError> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    /// A null-terminated Shift JIS string in a fixed-size field.
+    fn name(&mut self, len: usize) -> Result<String, VmdError> {
+        let bytes = self.take(len)?;
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(len);
+        Ok(SHIFT_JIS.decode(&bytes[..end]).0.into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(name: &str, len: usize) -> Vec<u8> {
+        let mut bytes = SHIFT_JIS.encode(name).0.into_owned();
+        bytes.resize(len, 0);
+        bytes
+    }
+
+    fn bone_keyframe(name_: &str, frame: u32, rotation: Quat) -> Vec<u8> {
+        let mut bytes = name(name_, 15);
+        bytes.extend(frame.to_le_bytes());
+        for value in [
+            0.0, 0.0, 0.0, rotation.x, rotation.y, rotation.z, rotation.w,
+        ] {
+            bytes.extend(f32::to_le_bytes(value));
+        }
+        // Linear curves for every channel.
+        for i in 0..64 {
+            bytes.push(if (i / 4) % 4 < 2 { 20 } else { 107 });
+        }
+        bytes
+    }
+
+    #[test]
+    fn bezier_eases_between_keyframes() {
+        assert!((VmdBezier::LINEAR.sample(0.3) - 0.3).abs() < 0.001);
+
+        let ease_in = VmdBezier {
+            x1: 1.0,
+            y1: 0.0,
+            x2: 1.0,
+            y2: 1.0,
+        };
+        assert!(ease_in.sample(0.5) < 0.5);
+        assert!((ease_in.sample(1.0) - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn vmd_maps_bones_and_morphs() {
+        let mut bytes = b"Vocaloid Motion Data 0002".to_vec();
+        bytes.resize(30, 0);
+        bytes.extend(name("テスト", 20));
+
+        bytes.extend(2u32.to_le_bytes());
+        bytes.extend(bone_keyframe("左腕", 0, Quat::IDENTITY));
+        bytes.extend(bone_keyframe("左腕", 10, Quat::from_rotation_z(0.5)));
+
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(name("あ", 15));
+        bytes.extend(5u32.to_le_bytes());
+        bytes.extend(f32::to_le_bytes(0.8));
+
+        let vmd = Vmd::parse(&bytes).unwrap();
+        assert_eq!(vmd.model_name, "テスト");
+        assert_eq!(vmd.bones.len(), 2);
+        assert_eq!(vmd.bones[0].name, "左腕");
+
+        let animation = vmd.to_animation(MMD_ARM_ANGLE);
+        assert!((animation.duration - 10.0 / FRAME_RATE).abs() < 0.001);
+        assert_eq!(animation.expressions[&Expression::A].sample(0.0), Some(0.8));
+
+        // At rest, the arm hangs in the A-pose.
+        let arm = &animation.rotations[&BoneName::LeftUpperArm];
+        let direction = arm.sample(0.0).unwrap() * Vec3::X;
+        assert!((direction.y + MMD_ARM_ANGLE.to_radians().sin()).abs() < 0.001);
+    }
+
+    #[test]
+    fn lower_body_keys_leave_the_spine_still() {
+        let mut bytes = b"Vocaloid Motion Data 0002".to_vec();
+        bytes.resize(30, 0);
+        bytes.extend(name("テスト", 20));
+
+        bytes.extend(4u32.to_le_bytes());
+        bytes.extend(bone_keyframe("下半身", 0, Quat::IDENTITY));
+        bytes.extend(bone_keyframe("下半身", 10, Quat::from_rotation_x(0.6)));
+        bytes.extend(bone_keyframe("上半身2", 0, Quat::IDENTITY));
+        bytes.extend(bone_keyframe("上半身2", 10, Quat::from_rotation_y(0.4)));
+        bytes.extend(0u32.to_le_bytes());
+
+        let animation = Vmd::parse(&bytes).unwrap().to_animation(MMD_ARM_ANGLE);
+
+        for time in [0.0, 5.0 / FRAME_RATE, 10.0 / FRAME_RATE] {
+            let hips = animation.rotations[&BoneName::Hips].sample(time).unwrap();
+            let spine = animation.rotations[&BoneName::Spine].sample(time).unwrap();
+            assert!((hips * spine).angle_between(Quat::IDENTITY) < 0.001);
+
+            // The neck is still parented to the chest.
+            let neck = animation.rotations[&BoneName::Neck].sample(time).unwrap();
+            assert!(neck.angle_between(Quat::IDENTITY) < 0.001);
+        }
+
+        let hips = &animation.rotations[&BoneName::Hips];
+        assert!(
+            hips.sample(10.0 / FRAME_RATE)
+                .unwrap()
+                .angle_between(Quat::IDENTITY)
+                > 0.5
+        );
+    }
+}
@@ -0,0 +1,402 @@
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+    prelude::*,
+    utils::{BoxedFuture, HashMap},
+};
+use gltf::animation::{util::ReadOutputs, Interpolation as GltfInterpolation};
+use serde_vrm::{vrm0::BoneName, vrm1::vrmc_vrm_animation::VrmcVrmAnimation};
+use thiserror::Error;
+
+use crate::expressions::Expression;
+
+use super::{leg_length, Interpolation, Track, VrmAnimation};
+
+const EXTENSION_NAME: &str = "VRMC_vrm_animation";
+
+#[derive(Default)]
+pub struct VrmaLoader;
+
+#[derive(Debug, Error)]
+pub enum VrmaError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Gltf(#[from] gltf::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("Missing {EXTENSION_NAME} extension")]
+    MissingExtension,
+    #[error("Node not found: {0}")]
+    NodeNotFound(usize),
+}
+
+impl AssetLoader for VrmaLoader {
+    type Asset = VrmAnimation;
+    type Settings = ();
+    type Error = VrmaError;
+
+    fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader,
+        _settings: &'a Self::Settings,
+        _load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            read_vrma(&bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vrma"]
+    }
+}
+
+/// Keyframes of a node's rotation and translation.
+#[derive(Default)]
+struct NodeTracks {
+    rotation: Option<Track<Quat>>,
+    translation: Option<Track<Vec3>>,
+}
+
+/// Reads the first animation of a VRMA file.
+/// Only the binary chunk of the GLB is used for buffer data.
+pub(crate) fn read_vrma(bytes: &[u8]) -> Result<VrmAnimation, VrmaError> {
+    let gltf = gltf::Gltf::from_slice(bytes)?;
+
+    let ext = gltf
+        .document
+        .extension_value(EXTENSION_NAME)
+        .ok_or(VrmaError::MissingExtension)?;
+    let ext: VrmcVrmAnimation = serde_json::from_value(ext.clone())?;
+
+    let locals = gltf
+        .document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Transform {
+                translation: Vec3::from(translation),
+                rotation: Quat::from_array(rotation),
+                scale: Vec3::from(scale),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut parents = vec![None; locals.len()];
+    for node in gltf.document.nodes() {
+        for child in node.children() {
+            parents[child.index()] = Some(node.index());
+        }
+    }
+
+    // Rest pose of each node's parent, relative to the scene.
+    let parent_rests = (0..locals.len())
+        .map(|index| {
+            let mut rest = Transform::IDENTITY;
+            let mut current = parents[index];
+
+            while let Some(parent) = current {
+                rest = locals[parent] * rest;
+                current = parents[parent];
+            }
+
+            rest
+        })
+        .collect::<Vec<_>>();
+
+    let buffer_data = |buffer: gltf::Buffer| match buffer.source() {
+        gltf::buffer::Source::Bin => gltf.blob.as_deref(),
+        gltf::buffer::Source::Uri(_) => None,
+    };
+
+    let mut tracks = HashMap::<usize, NodeTracks>::new();
+
+    if let Some(animation) = gltf.animations().next() {
+        for channel in animation.channels() {
+            let reader = channel.reader(buffer_data);
+
+            let Some(times) = reader.read_inputs() else {
+                continue;
+            };
+            let times = times.collect::<Vec<_>>();
+
+            let sampler = channel.sampler().interpolation();
+            let interpolation = match sampler {
+                GltfInterpolation::Step => Interpolation::Step,
+                _ => Interpolation::Linear,
+            };
+
+            let node_tracks = tracks.entry(channel.target().node().index()).or_default();
+
+            match reader.read_outputs() {
+                Some(ReadOutputs::Rotations(rotations)) => {
+                    let values = rotations.into_f32().map(Quat::from_array).collect();
+                    let values = spline_values(values, sampler);
+                    node_tracks.rotation = Some(Track::new(times, values, interpolation));
+                }
+                Some(ReadOutputs::Translations(translations)) => {
+                    let values = translations.map(Vec3::from).collect();
+                    let values = spline_values(values, sampler);
+                    node_tracks.translation = Some(Track::new(times, values, interpolation));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let check_node = |node: u32| {
+        let node = node as usize;
+        if node < locals.len() {
+            Ok(node)
+        } else {
+            Err(VrmaError::NodeNotFound(node))
+        }
+    };
+
+    let mut animation = VrmAnimation::default();
+    let mut rest_positions = HashMap::new();
+
+    for (name, bone) in ext.humanoid.map(|h| h.human_bones).unwrap_or_default() {
+        let node = check_node(bone.node)?;
+
+        let Some(bone_name) = bone_name_from_vrm1(&name) else {
+            warn!("Unknown humanoid bone in VRMA: {}", name);
+            continue;
+        };
+
+        let parent_rest = parent_rests[node];
+        let rest = parent_rest * locals[node];
+
+        rest_positions.insert(bone_name.clone(), rest.translation);
+
+        let Some(node_tracks) = tracks.remove(&node) else {
+            continue;
+        };
+
+        // Remove the rest rotation, so every bone is treated as having none.
+        if let Some(mut track) = node_tracks.rotation {
+            for value in track.values.iter_mut() {
+                *value = (parent_rest.rotation * *value * rest.rotation.inverse()).normalize();
+            }
+
+            animation.rotations.insert(bone_name.clone(), track);
+        }
+
+        if bone_name == BoneName::Hips {
+            if let Some(mut track) = node_tracks.translation {
+                for value in track.values.iter_mut() {
+                    *value = parent_rest.transform_point(*value);
+                }
+
+                animation.hips_position = Some(track);
+            }
+        }
+    }
+
+    if let (Some(upper_leg), Some(lower_leg), Some(foot)) = (
+        rest_positions.get(&BoneName::LeftUpperLeg),
+        rest_positions.get(&BoneName::LeftLowerLeg),
+        rest_positions.get(&BoneName::LeftFoot),
+    ) {
+        animation.leg_length = leg_length(*upper_leg, *lower_leg, *foot);
+    }
+
+    if let Some(expressions) = ext.expressions {
+        let presets = expressions
+            .preset
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, expression)| (Expression::from_vrm1_preset(&name), expression));
+        let custom = expressions
+            .custom
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, expression)| (Expression::Custom(name), expression));
+
+        for (expression, node) in presets.chain(custom) {
+            let node = check_node(node.node)?;
+
+            // The weight is stored in the X translation of the node.
+            let Some(track) = tracks.get(&node).and_then(|t| t.translation.as_ref()) else {
+                continue;
+            };
+
+            let track = Track::new(
+                track.times.clone(),
+                track.values.iter().map(|value| value.x).collect(),
+                track.interpolation,
+            );
+
+            animation.expressions.insert(expression, track);
+        }
+    }
+
+    if let Some(look_at) = ext.look_at {
+        let node = check_node(look_at.node)?;
+
+        if let Some(mut track) = tracks.remove(&node).and_then(|t| t.translation) {
+            for value in track.values.iter_mut() {
+                *value = parent_rests[node].transform_point(*value);
+            }
+
+            animation.look_at = Some(track);
+        }
+    }
+
+    animation.update_duration();
+
+    Ok(animation)
+}
+
+/// Cubic spline outputs store an in-tangent, value and out-tangent per keyframe.
+/// Only the values are kept, to be interpolated linearly.
+fn spline_values<T: Copy>(values: Vec<T>, interpolation: GltfInterpolation) -> Vec<T> {
+    match interpolation {
+        GltfInterpolation::CubicSpline => values.chunks_exact(3).map(|chunk| chunk[1]).collect(),
+        _ => values,
+    }
+}
+
+/// Maps a VRM 1.0 bone name to a [`BoneName`].
+/// VRM 1.0 names the thumb bones one joint further up the hand.
+fn bone_name_from_vrm1(name: &str) -> Option<BoneName> {
+    let bone = match name {
+        "leftThumbMetacarpal" => BoneName::LeftThumbProximal,
+        "leftThumbProximal" => BoneName::LeftThumbIntermediate,
+        "rightThumbMetacarpal" => BoneName::RightThumbProximal,
+        "rightThumbProximal" => BoneName::RightThumbIntermediate,
+        _ => serde_json::from_value(serde_json::Value::String(name.to_string())).ok()?,
+    };
+
+    Some(bone)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Packs a glTF JSON document and binary buffer into a GLB.
+    fn glb(json: serde_json::Value, bin: &[u8]) -> Vec<u8> {
+        let mut json = serde_json::to_vec(&json).unwrap();
+        while !json.len().is_multiple_of(4) {
+            json.push(b' ');
+        }
+
+        let mut bin = bin.to_vec();
+        while !bin.len().is_multiple_of(4) {
+            bin.push(0);
+        }
+
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"glTF");
+        bytes.extend_from_slice(&2u32.to_le_bytes());
+        bytes.extend_from_slice(&(length as u32).to_le_bytes());
+        bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"JSON");
+        bytes.extend_from_slice(&json);
+        bytes.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"BIN\0");
+        bytes.extend_from_slice(&bin);
+        bytes
+    }
+
+    #[test]
+    fn read_vrma_normalizes_rest_rotations() {
+        let spine_rest = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let bend = Quat::from_rotation_x(0.5);
+
+        let mut bin = Vec::new();
+        for value in [0.0, 1.0] {
+            bin.extend_from_slice(&f32::to_le_bytes(value));
+        }
+        for rotation in [spine_rest, spine_rest * bend] {
+            for value in rotation.to_array() {
+                bin.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        for value in [0.0, 0.0, 0.0, 1.0, 0.0, 0.0] {
+            bin.extend_from_slice(&f32::to_le_bytes(value));
+        }
+
+        let json = json!({
+            "asset": { "version": "2.0" },
+            "extensionsUsed": [EXTENSION_NAME],
+            "scene": 0,
+            "scenes": [{ "nodes": [0, 2] }],
+            "nodes": [
+                { "name": "hips", "translation": [0.0, 1.0, 0.0], "children": [1] },
+                { "name": "spine", "translation": [0.0, 0.1, 0.0], "rotation": spine_rest.to_array() },
+                { "name": "aa" }
+            ],
+            "buffers": [{ "byteLength": bin.len() }],
+            "bufferViews": [
+                { "buffer": 0, "byteOffset": 0, "byteLength": 8 },
+                { "buffer": 0, "byteOffset": 8, "byteLength": 32 },
+                { "buffer": 0, "byteOffset": 40, "byteLength": 24 }
+            ],
+            "accessors": [
+                { "bufferView": 0, "componentType": 5126, "count": 2, "type": "SCALAR", "min": [0.0], "max": [1.0] },
+                { "bufferView": 1, "componentType": 5126, "count": 2, "type": "VEC4" },
+                { "bufferView": 2, "componentType": 5126, "count": 2, "type": "VEC3" }
+            ],
+            "animations": [{
+                "channels": [
+                    { "sampler": 0, "target": { "node": 1, "path": "rotation" } },
+                    { "sampler": 1, "target": { "node": 2, "path": "translation" } }
+                ],
+                "samplers": [
+                    { "input": 0, "output": 1 },
+                    { "input": 0, "output": 2 }
+                ]
+            }],
+            "extensions": {
+                EXTENSION_NAME: {
+                    "specVersion": "1.0",
+                    "humanoid": { "humanBones": { "hips": { "node": 0 }, "spine": { "node": 1 } } },
+                    "expressions": { "preset": { "aa": { "node": 2 } } }
+                }
+            }
+        });
+
+        let animation = read_vrma(&glb(json, &bin)).unwrap();
+
+        assert_eq!(animation.duration, 1.0);
+
+        let spine = &animation.rotations[&BoneName::Spine];
+        assert!(spine.sample(0.0).unwrap().angle_between(Quat::IDENTITY) < 0.001);
+
+        // The bend around the spine's own X axis is around -Z once the rest rotation is removed.
+        let expected = Quat::from_rotation_z(-0.5);
+        assert!(spine.sample(1.0).unwrap().angle_between(expected) < 0.001);
+
+        let aa = &animation.expressions[&Expression::A];
+        assert_eq!(aa.sample(0.5), Some(0.5));
+    }
+
+    #[test]
+    fn vrm1_thumb_bones_shift_to_vrm0() {
+        assert_eq!(
+            bone_name_from_vrm1("leftThumbMetacarpal"),
+            Some(BoneName::LeftThumbProximal)
+        );
+        assert_eq!(
+            bone_name_from_vrm1("rightThumbProximal"),
+            Some(BoneName::RightThumbIntermediate)
+        );
+        assert_eq!(
+            bone_name_from_vrm1("leftThumbDistal"),
+            Some(BoneName::LeftThumbDistal)
+        );
+        assert_eq!(
+            bone_name_from_vrm1("upperChest"),
+            Some(BoneName::UpperChest)
+        );
+        assert_eq!(bone_name_from_vrm1("tail"), None);
+    }
+}
@@ -0,0 +1,380 @@
+//! Face tracking with [ARKit](https://developer.apple.com/documentation/arkit/arfaceanchor/blendshapelocation)
+//! blend shape coefficients, such as `eyeBlinkLeft` or `jawOpen`.
+//!
+//! Coefficients are mapped to the avatar's VRM expressions by its [`ArkitMapping`].
+//! "Perfect sync" avatars, which have morph targets or blend shape groups named after
+//! the 52 ARKit blend shapes, are driven directly instead.
+
+use bevy::{
+    animation::animation_player, prelude::*, render::mesh::morph::MorphWeights,
+    scene::SceneInstance, utils::HashMap,
+};
+use bevy_gltf_kun::import::gltf::mesh::GltfMesh;
+use gltf_kun::graph::{
+    gltf::{GltfDocument, GltfWeight},
+    Weight,
+};
+
+use crate::{
+    animation::play_vrm_animations,
+    expressions::{apply_expressions, Expression, ExpressionBinds, VrmExpressions},
+    loader::Vrm,
+    nodes::instance_node_entities,
+    vmc::receive_vmc,
+    GltfNodeIndex, VrmSet,
+};
+
+/// The 52 ARKit blend shapes.
+pub const ARKIT_BLEND_SHAPES: [&str; 52] = [
+    "browDownLeft",
+    "browDownRight",
+    "browInnerUp",
+    "browOuterUpLeft",
+    "browOuterUpRight",
+    "cheekPuff",
+    "cheekSquintLeft",
+    "cheekSquintRight",
+    "eyeBlinkLeft",
+    "eyeBlinkRight",
+    "eyeLookDownLeft",
+    "eyeLookDownRight",
+    "eyeLookInLeft",
+    "eyeLookInRight",
+    "eyeLookOutLeft",
+    "eyeLookOutRight",
+    "eyeLookUpLeft",
+    "eyeLookUpRight",
+    "eyeSquintLeft",
+    "eyeSquintRight",
+    "eyeWideLeft",
+    "eyeWideRight",
+    "jawForward",
+    "jawLeft",
+    "jawOpen",
+    "jawRight",
+    "mouthClose",
+    "mouthDimpleLeft",
+    "mouthDimpleRight",
+    "mouthFrownLeft",
+    "mouthFrownRight",
+    "mouthFunnel",
+    "mouthLeft",
+    "mouthLowerDownLeft",
+    "mouthLowerDownRight",
+    "mouthPressLeft",
+    "mouthPressRight",
+    "mouthPucker",
+    "mouthRight",
+    "mouthRollLower",
+    "mouthRollUpper",
+    "mouthShrugLower",
+    "mouthShrugUpper",
+    "mouthSmileLeft",
+    "mouthSmileRight",
+    "mouthStretchLeft",
+    "mouthStretchRight",
+    "mouthUpperUpLeft",
+    "mouthUpperUpRight",
+    "noseSneerLeft",
+    "noseSneerRight",
+    "tongueOut",
+];
+
+/// Maps a morph target or blend shape group name to its ARKit blend shape.
+/// Matching ignores case and any prefix before a `.`, as in `blendShape1.jawOpen`.
+pub fn arkit_name(name: &str) -> Option<&'static str> {
+    let name = name.rsplit('.').next().unwrap_or(name);
+
+    ARKIT_BLEND_SHAPES
+        .iter()
+        .find(|shape| shape.eq_ignore_ascii_case(name))
+        .copied()
+}
+
+/// Drives avatars with [`ArkitBlendShapes`] from their face tracking coefficients.
+pub struct ArkitPlugin;
+
+impl Plugin for ArkitPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            set_arkit_targets
+                .after(crate::expressions::set_expression_binds)
+                .in_set(VrmSet::Bones),
+        )
+        .add_systems(
+            PostUpdate,
+            (
+                map_arkit_expressions
+                    .after(animation_player)
+                    .after(play_vrm_animations)
+                    .after(receive_vmc)
+                    .before(apply_expressions),
+                apply_arkit_morphs
+                    .after(apply_expressions)
+                    .before(bevy::render::mesh::morph::inherit_weights),
+            ),
+        );
+    }
+}
+
+/// ARKit blend shape coefficients from a face tracker, from 0 to 1, by name.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ArkitBlendShapes(pub HashMap<String, f32>);
+
+impl ArkitBlendShapes {
+    pub fn get(&self, name: &str) -> f32 {
+        self.0.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, name: impl Into<String>, weight: f32) {
+        self.0.insert(name.into(), weight);
+    }
+}
+
+/// How an avatar's [`ArkitBlendShapes`] drive its [`VrmExpressions`].
+/// Avatars without a mapping use [`ArkitMapping::default`].
+#[derive(Component, Clone, Debug)]
+pub struct ArkitMapping {
+    /// Blend shapes summed into each expression, with their weights.
+    pub expressions: HashMap<Expression, Vec<(String, f32)>>,
+    /// Whether to drive ARKit-named morph targets and blend shape groups directly.
+    /// Mapped expressions are skipped on avatars that have any, so the face
+    /// is not deformed twice.
+    pub perfect_sync: bool,
+}
+
+impl Default for ArkitMapping {
+    /// Maps blinks, vowels and emotions. Gaze is left to the avatar's look-at.
+    fn default() -> Self {
+        Self::empty()
+            .with(Expression::BLINK_LEFT, "eyeBlinkLeft", 1.0)
+            .with(Expression::BLINK_RIGHT, "eyeBlinkRight", 1.0)
+            .with(Expression::A, "jawOpen", 1.0)
+            .with(Expression::I, "mouthStretchLeft", 0.5)
+            .with(Expression::I, "mouthStretchRight", 0.5)
+            .with(Expression::U, "mouthPucker", 1.0)
+            .with(Expression::E, "mouthLowerDownLeft", 0.5)
+            .with(Expression::E, "mouthLowerDownRight", 0.5)
+            .with(Expression::O, "mouthFunnel", 1.0)
+            .with(Expression::JOY, "mouthSmileLeft", 0.5)
+            .with(Expression::JOY, "mouthSmileRight", 0.5)
+            .with(Expression::ANGRY, "browDownLeft", 0.5)
+            .with(Expression::ANGRY, "browDownRight", 0.5)
+            .with(Expression::SORROW, "mouthFrownLeft", 0.5)
+            .with(Expression::SORROW, "mouthFrownRight", 0.5)
+    }
+}
+
+impl ArkitMapping {
+    /// A mapping without any expressions.
+    pub fn empty() -> Self {
+        Self {
+            expressions: HashMap::new(),
+            perfect_sync: true,
+        }
+    }
+
+    /// Adds `blend_shape`, scaled by `weight`, to `expression`.
+    pub fn with(mut self, expression: Expression, blend_shape: &str, weight: f32) -> Self {
+        self.expressions
+            .entry(expression)
+            .or_default()
+            .push((blend_shape.to_string(), weight));
+        self
+    }
+
+    pub fn with_perfect_sync(mut self, perfect_sync: bool) -> Self {
+        self.perfect_sync = perfect_sync;
+        self
+    }
+
+    /// The weight of each mapped expression, from 0 to 1.
+    pub fn evaluate<'a>(
+        &'a self,
+        blend_shapes: &'a ArkitBlendShapes,
+    ) -> impl Iterator<Item = (Expression, f32)> + 'a {
+        self.expressions.iter().map(|(expression, sources)| {
+            let weight = sources
+                .iter()
+                .map(|(name, weight)| blend_shapes.get(name) * weight)
+                .sum::<f32>();
+
+            (expression.clone(), weight.clamp(0.0, 1.0))
+        })
+    }
+}
+
+/// The avatar's morph targets and blend shape groups named after ARKit blend shapes.
+#[derive(Component, Clone, Debug, Default)]
+pub(crate) struct ArkitTargets {
+    morphs: HashMap<&'static str, Vec<(Entity, usize)>>,
+    expressions: HashMap<&'static str, Expression>,
+}
+
+impl ArkitTargets {
+    fn is_empty(&self) -> bool {
+        self.morphs.is_empty() && self.expressions.is_empty()
+    }
+}
+
+/// Reads morph target names from the `targetNames` extras of each mesh.
+pub(crate) fn set_arkit_targets(
+    mut commands: Commands,
+    avatars: Query<
+        (Entity, &Handle<Vrm>, &SceneInstance, &ExpressionBinds),
+        (With<ArkitBlendShapes>, Without<ArkitTargets>),
+    >,
+    node_indices: Query<&GltfNodeIndex>,
+    scene_manager: Res<SceneSpawner>,
+    vrms: Res<Assets<Vrm>>,
+    meshes: Res<Assets<GltfMesh>>,
+) {
+    for (entity, handle, instance, binds) in avatars.iter() {
+        let Some(vrm) = vrms.get(handle) else {
+            continue;
+        };
+
+        let mut targets = ArkitTargets::default();
+
+        for expression in binds.expressions() {
+            if let Expression::Custom(name) = expression {
+                if let Some(shape) = arkit_name(name) {
+                    targets.expressions.insert(shape, expression.clone());
+                }
+            }
+        }
+
+        let graph = &vrm.gltf.graph;
+
+        let doc = graph.node_indices().find(|n| {
+            let weight = graph.node_weight(*n);
+            matches!(weight, Some(Weight::Gltf(GltfWeight::Document)))
+        });
+
+        if let Some(doc) = doc.map(GltfDocument) {
+            let node_entities = instance_node_entities(&scene_manager, **instance, &node_indices);
+
+            for node in doc.nodes(graph) {
+                let Some(mesh) = node.mesh(graph) else {
+                    continue;
+                };
+
+                let (Some(node_entity), Some(mesh)) = (
+                    doc.node_index(graph, node)
+                        .and_then(|index| node_entities.get(&index)),
+                    doc.mesh_index(graph, mesh)
+                        .and_then(|index| vrm.gltf.meshes.get(index))
+                        .and_then(|handle| meshes.get(handle)),
+                ) else {
+                    continue;
+                };
+
+                for (index, name) in target_names(mesh).iter().enumerate() {
+                    if let Some(shape) = arkit_name(name) {
+                        targets
+                            .morphs
+                            .entry(shape)
+                            .or_default()
+                            .push((*node_entity, index));
+                    }
+                }
+            }
+        }
+
+        commands.entity(entity).insert(targets);
+    }
+}
+
+fn target_names(mesh: &GltfMesh) -> Vec<String> {
+    #[derive(serde::Deserialize)]
+    struct Extras {
+        #[serde(rename = "targetNames")]
+        target_names: Vec<String>,
+    }
+
+    mesh.extras
+        .as_ref()
+        .and_then(|extras| serde_json::from_str::<Extras>(extras.get()).ok())
+        .map(|extras| extras.target_names)
+        .unwrap_or_default()
+}
+
+pub(crate) fn map_arkit_expressions(
+    mut avatars: Query<(
+        &ArkitBlendShapes,
+        Option<&ArkitMapping>,
+        Option<&ArkitTargets>,
+        &mut VrmExpressions,
+    )>,
+    default_mapping: Local<ArkitMapping>,
+) {
+    for (blend_shapes, mapping, targets, mut expressions) in avatars.iter_mut() {
+        let mapping = mapping.unwrap_or(&default_mapping);
+
+        match targets {
+            Some(targets) if mapping.perfect_sync && !targets.is_empty() => {
+                for (shape, expression) in targets.expressions.iter() {
+                    expressions.set(expression.clone(), blend_shapes.get(shape));
+                }
+            }
+            _ => {
+                for (expression, weight) in mapping.evaluate(blend_shapes) {
+                    expressions.set(expression, weight);
+                }
+            }
+        }
+    }
+}
+
+/// Sets ARKit-named morph targets after the expressions, which may share them.
+pub(crate) fn apply_arkit_morphs(
+    avatars: Query<(&ArkitBlendShapes, Option<&ArkitMapping>, &ArkitTargets)>,
+    mut morph_weights: Query<&mut MorphWeights>,
+) {
+    for (blend_shapes, mapping, targets) in avatars.iter() {
+        if !mapping.is_none_or(|mapping| mapping.perfect_sync) {
+            continue;
+        }
+
+        for (shape, morphs) in targets.morphs.iter() {
+            let weight = blend_shapes.get(shape).clamp(0.0, 1.0);
+
+            for (entity, index) in morphs.iter() {
+                let Ok(mut morph_weights) = morph_weights.get_mut(*entity) else {
+                    continue;
+                };
+
+                if let Some(value) = morph_weights.weights_mut().get_mut(*index) {
+                    *value = weight;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blend_shapes_map_to_expressions() {
+        assert_eq!(arkit_name("blendShape1.JawOpen"), Some("jawOpen"));
+        assert_eq!(arkit_name("Fcl_MTH_A"), None);
+
+        let mut blend_shapes = ArkitBlendShapes::default();
+        blend_shapes.set("eyeBlinkLeft", 0.8);
+        blend_shapes.set("mouthSmileLeft", 1.0);
+        blend_shapes.set("mouthSmileRight", 0.6);
+        blend_shapes.set("jawOpen", 1.5);
+
+        let mapping = ArkitMapping::default().with(Expression::FUN, "cheekSquintLeft", 1.0);
+        let weights = mapping.evaluate(&blend_shapes).collect::<HashMap<_, _>>();
+
+        assert_eq!(weights[&Expression::BLINK_LEFT], 0.8);
+        assert_eq!(weights[&Expression::BLINK_RIGHT], 0.0);
+        assert!((weights[&Expression::JOY] - 0.8).abs() < 0.0001);
+        assert_eq!(weights[&Expression::A], 1.0);
+        assert_eq!(weights[&Expression::FUN], 0.0);
+    }
+}
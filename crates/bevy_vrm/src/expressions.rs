@@ -0,0 +1,304 @@
+//! Facial expressions, driven by the VRM blend shape groups.
+
+use bevy::{prelude::*, render::mesh::morph::MorphWeights, scene::SceneInstance, utils::HashMap};
+use gltf_kun::graph::{
+    gltf::{GltfDocument, GltfWeight},
+    ByteNode, Extensions, Weight,
+};
+use serde_vrm::vrm0::PresetName;
+
+use crate::{
+    humanoid_bones::HumanoidBonesInitialized, loader::Vrm, nodes::instance_node_entities,
+    GltfNodeIndex,
+};
+
+/// A blend shape group of the avatar, by preset or by name.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Expression {
+    Preset(PresetName),
+    /// A group without a preset, by its name.
+    Custom(String),
+}
+
+impl Expression {
+    pub const NEUTRAL: Self = Self::Preset(PresetName::Neutral);
+    pub const A: Self = Self::Preset(PresetName::A);
+    pub const I: Self = Self::Preset(PresetName::I);
+    pub const U: Self = Self::Preset(PresetName::U);
+    pub const E: Self = Self::Preset(PresetName::E);
+    pub const O: Self = Self::Preset(PresetName::O);
+    pub const BLINK: Self = Self::Preset(PresetName::Blink);
+    pub const BLINK_LEFT: Self = Self::Preset(PresetName::BlinkLeft);
+    pub const BLINK_RIGHT: Self = Self::Preset(PresetName::BlinkRight);
+    pub const JOY: Self = Self::Preset(PresetName::Joy);
+    pub const ANGRY: Self = Self::Preset(PresetName::Angry);
+    pub const SORROW: Self = Self::Preset(PresetName::Sorrow);
+    pub const FUN: Self = Self::Preset(PresetName::Fun);
+    pub const LOOK_UP: Self = Self::Preset(PresetName::LookUp);
+    pub const LOOK_DOWN: Self = Self::Preset(PresetName::LookDown);
+    pub const LOOK_LEFT: Self = Self::Preset(PresetName::LookLeft);
+    pub const LOOK_RIGHT: Self = Self::Preset(PresetName::LookRight);
+
+    /// The vowels used for lip sync, in A, I, U, E, O order.
+    pub const VOWELS: [Self; 5] = [Self::A, Self::I, Self::U, Self::E, Self::O];
+
+    /// Maps a VRM 1.0 preset name, such as `happy` or `aa`, to its VRM 0.x equivalent.
+    /// Unknown names become [`Expression::Custom`].
+    pub fn from_vrm1_preset(name: &str) -> Self {
+        let preset = match name {
+            "happy" => PresetName::Joy,
+            "angry" => PresetName::Angry,
+            "sad" => PresetName::Sorrow,
+            "relaxed" => PresetName::Fun,
+            "aa" => PresetName::A,
+            "ih" => PresetName::I,
+            "ou" => PresetName::U,
+            "ee" => PresetName::E,
+            "oh" => PresetName::O,
+            "blink" => PresetName::Blink,
+            "blinkLeft" => PresetName::BlinkLeft,
+            "blinkRight" => PresetName::BlinkRight,
+            "lookUp" => PresetName::LookUp,
+            "lookDown" => PresetName::LookDown,
+            "lookLeft" => PresetName::LookLeft,
+            "lookRight" => PresetName::LookRight,
+            "neutral" => PresetName::Neutral,
+            _ => return Self::Custom(name.to_string()),
+        };
+
+        Self::Preset(preset)
+    }
+}
+
+/// Weight of each expression on an avatar, from 0 to 1.
+/// Expressions the avatar has no blend shape group for are ignored.
+#[derive(Component, Clone, Debug, Default)]
+pub struct VrmExpressions(pub HashMap<Expression, f32>);
+
+impl VrmExpressions {
+    pub fn get(&self, expression: &Expression) -> f32 {
+        self.0.get(expression).copied().unwrap_or_default()
+    }
+
+    pub fn set(&mut self, expression: Expression, weight: f32) {
+        self.0.insert(expression, weight);
+    }
+}
+
+/// A morph target set by an expression.
+#[derive(Clone, Debug)]
+struct MorphBind {
+    /// Entity with the [`MorphWeights`] of the mesh.
+    entity: Entity,
+    index: usize,
+    /// Weight of the morph target at full expression.
+    weight: f32,
+}
+
+#[derive(Clone, Debug, Default)]
+struct ExpressionBind {
+    /// Whether the expression snaps to 0 or 1.
+    is_binary: bool,
+    morphs: Vec<MorphBind>,
+}
+
+/// Morph targets of each expression, read from the blend shape groups.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ExpressionBinds(HashMap<Expression, ExpressionBind>);
+
+impl ExpressionBinds {
+    /// Whether the avatar has a blend shape group for `expression`.
+    pub fn contains(&self, expression: &Expression) -> bool {
+        self.0.contains_key(expression)
+    }
+
+    pub fn expressions(&self) -> impl Iterator<Item = &Expression> {
+        self.0.keys()
+    }
+}
+
+pub(crate) fn set_expression_binds(
+    mut commands: Commands,
+    avatars: Query<
+        (Entity, &Handle<Vrm>, &SceneInstance),
+        (With<HumanoidBonesInitialized>, Without<ExpressionBinds>),
+    >,
+    node_indices: Query<&GltfNodeIndex>,
+    scene_manager: Res<SceneSpawner>,
+    vrms: Res<Assets<Vrm>>,
+) {
+    for (entity, handle, instance) in avatars.iter() {
+        let Some(vrm) = vrms.get(handle) else {
+            continue;
+        };
+
+        let mut binds = ExpressionBinds::default();
+
+        let graph = &vrm.gltf.graph;
+
+        let doc = graph.node_indices().find(|n| {
+            let weight = graph.node_weight(*n);
+            matches!(weight, Some(Weight::Gltf(GltfWeight::Document)))
+        });
+
+        let ext = doc.and_then(|doc| {
+            GltfDocument(doc)
+                .get_extension::<gltf_kun_vrm::vrm0::Vrm>(graph)
+                .map(|ext| (GltfDocument(doc), ext))
+        });
+
+        if let Some((doc, ext)) = ext {
+            let node_entities = instance_node_entities(&scene_manager, **instance, &node_indices);
+
+            for group in ext.blend_shape_groups(graph) {
+                let weight = group.read(graph);
+
+                let expression = match weight.preset_name {
+                    Some(preset) if preset != PresetName::Unknown => Expression::Preset(preset),
+                    _ => match weight.name {
+                        Some(name) => Expression::Custom(name),
+                        None => continue,
+                    },
+                };
+
+                let mut bind = ExpressionBind {
+                    is_binary: weight.is_binary.unwrap_or_default(),
+                    morphs: Vec::new(),
+                };
+
+                for morph in group.binds(graph) {
+                    let Some(mesh) = morph.mesh(graph) else {
+                        continue;
+                    };
+
+                    let morph_weight = morph.read(graph);
+                    let index = morph_weight.index.unwrap_or_default() as usize;
+                    let weight = morph_weight.weight.unwrap_or(100.0) / 100.0;
+
+                    // A mesh may be used by several nodes.
+                    for node in doc.nodes(graph) {
+                        if node.mesh(graph) != Some(mesh) {
+                            continue;
+                        }
+
+                        let Some(entity) = doc
+                            .node_index(graph, node)
+                            .and_then(|index| node_entities.get(&index))
+                        else {
+                            continue;
+                        };
+
+                        bind.morphs.push(MorphBind {
+                            entity: *entity,
+                            index,
+                            weight,
+                        });
+                    }
+                }
+
+                binds.0.insert(expression, bind);
+            }
+        }
+
+        commands.entity(entity).insert(binds);
+    }
+}
+
+/// Sets the morph weights of every avatar from its [`VrmExpressions`].
+/// Runs in [`PostUpdate`], after animation and before the weights reach the meshes.
+pub(crate) fn apply_expressions(
+    avatars: Query<(&VrmExpressions, &ExpressionBinds)>,
+    mut morph_weights: Query<&mut MorphWeights>,
+) {
+    for (expressions, binds) in avatars.iter() {
+        let mut totals = HashMap::<(Entity, usize), f32>::new();
+
+        for (expression, bind) in binds.0.iter() {
+            let mut weight = expressions.get(expression).clamp(0.0, 1.0);
+
+            if bind.is_binary {
+                weight = if weight > 0.5 { 1.0 } else { 0.0 };
+            }
+
+            for morph in bind.morphs.iter() {
+                *totals.entry((morph.entity, morph.index)).or_default() += morph.weight * weight;
+            }
+        }
+
+        for ((entity, index), total) in totals {
+            let Ok(mut morph_weights) = morph_weights.get_mut(entity) else {
+                continue;
+            };
+
+            if let Some(weight) = morph_weights.weights_mut().get_mut(index) {
+                *weight = total.clamp(0.0, 1.0);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vrm1_presets_map_to_vrm0() {
+        assert_eq!(Expression::from_vrm1_preset("happy"), Expression::JOY);
+        assert_eq!(Expression::from_vrm1_preset("aa"), Expression::A);
+        assert_eq!(
+            Expression::from_vrm1_preset("surprised"),
+            Expression::Custom("surprised".to_string())
+        );
+    }
+
+    #[test]
+    fn expressions_set_morph_weights() {
+        use bevy::ecs::system::RunSystemOnce;
+
+        let mut world = World::new();
+        let mesh = world
+            .spawn(MorphWeights::new(vec![0.0; 3], None).unwrap())
+            .id();
+
+        let bind = |morphs: &[(usize, f32)], is_binary| ExpressionBind {
+            is_binary,
+            morphs: morphs
+                .iter()
+                .map(|(index, weight)| MorphBind {
+                    entity: mesh,
+                    index: *index,
+                    weight: *weight,
+                })
+                .collect(),
+        };
+
+        let mut binds = ExpressionBinds::default();
+        binds
+            .0
+            .insert(Expression::JOY, bind(&[(0, 1.0), (1, 0.5)], false));
+        binds.0.insert(Expression::A, bind(&[(1, 0.8)], false));
+        binds.0.insert(Expression::BLINK, bind(&[(2, 1.0)], true));
+
+        let mut expressions = VrmExpressions::default();
+        expressions.set(Expression::JOY, 0.5);
+        expressions.set(Expression::A, 1.0);
+        expressions.set(Expression::BLINK, 0.4);
+        expressions.set(Expression::SORROW, 1.0);
+
+        let avatar = world.spawn((expressions, binds)).id();
+        world.run_system_once(apply_expressions);
+
+        // Shared morph targets add up and are clamped, binary expressions snap.
+        let weights = world.get::<MorphWeights>(mesh).unwrap().weights();
+        assert_eq!(weights, [0.5, 1.0, 0.0]);
+
+        world
+            .get_mut::<VrmExpressions>(avatar)
+            .unwrap()
+            .set(Expression::BLINK, 0.6);
+        world.run_system_once(apply_expressions);
+
+        let weights = world.get::<MorphWeights>(mesh).unwrap().weights();
+        assert_eq!(weights[2], 1.0);
+    }
+}
@@ -10,6 +10,8 @@ use gltf_kun::{
 };
 use gltf_kun_vrm::vrm0::Vrm;
 
+use crate::GltfNodeIndex;
+
 use self::vrm0::{import_material, import_primitive_material};
 
 pub mod vrm0;
@@ -40,7 +42,11 @@ impl BevyImportExtensions<GltfDocument> for VrmExtensions {
         }
     }
 
-    fn import_node(_context: &mut ImportContext, _entity: &mut EntityWorldMut, _node: Node) {}
+    fn import_node(context: &mut ImportContext, entity: &mut EntityWorldMut, node: Node) {
+        if let Some(index) = context.doc.node_index(context.graph, node) {
+            entity.insert(GltfNodeIndex(index));
+        }
+    }
 
     fn import_primitive(
         context: &mut ImportContext,
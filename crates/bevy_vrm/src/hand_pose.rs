@@ -0,0 +1,480 @@
+//! Posing the fingers from curl and splay values, such as VR controller trigger and grip.
+
+use bevy::{animation::animation_player, prelude::*};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    ik::{rest_pose, BoneRest, IkSet, JointLimits},
+    pose::evaluate_pose_stacks,
+    retargeting::{SkeletonProfileHumanoid, VrmRetargetingInitialized},
+    HumanoidBones,
+};
+
+/// Applies [`HandPose`]s on top of animation and any [`PoseStack`](crate::pose::PoseStack), before IK.
+pub struct HandPosePlugin;
+
+impl Plugin for HandPosePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            apply_hand_poses
+                .after(animation_player)
+                .after(evaluate_pose_stacks)
+                .before(IkSet::Body),
+        );
+
+        #[cfg(feature = "reflect")]
+        app.register_type::<FingerPose>()
+            .register_type::<HandShape>()
+            .register_type::<HandPose>();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Finger {
+    Thumb,
+    Index,
+    Middle,
+    Ring,
+    Little,
+}
+
+impl Finger {
+    pub const ALL: [Finger; 5] = [
+        Finger::Thumb,
+        Finger::Index,
+        Finger::Middle,
+        Finger::Ring,
+        Finger::Little,
+    ];
+
+    /// The proximal, intermediate and distal bones of the finger.
+    pub fn bones(&self, side: Side) -> [BoneName; 3] {
+        use BoneName::*;
+
+        match (side, self) {
+            (Side::Left, Finger::Thumb) => {
+                [LeftThumbProximal, LeftThumbIntermediate, LeftThumbDistal]
+            }
+            (Side::Left, Finger::Index) => {
+                [LeftIndexProximal, LeftIndexIntermediate, LeftIndexDistal]
+            }
+            (Side::Left, Finger::Middle) => {
+                [LeftMiddleProximal, LeftMiddleIntermediate, LeftMiddleDistal]
+            }
+            (Side::Left, Finger::Ring) => [LeftRingProximal, LeftRingIntermediate, LeftRingDistal],
+            (Side::Left, Finger::Little) => {
+                [LeftLittleProximal, LeftLittleIntermediate, LeftLittleDistal]
+            }
+            (Side::Right, Finger::Thumb) => {
+                [RightThumbProximal, RightThumbIntermediate, RightThumbDistal]
+            }
+            (Side::Right, Finger::Index) => {
+                [RightIndexProximal, RightIndexIntermediate, RightIndexDistal]
+            }
+            (Side::Right, Finger::Middle) => [
+                RightMiddleProximal,
+                RightMiddleIntermediate,
+                RightMiddleDistal,
+            ],
+            (Side::Right, Finger::Ring) => {
+                [RightRingProximal, RightRingIntermediate, RightRingDistal]
+            }
+            (Side::Right, Finger::Little) => [
+                RightLittleProximal,
+                RightLittleIntermediate,
+                RightLittleDistal,
+            ],
+        }
+    }
+
+    /// Bend of the proximal, intermediate and distal joints at full curl, in degrees.
+    fn curl_degrees(&self) -> [f32; 3] {
+        match self {
+            Finger::Thumb => [40.0, 40.0, 60.0],
+            _ => [80.0, 100.0, 70.0],
+        }
+    }
+
+    /// Spread of the proximal joint at full splay, in degrees.
+    /// Positive is towards the thumb, so the middle finger stays in place.
+    fn splay_degrees(&self) -> f32 {
+        match self {
+            Finger::Thumb => 30.0,
+            Finger::Index => 10.0,
+            Finger::Middle => 0.0,
+            Finger::Ring => -8.0,
+            Finger::Little => -15.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Left,
+    Right,
+}
+
+/// Pose of a single finger.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct FingerPose {
+    /// How far the finger bends towards the palm, from 0 (straight) to 1 (fully bent).
+    pub curl: f32,
+    /// How far the finger spreads from the others, from 0 (together) to 1 (fully spread).
+    pub splay: f32,
+}
+
+impl FingerPose {
+    pub fn new(curl: f32, splay: f32) -> Self {
+        Self { curl, splay }
+    }
+}
+
+/// Pose of every finger of one hand.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct HandShape {
+    pub thumb: FingerPose,
+    pub index: FingerPose,
+    pub middle: FingerPose,
+    pub ring: FingerPose,
+    pub little: FingerPose,
+}
+
+impl HandShape {
+    /// Every finger with the same pose.
+    pub fn uniform(pose: FingerPose) -> Self {
+        Self {
+            thumb: pose,
+            index: pose,
+            middle: pose,
+            ring: pose,
+            little: pose,
+        }
+    }
+
+    /// Straight, slightly spread fingers.
+    pub fn open() -> Self {
+        Self::uniform(FingerPose::new(0.0, 0.5))
+    }
+
+    pub fn fist() -> Self {
+        Self {
+            thumb: FingerPose::new(0.7, 0.0),
+            ..Self::uniform(FingerPose::new(1.0, 0.0))
+        }
+    }
+
+    /// A fist with the index finger straight.
+    pub fn point() -> Self {
+        Self {
+            index: FingerPose::new(0.0, 0.0),
+            ..Self::fist()
+        }
+    }
+
+    /// Holding an object such as a controller or handle.
+    pub fn grip() -> Self {
+        Self {
+            thumb: FingerPose::new(0.4, 0.3),
+            ..Self::uniform(FingerPose::new(0.5, 0.1))
+        }
+    }
+
+    /// Maps VR controller inputs, from 0 to 1, onto the fingers.
+    /// The trigger curls the index finger, the grip curls the other three.
+    pub fn from_controller(trigger: f32, grip: f32) -> Self {
+        let grip = FingerPose::new(grip, 0.0);
+
+        Self {
+            thumb: FingerPose::new(0.7 * trigger.max(grip.curl), 0.0),
+            index: FingerPose::new(trigger, 0.0),
+            ..Self::uniform(grip)
+        }
+    }
+
+    pub fn finger(&self, finger: Finger) -> FingerPose {
+        match finger {
+            Finger::Thumb => self.thumb,
+            Finger::Index => self.index,
+            Finger::Middle => self.middle,
+            Finger::Ring => self.ring,
+            Finger::Little => self.little,
+        }
+    }
+
+    pub fn finger_mut(&mut self, finger: Finger) -> &mut FingerPose {
+        match finger {
+            Finger::Thumb => &mut self.thumb,
+            Finger::Index => &mut self.index,
+            Finger::Middle => &mut self.middle,
+            Finger::Ring => &mut self.ring,
+            Finger::Little => &mut self.little,
+        }
+    }
+}
+
+/// Poses the fingers of an avatar, see [`HandPosePlugin`].
+/// A hand without a shape keeps its animated pose.
+#[derive(Component, Clone, Debug, Default)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct HandPose {
+    pub left: Option<HandShape>,
+    pub right: Option<HandShape>,
+}
+
+impl HandPose {
+    pub fn both(shape: HandShape) -> Self {
+        Self {
+            left: Some(shape),
+            right: Some(shape),
+        }
+    }
+}
+
+fn apply_hand_poses(
+    profile: Local<SkeletonProfileHumanoid>,
+    avatars: Query<
+        (Entity, &HumanoidBones, &HandPose, Option<&JointLimits>),
+        With<VrmRetargetingInitialized>,
+    >,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    for (entity, skeleton, hand_pose, limits) in avatars.iter() {
+        for (side, shape, hand) in [
+            (Side::Left, hand_pose.left, BoneName::LeftHand),
+            (Side::Right, hand_pose.right, BoneName::RightHand),
+        ] {
+            let Some(shape) = shape else {
+                continue;
+            };
+
+            let Some(hand_rest) = skeleton
+                .0
+                .get(&hand)
+                .and_then(|hand| rest_pose(*hand, entity, &parents, &bone_rests))
+            else {
+                continue;
+            };
+
+            // The profile mirrors the right hand, so it spreads the other way.
+            let side_sign = match side {
+                Side::Left => 1.0,
+                Side::Right => -1.0,
+            };
+
+            for finger in Finger::ALL {
+                let pose = shape.finger(finger);
+                let curl = pose.curl.clamp(0.0, 1.0);
+                let splay = pose.splay.clamp(0.0, 1.0);
+
+                // Rotation of the bone in the normalized profile, relative to the hand.
+                let mut profile_rotation = Quat::IDENTITY;
+                let bones = finger.bones(side);
+
+                for (i, (bone, curl_degrees)) in bones.iter().zip(finger.curl_degrees()).enumerate()
+                {
+                    if let Some(rest) = profile.get(bone) {
+                        profile_rotation *= rest.rotation;
+                    }
+
+                    let name = bone;
+                    let Some(bone) = skeleton.0.get(name) else {
+                        continue;
+                    };
+
+                    let (Some(rest), Ok(local_rest)) = (
+                        rest_pose(*bone, entity, &parents, &bone_rests),
+                        bone_rests.get(*bone),
+                    ) else {
+                        continue;
+                    };
+
+                    // In the profile, fingers bend around their X axis and spread around Z.
+                    let to_local = rest.rotation.inverse() * hand_rest.rotation * profile_rotation;
+                    let mut rotation = Quat::from_axis_angle(
+                        to_local * Vec3::X,
+                        (curl * curl_degrees).to_radians(),
+                    );
+
+                    if i == 0 {
+                        let splay = splay * finger.splay_degrees() * side_sign;
+                        rotation = Quat::from_axis_angle(to_local * Vec3::Z, splay.to_radians())
+                            * rotation;
+                    }
+
+                    let mut rotation = local_rest.0.rotation * rotation;
+
+                    if let Some(limits) = limits {
+                        // The bone points towards the next one in the finger,
+                        // the distal bone continues the direction of its parent.
+                        let axis = match bones
+                            .get(i + 1)
+                            .and_then(|next| skeleton.0.get(next))
+                            .and_then(|next| bone_rests.get(*next).ok())
+                        {
+                            Some(next) => next.0.translation,
+                            None => local_rest.0.rotation.inverse() * local_rest.0.translation,
+                        };
+
+                        rotation = limits.clamp(name, rotation, local_rest.0.rotation, axis);
+                    }
+
+                    if let Ok(mut transform) = local_transforms.get_mut(*bone) {
+                        transform.rotation = rotation;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::{ecs::system::RunSystemOnce, utils::HashMap};
+
+    use super::*;
+    use crate::test_utils::{propagate, spawn_bone, spawn_root};
+
+    /// Spawns an avatar with hands and fingers at the rest pose of [`SkeletonProfileHumanoid`].
+    fn spawn_hands(world: &mut World) -> Entity {
+        let profile = SkeletonProfileHumanoid::default();
+        let root = spawn_root(world);
+        let mut bones = HashMap::new();
+
+        for (side, arm) in [
+            (
+                Side::Left,
+                [
+                    BoneName::LeftShoulder,
+                    BoneName::LeftUpperArm,
+                    BoneName::LeftLowerArm,
+                    BoneName::LeftHand,
+                ],
+            ),
+            (
+                Side::Right,
+                [
+                    BoneName::RightShoulder,
+                    BoneName::RightUpperArm,
+                    BoneName::RightLowerArm,
+                    BoneName::RightHand,
+                ],
+            ),
+        ] {
+            // The hand is placed directly under the root, at its rest pose in the profile.
+            let hand = [
+                BoneName::Hips,
+                BoneName::Spine,
+                BoneName::Chest,
+                BoneName::UpperChest,
+            ]
+            .iter()
+            .chain(&arm)
+            .fold(Transform::IDENTITY, |pose, bone| pose * profile.0[bone]);
+            let hand = spawn_bone(world, root, hand);
+            bones.insert(arm[3].clone(), hand);
+
+            for finger in Finger::ALL {
+                let mut parent = hand;
+                for bone in finger.bones(side) {
+                    parent = spawn_bone(world, parent, profile.0[&bone]);
+                    bones.insert(bone, parent);
+                }
+            }
+        }
+
+        world.entity_mut(root).insert(HumanoidBones(bones));
+        root
+    }
+
+    /// Poses both hands of a new avatar with `shape`, clamped to `limits` if any.
+    fn pose_hands(shape: HandShape, limits: Option<JointLimits>) -> (World, Entity) {
+        let mut world = World::new();
+        let root = spawn_hands(&mut world);
+        world.entity_mut(root).insert(HandPose::both(shape));
+
+        if let Some(limits) = limits {
+            world.entity_mut(root).insert(limits);
+        }
+
+        world.run_system_once(apply_hand_poses);
+        propagate(&mut world);
+
+        (world, root)
+    }
+
+    /// Height of each fingertip above its hand, after posing both hands with `shape`.
+    fn fingertip_heights(shape: HandShape) -> Vec<f32> {
+        let (world, root) = pose_hands(shape, None);
+
+        let bones = world.get::<HumanoidBones>(root).unwrap().0.clone();
+        let height = |bone: &BoneName| {
+            let global = world.get::<GlobalTransform>(bones[bone]).unwrap();
+            global.transform_point(Vec3::Y * 0.02).y
+        };
+
+        [
+            (Side::Left, BoneName::LeftHand),
+            (Side::Right, BoneName::RightHand),
+        ]
+        .into_iter()
+        .flat_map(|(side, hand)| {
+            let hand = height(&hand);
+            [Finger::Index, Finger::Middle, Finger::Ring, Finger::Little]
+                .map(|finger| height(&finger.bones(side)[2]) - hand)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn fist_curls_fingers_towards_the_palm() {
+        // Palms face down in the profile's T-pose.
+        for height in fingertip_heights(HandShape::open()) {
+            assert!(height.abs() < 0.001, "{height}");
+        }
+
+        for height in fingertip_heights(HandShape::fist()) {
+            assert!(height < -0.02, "{height}");
+        }
+    }
+
+    #[test]
+    fn fingers_are_clamped_to_joint_limits() {
+        let limits = JointLimits::default();
+        let (world, root) = pose_hands(HandShape::fist(), Some(limits.clone()));
+
+        // The intermediate joints bend 100 degrees at full curl, past their limit.
+        for side in [Side::Left, Side::Right] {
+            let name = Finger::Middle.bones(side)[1].clone();
+            let bone = world.get::<HumanoidBones>(root).unwrap().0[&name];
+            let rest = world.get::<BoneRest>(bone).unwrap().0.rotation;
+            let rotation = world.get::<Transform>(bone).unwrap().rotation;
+
+            let angle = rest.angle_between(rotation);
+            let swing = limits.get(&name).unwrap().swing;
+            assert!(angle < 100f32.to_radians() - 0.01, "{angle}");
+            assert!(angle <= swing + 0.001, "{angle}");
+        }
+    }
+
+    #[test]
+    fn controller_interpolates_between_presets() {
+        assert_eq!(HandShape::from_controller(1.0, 1.0), HandShape::fist());
+        assert_eq!(HandShape::from_controller(0.0, 1.0), HandShape::point());
+
+        let half = HandShape::from_controller(0.5, 0.5);
+        for finger in Finger::ALL {
+            let fist = HandShape::fist().finger(finger).curl;
+            assert!((half.finger(finger).curl - fist * 0.5).abs() < f32::EPSILON);
+        }
+
+        assert_eq!(
+            HandShape::from_controller(0.0, 0.0),
+            HandShape::uniform(FingerPose::default())
+        );
+    }
+}
@@ -4,7 +4,10 @@ use gltf_kun::graph::{
     ByteNode, Extensions, Weight,
 };
 
-use crate::{loader::Vrm, HumanoidBones};
+use crate::{
+    loader::Vrm, nodes::instance_node_entities, GltfNodeIndex, HumanoidBones, VrmBonesMapped,
+    VrmLoaded,
+};
 
 #[derive(Component)]
 pub struct HumanoidBonesInitialized;
@@ -15,9 +18,11 @@ pub fn set_humanoid_bones(
         (Entity, &mut HumanoidBones, &Handle<Vrm>, &SceneInstance),
         Without<HumanoidBonesInitialized>,
     >,
-    names: Query<(Entity, &Name)>,
+    node_indices: Query<&GltfNodeIndex>,
     scene_manager: Res<SceneSpawner>,
     vrms: Res<Assets<Vrm>>,
+    mut loaded: EventWriter<VrmLoaded>,
+    mut bones_mapped: EventWriter<VrmBonesMapped>,
 ) {
     for (entity, mut humanoid_bones, handle, instance) in vrm.iter_mut() {
         if !scene_manager.instance_is_ready(**instance) {
@@ -26,6 +31,7 @@ pub fn set_humanoid_bones(
 
         if let Some(vrm) = vrms.get(handle) {
             commands.entity(entity).insert(HumanoidBonesInitialized);
+            loaded.send(VrmLoaded { entity });
 
             let graph = &vrm.gltf.graph;
 
@@ -42,6 +48,8 @@ pub fn set_humanoid_bones(
                 None => continue,
             };
 
+            let node_entities = instance_node_entities(&scene_manager, **instance, &node_indices);
+
             for bone in ext.human_bones(graph) {
                 let node = match bone.node(graph) {
                     Some(n) => n,
@@ -55,32 +63,11 @@ pub fn set_humanoid_bones(
                     None => continue,
                 };
 
-                let node_handle = match vrm.gltf.node_handles.get(&node) {
-                    Some(handle) => handle.clone(),
-                    None => continue,
-                };
-
-                let node_name = vrm.gltf.named_nodes.iter().find_map(|(name, n)| {
-                    if *n == node_handle {
-                        Some(name.clone())
-                    } else {
-                        None
-                    }
-                });
-
-                let node_name = match node_name {
-                    Some(n) => n,
-                    None => continue,
-                };
-
-                let node_entity = match names.iter().find_map(|(entity, name)| {
-                    if name.as_str() == node_name.as_str() {
-                        Some(entity)
-                    } else {
-                        None
-                    }
-                }) {
-                    Some(entity) => entity,
+                let node_entity = match doc
+                    .node_index(graph, node)
+                    .and_then(|index| node_entities.get(&index))
+                {
+                    Some(entity) => *entity,
                     None => {
                         warn!("Could not find entity for bone: {:?}", bone_name);
                         continue;
@@ -89,6 +76,8 @@ pub fn set_humanoid_bones(
 
                 humanoid_bones.0.insert(bone_name, node_entity);
             }
+
+            bones_mapped.send(VrmBonesMapped { entity });
         }
     }
 }
@@ -0,0 +1,507 @@
+//! General purpose solvers for chains of any length, such as spines, tails or hair.
+
+use bevy::{prelude::*, utils::HashMap};
+use serde_vrm::vrm0::BoneName;
+
+use crate::HumanoidBones;
+
+use super::{BoneRest, IkTarget, JointLimits};
+
+/// Limits how far a joint may rotate away from its rest rotation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct JointLimit {
+    /// Largest angle the bone may swing away from its rest direction, in radians.
+    pub swing: f32,
+    /// Smallest rotation around the bone's own axis, in radians.
+    pub min_twist: f32,
+    /// Largest rotation around the bone's own axis, in radians.
+    pub max_twist: f32,
+}
+
+impl JointLimit {
+    pub const FREE: Self = Self {
+        swing: std::f32::consts::PI,
+        min_twist: -std::f32::consts::PI,
+        max_twist: std::f32::consts::PI,
+    };
+
+    /// Clamps `offset`, a rotation relative to the rest rotation,
+    /// where `axis` is the direction of the bone in its rest frame.
+    pub fn clamp(&self, offset: Quat, axis: Vec3) -> Quat {
+        let Some(axis) = axis.try_normalize() else {
+            return offset;
+        };
+
+        let (swing, twist) = swing_twist(offset, axis);
+
+        let twist_angle = twist.xyz().dot(axis).atan2(twist.w) * 2.0;
+        let twist_angle = wrap_angle(twist_angle).clamp(self.min_twist, self.max_twist);
+        let twist = Quat::from_axis_angle(axis, twist_angle);
+
+        let (swing_axis, swing_angle) = swing.to_axis_angle();
+        let swing_angle = wrap_angle(swing_angle);
+        let swing = if swing_angle.abs() > self.swing {
+            Quat::from_axis_angle(swing_axis, self.swing * swing_angle.signum())
+        } else {
+            swing
+        };
+
+        (swing * twist).normalize()
+    }
+
+    /// Clamps a bone's local `rotation` relative to its `rest` rotation,
+    /// where `axis` is the direction of the bone in its rest frame.
+    pub fn clamp_local(&self, rotation: Quat, rest: Quat, axis: Vec3) -> Quat {
+        rest * self.clamp(rest.inverse() * rotation, axis)
+    }
+}
+
+/// Splits `rotation` into a swing, followed by a twist around `axis`.
+fn swing_twist(rotation: Quat, axis: Vec3) -> (Quat, Quat) {
+    let projected = axis * rotation.xyz().dot(axis);
+    let twist = Quat::from_xyzw(projected.x, projected.y, projected.z, rotation.w);
+
+    let twist = if twist.length_squared() <= f32::EPSILON {
+        Quat::IDENTITY
+    } else {
+        twist.normalize()
+    };
+
+    (rotation * twist.inverse(), twist)
+}
+
+fn wrap_angle(angle: f32) -> f32 {
+    use std::f32::consts::{PI, TAU};
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+/// A joint of an [`IkChainPose`].
+#[derive(Clone, Copy, Debug)]
+pub struct ChainJoint {
+    /// Translation relative to the previous joint, or to the chain origin for the first.
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    /// Rotation at rest, which [`JointLimit`]s are relative to.
+    pub rest: Quat,
+    pub limit: Option<JointLimit>,
+}
+
+impl ChainJoint {
+    fn local(&self) -> Transform {
+        Transform {
+            translation: self.translation,
+            rotation: self.rotation,
+            scale: self.scale,
+        }
+    }
+}
+
+/// The local pose of a chain of joints, from the root to the tip, for an [`IkSolver`].
+#[derive(Clone, Debug)]
+pub struct IkChainPose {
+    /// World transform of the root joint's parent.
+    pub origin: Transform,
+    pub joints: Vec<ChainJoint>,
+}
+
+impl IkChainPose {
+    /// World transforms of every joint.
+    pub fn globals(&self) -> Vec<Transform> {
+        let mut current = self.origin;
+
+        self.joints
+            .iter()
+            .map(|joint| {
+                current = current * joint.local();
+                current
+            })
+            .collect()
+    }
+
+    /// World position of the last joint.
+    pub fn tip(&self) -> Vec3 {
+        self.globals()
+            .last()
+            .map(|tip| tip.translation)
+            .unwrap_or(self.origin.translation)
+    }
+
+    /// Sets the world rotation of joint `index`, then applies its limit.
+    pub fn set_global_rotation(&mut self, index: usize, rotation: Quat) {
+        let parent = match index {
+            0 => self.origin.rotation,
+            _ => self.globals()[index - 1].rotation,
+        };
+
+        self.set_rotation(index, (parent.inverse() * rotation).normalize());
+    }
+
+    /// Sets the local rotation of joint `index`, then applies its limit.
+    pub fn set_rotation(&mut self, index: usize, rotation: Quat) {
+        // The bone points towards the next joint.
+        let axis = match self.joints.get(index + 1) {
+            Some(next) => next.translation,
+            None => Vec3::Y,
+        };
+
+        let joint = &mut self.joints[index];
+
+        joint.rotation = match joint.limit {
+            Some(limit) => limit.clamp_local(rotation, joint.rest, axis),
+            None => rotation,
+        };
+    }
+}
+
+/// Rotates the joints of a chain so that its tip reaches a target.
+pub trait IkSolver: Send + Sync + 'static {
+    /// Solves `pose` towards `target`, a position in world space.
+    /// The last joint is treated as the end of the chain and is not rotated.
+    fn solve(&self, pose: &mut IkChainPose, target: Vec3);
+}
+
+/// Forward And Backward Reaching Inverse Kinematics.
+/// Converges quickly and spreads the bend evenly along the chain.
+#[derive(Clone, Copy, Debug)]
+pub struct Fabrik {
+    pub iterations: usize,
+    /// Distance from the target at which the tip has arrived.
+    pub tolerance: f32,
+}
+
+impl Default for Fabrik {
+    fn default() -> Self {
+        Self {
+            iterations: 10,
+            tolerance: 0.001,
+        }
+    }
+}
+
+impl IkSolver for Fabrik {
+    fn solve(&self, pose: &mut IkChainPose, target: Vec3) {
+        if pose.joints.len() < 2 {
+            return;
+        }
+
+        for _ in 0..self.iterations {
+            let globals = pose.globals();
+
+            if globals.last().unwrap().translation.distance(target) <= self.tolerance {
+                break;
+            }
+
+            let mut positions = globals.iter().map(|t| t.translation).collect::<Vec<_>>();
+            let lengths = positions
+                .windows(2)
+                .map(|pair| pair[0].distance(pair[1]))
+                .collect::<Vec<_>>();
+            let root = positions[0];
+
+            // Backward, from the tip to the root.
+            *positions.last_mut().unwrap() = target;
+            for i in (0..positions.len() - 1).rev() {
+                let direction = (positions[i] - positions[i + 1]).normalize_or_zero();
+                positions[i] = positions[i + 1] + direction * lengths[i];
+            }
+
+            // Forward, from the root to the tip.
+            positions[0] = root;
+            for i in 0..positions.len() - 1 {
+                let direction = (positions[i + 1] - positions[i]).normalize_or_zero();
+                positions[i + 1] = positions[i] + direction * lengths[i];
+            }
+
+            // Turn each joint towards its new child position, limits included.
+            for i in 0..positions.len() - 1 {
+                let globals = pose.globals();
+                let current = globals[i + 1].translation - globals[i].translation;
+                let desired = positions[i + 1] - globals[i].translation;
+
+                let (Some(current), Some(desired)) =
+                    (current.try_normalize(), desired.try_normalize())
+                else {
+                    continue;
+                };
+
+                let rotation = Quat::from_rotation_arc(current, desired) * globals[i].rotation;
+                pose.set_global_rotation(i, rotation);
+            }
+        }
+    }
+}
+
+/// Cyclic Coordinate Descent.
+/// Cheap per iteration and handles tight joint limits well, but favours the joints near the tip.
+#[derive(Clone, Copy, Debug)]
+pub struct Ccd {
+    pub iterations: usize,
+    /// Distance from the target at which the tip has arrived.
+    pub tolerance: f32,
+}
+
+impl Default for Ccd {
+    fn default() -> Self {
+        Self {
+            iterations: 20,
+            tolerance: 0.001,
+        }
+    }
+}
+
+impl IkSolver for Ccd {
+    fn solve(&self, pose: &mut IkChainPose, target: Vec3) {
+        if pose.joints.len() < 2 {
+            return;
+        }
+
+        for _ in 0..self.iterations {
+            if pose.tip().distance(target) <= self.tolerance {
+                break;
+            }
+
+            for i in (0..pose.joints.len() - 1).rev() {
+                let globals = pose.globals();
+                let joint = globals[i];
+                let tip = globals.last().unwrap().translation;
+
+                let (Some(current), Some(desired)) = (
+                    (tip - joint.translation).try_normalize(),
+                    (target - joint.translation).try_normalize(),
+                ) else {
+                    continue;
+                };
+
+                let rotation = Quat::from_rotation_arc(current, desired) * joint.rotation;
+                pose.set_global_rotation(i, rotation);
+            }
+        }
+    }
+}
+
+/// A chain of bones solved towards a target by an [`IkSolver`].
+/// Can be added to any entity, such as the avatar or a tail.
+#[derive(Component)]
+pub struct IkChain {
+    /// Bones from the root to the tip of the chain.
+    pub joints: Vec<Entity>,
+    /// Limits for each of the [`Self::joints`], if any.
+    /// Humanoid bones without one use the avatar's [`JointLimits`].
+    pub limits: Vec<Option<JointLimit>>,
+    pub target: IkTarget,
+    pub solver: Box<dyn IkSolver>,
+}
+
+impl IkChain {
+    pub fn new(joints: Vec<Entity>, target: IkTarget, solver: impl IkSolver) -> Self {
+        Self {
+            limits: vec![None; joints.len()],
+            joints,
+            target,
+            solver: Box::new(solver),
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Vec<Option<JointLimit>>) -> Self {
+        self.limits = limits;
+        self
+    }
+}
+
+/// Bones from `root` down to `tip`, following the entity hierarchy.
+/// Returns [`None`] if either bone is missing or `root` is not an ancestor of `tip`.
+pub fn humanoid_chain(
+    bones: &HumanoidBones,
+    root: BoneName,
+    tip: BoneName,
+    parents: &Query<&Parent>,
+) -> Option<Vec<Entity>> {
+    let root = *bones.0.get(&root)?;
+    let tip = *bones.0.get(&tip)?;
+
+    let mut chain = vec![tip];
+    let mut current = tip;
+
+    while current != root {
+        current = parents.get(current).ok()?.get();
+        chain.push(current);
+    }
+
+    chain.reverse();
+    Some(chain)
+}
+
+pub(super) fn solve_chains(
+    chains: Query<&IkChain>,
+    avatars: Query<(&HumanoidBones, &JointLimits)>,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    global_transforms: Query<&GlobalTransform>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    if chains.is_empty() {
+        return;
+    }
+
+    let avatar_limits = avatars
+        .iter()
+        .flat_map(|(bones, limits)| {
+            bones
+                .0
+                .iter()
+                .filter_map(|(name, entity)| Some((*entity, limits.get(name)?)))
+        })
+        .collect::<HashMap<_, _>>();
+
+    for chain in chains.iter() {
+        let (Some(first), Some(last)) = (chain.joints.first(), chain.joints.last()) else {
+            continue;
+        };
+
+        let origin = match parents
+            .get(*first)
+            .and_then(|parent| global_transforms.get(parent.get()))
+        {
+            Ok(origin) => *origin,
+            Err(_) => GlobalTransform::IDENTITY,
+        };
+
+        let Some(target) = chain.target.resolve(&global_transforms, &origin, *last) else {
+            continue;
+        };
+
+        let mut joints = Vec::with_capacity(chain.joints.len());
+
+        for (i, entity) in chain.joints.iter().enumerate() {
+            let Ok(local) = local_transforms.get(*entity) else {
+                break;
+            };
+
+            let rest = match bone_rests.get(*entity) {
+                Ok(rest) => rest.0.rotation,
+                Err(_) => local.rotation,
+            };
+
+            joints.push(ChainJoint {
+                translation: local.translation,
+                rotation: local.rotation,
+                scale: local.scale,
+                rest,
+                limit: chain
+                    .limits
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .or_else(|| avatar_limits.get(entity).copied()),
+            });
+        }
+
+        if joints.len() != chain.joints.len() {
+            continue;
+        }
+
+        let mut pose = IkChainPose {
+            origin: origin.compute_transform(),
+            joints,
+        };
+
+        chain.solver.solve(&mut pose, target.translation);
+
+        for (entity, joint) in chain.joints.iter().zip(pose.joints) {
+            if let Ok(mut transform) = local_transforms.get_mut(*entity) {
+                transform.rotation = joint.rotation;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_4;
+
+    use super::*;
+
+    /// A straight chain of three bones, each 1 unit long, pointing up.
+    fn straight_chain() -> IkChainPose {
+        let joint = |y: f32| ChainJoint {
+            translation: Vec3::new(0.0, y, 0.0),
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+            rest: Quat::IDENTITY,
+            limit: None,
+        };
+
+        IkChainPose {
+            origin: Transform::IDENTITY,
+            joints: vec![joint(0.0), joint(1.0), joint(1.0), joint(1.0)],
+        }
+    }
+
+    #[test]
+    fn fabrik_reaches_target() {
+        let mut pose = straight_chain();
+        let target = Vec3::new(1.5, 1.5, 0.5);
+
+        Fabrik::default().solve(&mut pose, target);
+
+        assert!(pose.tip().distance(target) < 0.01);
+    }
+
+    #[test]
+    fn ccd_reaches_target() {
+        let mut pose = straight_chain();
+        let target = Vec3::new(-1.0, 2.0, 1.0);
+
+        Ccd {
+            iterations: 50,
+            ..default()
+        }
+        .solve(&mut pose, target);
+
+        assert!(pose.tip().distance(target) < 0.01);
+    }
+
+    #[test]
+    fn unreachable_target_stretches_towards_it() {
+        let mut pose = straight_chain();
+
+        Fabrik::default().solve(&mut pose, Vec3::new(10.0, 0.0, 0.0));
+
+        assert!(pose.tip().distance(Vec3::new(3.0, 0.0, 0.0)) < 0.01);
+    }
+
+    #[test]
+    fn limits_are_respected() {
+        let mut pose = straight_chain();
+        let limit = JointLimit {
+            swing: FRAC_PI_4,
+            min_twist: 0.0,
+            max_twist: 0.0,
+        };
+
+        for joint in pose.joints.iter_mut() {
+            joint.limit = Some(limit);
+        }
+
+        Ccd::default().solve(&mut pose, Vec3::new(0.0, -3.0, 0.0));
+
+        for joint in pose.joints.iter() {
+            assert!(joint.rotation.angle_between(Quat::IDENTITY) <= FRAC_PI_4 + 0.001);
+        }
+    }
+
+    #[test]
+    fn twist_is_clamped() {
+        let limit = JointLimit {
+            swing: FRAC_PI_4,
+            min_twist: -0.1,
+            max_twist: 0.1,
+        };
+
+        let clamped = limit.clamp(Quat::from_rotation_y(1.0), Vec3::Y);
+
+        assert!(clamped.angle_between(Quat::from_rotation_y(0.1)) < 0.001);
+    }
+}
@@ -0,0 +1,65 @@
+use bevy::prelude::*;
+
+use crate::{retargeting::VrmRetargetingInitialized, HumanoidBones, VrmSet};
+
+use super::{IkTarget, IkTargets, Limb};
+
+/// Spawns a draggable sphere target for each limb of avatars without [`IkTargets`].
+pub struct RenIkDebugPlugin;
+
+impl Plugin for RenIkDebugPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, add_debug_targets.after(VrmSet::Retarget));
+    }
+}
+
+fn add_debug_targets(
+    mut commands: Commands,
+    skeletons: Query<
+        (Entity, &HumanoidBones),
+        (Without<IkTargets>, With<VrmRetargetingInitialized>),
+    >,
+    global_transforms: Query<&GlobalTransform>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, skeleton) in skeletons.iter() {
+        let mut targets = IkTargets::default();
+
+        for limb in Limb::ALL {
+            let [_, _, leaf] = limb.bones();
+
+            let Some(leaf) = skeleton.0.get(&leaf) else {
+                continue;
+            };
+
+            // Start the target where the limb already is, so the avatar keeps its pose.
+            let transform = match global_transforms.get(*leaf) {
+                Ok(global) => global.compute_transform(),
+                Err(_) => continue,
+            };
+
+            let id = commands
+                .spawn((
+                    PbrBundle {
+                        mesh: meshes.add(Sphere::new(0.05)),
+                        material: materials.add(StandardMaterial {
+                            base_color: Color::rgba(0.8, 0.8, 0.8, 1.0),
+                            alpha_mode: AlphaMode::Blend,
+                            unlit: true,
+                            ..default()
+                        }),
+                        transform,
+                        ..default()
+                    },
+                    bevy_mod_picking::PickableBundle::default(),
+                    bevy_transform_gizmo::GizmoTransformable,
+                ))
+                .id();
+
+            *targets.limb_mut(limb) = Some(IkTarget::entity(id));
+        }
+
+        commands.entity(entity).insert(targets);
+    }
+}
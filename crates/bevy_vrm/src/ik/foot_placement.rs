@@ -0,0 +1,288 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    ecs::system::{StaticSystemParam, SystemParam, SystemParamItem},
+    prelude::*,
+};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{retargeting::VrmRetargetingInitialized, HumanoidBones};
+
+use super::{rest_pose, BoneRest, IkSet, IkTargets, Limb};
+
+/// Where a ground ray hit, in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GroundHit {
+    pub position: Vec3,
+    pub normal: Vec3,
+}
+
+/// Finds the ground below the feet, implemented by the application.
+/// Lets a physics engine or heightmap be used without depending on it here.
+pub trait GroundQuery: Send + Sync + 'static {
+    /// Resources or queries needed to cast against the ground, such as a physics context.
+    type Param: SystemParam + 'static;
+
+    /// Casts a ray from `origin` along the normalized `direction`.
+    fn cast(
+        param: &SystemParamItem<'_, '_, Self::Param>,
+        origin: Vec3,
+        direction: Vec3,
+        max_distance: f32,
+    ) -> Option<GroundHit>;
+}
+
+/// Plants the feet of avatars with [`FootPlacement`] on the ground found by `G`.
+/// Requires [`RenIkPlugin`](super::RenIkPlugin).
+pub struct FootPlacementPlugin<G: GroundQuery>(PhantomData<G>);
+
+impl<G: GroundQuery> Default for FootPlacementPlugin<G> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<G: GroundQuery> Plugin for FootPlacementPlugin<G> {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, place_feet::<G>.in_set(IkSet::Feet));
+
+        #[cfg(feature = "reflect")]
+        app.register_type::<FootPlacement>();
+    }
+}
+
+/// Settings for planting the feet on the ground, see [`FootPlacementPlugin`].
+#[derive(Component, Clone, Debug)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct FootPlacement {
+    /// How far above a foot to look for ground, for stepping up.
+    pub step_up: f32,
+    /// How far below a foot to look for ground, for stepping down.
+    pub step_down: f32,
+    /// The furthest the hips are lowered so that a foot can reach the ground.
+    pub max_hip_drop: f32,
+    /// Turns the feet to lie flat on the ground.
+    pub align_to_normal: bool,
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    placed: [Option<Transform>; 2],
+    /// How far the hips are currently lowered, along the avatar's up.
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    hip_drop: f32,
+}
+
+impl Default for FootPlacement {
+    fn default() -> Self {
+        Self {
+            step_up: 0.5,
+            step_down: 0.5,
+            max_hip_drop: 0.4,
+            align_to_normal: true,
+            placed: [None; 2],
+            hip_drop: 0.0,
+        }
+    }
+}
+
+impl FootPlacement {
+    /// The world space pose of the foot on the ground this frame, if ground was found.
+    pub fn placed(&self, limb: Limb) -> Option<Transform> {
+        match limb {
+            Limb::LeftLeg => self.placed[0],
+            Limb::RightLeg => self.placed[1],
+            Limb::LeftArm | Limb::RightArm => None,
+        }
+    }
+}
+
+fn place_feet<G: GroundQuery>(
+    ground: StaticSystemParam<G::Param>,
+    mut skeletons: Query<
+        (
+            Entity,
+            &HumanoidBones,
+            Option<&IkTargets>,
+            &mut FootPlacement,
+            &GlobalTransform,
+        ),
+        With<VrmRetargetingInitialized>,
+    >,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    global_transforms: Query<&GlobalTransform>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    for (entity, skeleton, targets, mut placement, root_global_transform) in skeletons.iter_mut() {
+        let up = root_global_transform
+            .affine()
+            .transform_vector3(Vec3::Y)
+            .normalize();
+        // The animated feet are measured without the drop applied last frame.
+        let previous_drop = placement.hip_drop;
+        let mut hip_drop = 0.0f32;
+
+        for (index, (limb, bone)) in [
+            (Limb::LeftLeg, BoneName::LeftFoot),
+            (Limb::RightLeg, BoneName::RightFoot),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            placement.placed[index] = None;
+
+            let Some(foot) = skeleton.0.get(&bone) else {
+                continue;
+            };
+
+            // The ankle is this far above the sole, with the avatar root on the ground.
+            let Some(ankle_height) =
+                rest_pose(*foot, entity, &parents, &bone_rests).map(|rest| rest.translation.y)
+            else {
+                continue;
+            };
+
+            // Where the foot would be without ground, from its target or animation.
+            let target = targets.and_then(|targets| targets.limb(limb));
+            let desired = match target
+                .and_then(|target| target.resolve(&global_transforms, root_global_transform, *foot))
+            {
+                Some(desired) => desired,
+                None => match global_transforms.get(*foot) {
+                    Ok(global) => {
+                        let mut global = global.compute_transform();
+                        global.translation -= up * previous_drop;
+                        global
+                    }
+                    Err(_) => continue,
+                },
+            };
+
+            let origin = desired.translation - up * ankle_height + up * placement.step_up;
+            let max_distance = placement.step_up + placement.step_down;
+
+            let Some(hit) = G::cast(&*ground, origin, -up, max_distance) else {
+                continue;
+            };
+
+            let normal = hit.normal.try_normalize().unwrap_or(up);
+            let translation = hit.position + normal * ankle_height;
+            let rotation = if placement.align_to_normal {
+                Quat::from_rotation_arc(up, normal) * desired.rotation
+            } else {
+                desired.rotation
+            };
+
+            hip_drop = hip_drop.min((translation - desired.translation).dot(up));
+
+            placement.placed[index] = Some(
+                desired
+                    .with_translation(translation)
+                    .with_rotation(rotation),
+            );
+        }
+
+        let hip_drop = hip_drop.max(-placement.max_hip_drop);
+
+        if hip_drop == previous_drop {
+            continue;
+        }
+
+        // Lower the hips so the lower foot can reach the ground,
+        // or raise them back by what is no longer needed.
+        let Some(hips) = skeleton.0.get(&BoneName::Hips) else {
+            continue;
+        };
+
+        let parent = match parents
+            .get(*hips)
+            .and_then(|parent| global_transforms.get(parent.get()))
+        {
+            Ok(parent) => *parent,
+            Err(_) => *root_global_transform,
+        };
+
+        if let Ok(mut transform) = local_transforms.get_mut(*hips) {
+            let offset = up * (hip_drop - previous_drop);
+            transform.translation += parent.affine().inverse().transform_vector3(offset);
+            placement.hip_drop = hip_drop;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::test_utils::{bone, propagate, spawn_skeleton};
+
+    use super::*;
+
+    /// Height of the flat ground.
+    #[derive(Resource)]
+    struct Ground(f32);
+
+    struct Plane;
+
+    impl GroundQuery for Plane {
+        type Param = Res<'static, Ground>;
+
+        fn cast(
+            ground: &SystemParamItem<'_, '_, Self::Param>,
+            origin: Vec3,
+            direction: Vec3,
+            max_distance: f32,
+        ) -> Option<GroundHit> {
+            let distance = (ground.0 - origin.y) / direction.y;
+
+            (0.0..=max_distance).contains(&distance).then(|| GroundHit {
+                position: origin + direction * distance,
+                normal: Vec3::Y,
+            })
+        }
+    }
+
+    fn place(world: &mut World, root: Entity, ground: f32) -> [Vec3; 2] {
+        world.insert_resource(Ground(ground));
+        world.run_system_once(place_feet::<Plane>);
+        propagate(world);
+
+        let placement = world.get::<FootPlacement>(root).unwrap();
+        [Limb::LeftLeg, Limb::RightLeg].map(|limb| placement.placed(limb).unwrap().translation)
+    }
+
+    #[test]
+    fn feet_are_placed_on_the_ground() {
+        let mut world = World::new();
+        let root = spawn_skeleton(&mut world);
+        world.entity_mut(root).insert(FootPlacement::default());
+
+        let hips = bone(&world, root, BoneName::Hips);
+        let hips_height =
+            |world: &World| world.get::<GlobalTransform>(hips).unwrap().translation().y;
+
+        // Raised ground is reached by bending the knees, the hips stay.
+        let [left, right] = place(&mut world, root, 0.1);
+        assert!(left.distance(Vec3::new(0.1, 0.15, 0.0)) < 0.001);
+        assert!(right.distance(Vec3::new(-0.1, 0.15, 0.0)) < 0.001);
+        assert!((hips_height(&world) - 1.0).abs() < 0.001);
+
+        // Lower ground is reached by dropping the hips.
+        let [left, right] = place(&mut world, root, -0.2);
+        assert!(left.distance(Vec3::new(0.1, -0.15, 0.0)) < 0.001);
+        assert!(right.distance(Vec3::new(-0.1, -0.15, 0.0)) < 0.001);
+        assert!((hips_height(&world) - 0.8).abs() < 0.001);
+
+        // The drop is kept, not added again, while the ground stays.
+        place(&mut world, root, -0.2);
+        assert!((hips_height(&world) - 0.8).abs() < 0.001);
+
+        // The total drop is limited.
+        let [left, _] = place(&mut world, root, -0.45);
+        assert!(left.distance(Vec3::new(0.1, -0.4, 0.0)) < 0.001);
+        assert!((hips_height(&world) - 0.6).abs() < 0.001);
+
+        // The hips rise again with the ground.
+        let [left, _] = place(&mut world, root, 0.0);
+        assert!(left.distance(Vec3::new(0.1, 0.05, 0.0)) < 0.001);
+        assert!((hips_height(&world) - 1.0).abs() < 0.001);
+    }
+}
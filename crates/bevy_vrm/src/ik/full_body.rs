@@ -0,0 +1,424 @@
+//! Full-body pose from head and hand tracking, ported from RenIK.
+//!
+//! The hips are placed below the head and turned with its yaw, the spine is bent
+//! so the neck follows the head, and the feet step to stay under the hips.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use serde_vrm::vrm0::BoneName;
+
+use crate::{retargeting::VrmRetargetingInitialized, HumanoidBones};
+
+use super::{align_vectors, rest_pose, BoneRest, IkTarget, IkTargets, JointLimits};
+
+/// Bones bent to follow the head, from the hips up.
+const SPINE: [BoneName; 4] = [
+    BoneName::Spine,
+    BoneName::Chest,
+    BoneName::UpperChest,
+    BoneName::Neck,
+];
+
+/// Estimates the rest of the body from the head target in [`IkTargets`].
+/// A hips target, if set, is used instead of the estimated hips.
+#[derive(Component, Clone, Debug)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct FullBodyIk {
+    /// How far the hips move back as the head pitches forward, in meters per radian.
+    pub hip_lean: f32,
+    /// Share of the spine bend given to `Spine`, `Chest`, `UpperChest` and `Neck`.
+    /// Missing bones are skipped and the rest are normalized.
+    pub spine_weights: [f32; 4],
+    /// Sets the foot targets in [`IkTargets`] by stepping.
+    /// Turn off when the feet are tracked.
+    pub procedural_feet: bool,
+    /// Horizontal distance from a foot to where it should be that starts a step.
+    pub step_threshold: f32,
+    /// Yaw difference from a foot to where it should be that starts a step, in radians.
+    pub step_angle: f32,
+    /// Length of a step, in seconds.
+    pub step_duration: f32,
+    /// How high a foot is lifted mid-step.
+    pub step_height: f32,
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    feet: [FootState; 2],
+}
+
+impl Default for FullBodyIk {
+    fn default() -> Self {
+        Self {
+            hip_lean: 0.1,
+            spine_weights: [0.25, 0.3, 0.25, 0.2],
+            procedural_feet: true,
+            step_threshold: 0.25,
+            step_angle: PI / 4.0,
+            step_duration: 0.25,
+            step_height: 0.08,
+            feet: Default::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct FootState {
+    /// World space pose of the foot while on the ground.
+    planted: Option<Transform>,
+    step: Option<Step>,
+}
+
+#[derive(Clone, Debug)]
+struct Step {
+    from: Transform,
+    to: Transform,
+    elapsed: f32,
+}
+
+pub(super) fn solve_full_body(
+    time: Res<Time>,
+    mut skeletons: Query<
+        (
+            Entity,
+            &HumanoidBones,
+            &mut IkTargets,
+            &mut FullBodyIk,
+            Option<&JointLimits>,
+            &GlobalTransform,
+        ),
+        With<VrmRetargetingInitialized>,
+    >,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    global_transforms: Query<&GlobalTransform>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    for (entity, skeleton, mut targets, mut full_body, limits, root_global_transform) in
+        skeletons.iter_mut()
+    {
+        let (Some(hips), Some(head)) = (
+            skeleton.0.get(&BoneName::Hips),
+            skeleton.0.get(&BoneName::Head),
+        ) else {
+            continue;
+        };
+
+        let Some(head_target) = targets.head else {
+            continue;
+        };
+
+        let (Some(hips_rest), Some(head_rest)) = (
+            rest_pose(*hips, entity, &parents, &bone_rests),
+            rest_pose(*head, entity, &parents, &bone_rests),
+        ) else {
+            continue;
+        };
+
+        let root = root_global_transform.compute_transform();
+        let root_inverse = root_global_transform.compute_matrix().inverse();
+        let to_avatar =
+            |world: Transform| Transform::from_matrix(root_inverse * world.compute_matrix());
+
+        // Everything below is in avatar space, with +Y up and the avatar facing +Z.
+        let head_pose = match head_target.resolve(&global_transforms, root_global_transform, *head)
+        {
+            Some(pose) => to_avatar(pose),
+            None => continue,
+        };
+
+        let head_delta = head_pose.rotation * head_rest.rotation.inverse();
+        let yaw = yaw(head_delta);
+        let pitch = -(yaw.inverse() * head_delta * Vec3::Z)
+            .y
+            .clamp(-1.0, 1.0)
+            .asin();
+
+        let hips_pose = match targets
+            .hips
+            .and_then(|target| target.resolve(&global_transforms, root_global_transform, *hips))
+        {
+            Some(pose) => to_avatar(pose),
+            None => {
+                let mut translation = head_pose.translation
+                    - yaw * (head_rest.translation - hips_rest.translation)
+                    - yaw * Vec3::Z * full_body.hip_lean * pitch.max(0.0);
+
+                // The legs cannot stretch past their rest length.
+                translation.y = translation.y.min(hips_rest.translation.y);
+
+                Transform {
+                    translation,
+                    rotation: yaw * hips_rest.rotation,
+                    scale: hips_rest.scale,
+                }
+            }
+        };
+
+        // Place the hips.
+        let hips_parent = match parents
+            .get(*hips)
+            .and_then(|parent| global_transforms.get(parent.get()))
+        {
+            Ok(parent) => root_inverse * parent.compute_matrix(),
+            Err(_) => Mat4::IDENTITY,
+        };
+
+        if let Ok(mut transform) = local_transforms.get_mut(*hips) {
+            let local = Transform::from_matrix(hips_parent.inverse() * hips_pose.compute_matrix());
+            transform.translation = local.translation;
+            transform.rotation = local.rotation;
+        }
+
+        // Bend the spine so the neck points from the hips towards the head.
+        let rest_direction =
+            hips_rest.rotation.inverse() * (head_rest.translation - hips_rest.translation);
+        let target_direction =
+            hips_pose.rotation.inverse() * (head_pose.translation - hips_pose.translation);
+        let bend = align_vectors(rest_direction, target_direction, 1.0);
+
+        let spine = SPINE
+            .iter()
+            .zip(full_body.spine_weights)
+            .filter_map(|(bone, weight)| Some((bone, *skeleton.0.get(bone)?, weight)))
+            .collect::<Vec<_>>();
+        let total_weight = spine.iter().map(|(_, _, weight)| weight).sum::<f32>();
+
+        // Rotation of the last placed bone, relative to the avatar.
+        let mut parent_rotation = hips_pose.rotation;
+        let mut parent_rest = hips_rest.rotation;
+
+        for (i, (name, bone, weight)) in spine.iter().enumerate() {
+            let (Some(rest), Ok(local_rest)) = (
+                rest_pose(*bone, entity, &parents, &bone_rests),
+                bone_rests.get(*bone),
+            ) else {
+                continue;
+            };
+
+            let part = if total_weight > 0.0 {
+                Quat::IDENTITY.slerp(bend, weight / total_weight)
+            } else {
+                Quat::IDENTITY
+            };
+
+            // Rest rotation relative to the hips, the bend is applied in that frame.
+            let relative = hips_rest.rotation.inverse() * rest.rotation;
+            let mut rotation = local_rest.0.rotation * relative.inverse() * part * relative;
+
+            if let Some(limits) = limits {
+                // The bone points towards the next one in the spine, or the head.
+                let child = spine.get(i + 1).map(|(_, bone, _)| *bone).unwrap_or(*head);
+                let axis = match bone_rests.get(child) {
+                    Ok(child) => child.0.translation,
+                    Err(_) => Vec3::Y,
+                };
+
+                rotation = limits.clamp(name, rotation, local_rest.0.rotation, axis);
+            }
+
+            if let Ok(mut transform) = local_transforms.get_mut(*bone) {
+                transform.rotation = rotation;
+            }
+
+            // Bones between the spine bones keep their rest rotation.
+            let between = parent_rest.inverse() * rest.rotation * local_rest.0.rotation.inverse();
+            parent_rotation = parent_rotation * between * rotation;
+            parent_rest = rest.rotation;
+        }
+
+        // Turn the head to the target.
+        if let Ok(mut transform) = local_transforms.get_mut(*head) {
+            let rotation = parent_rotation.inverse() * head_pose.rotation;
+
+            transform.rotation = match (limits, bone_rests.get(*head)) {
+                (Some(limits), Ok(rest)) => {
+                    limits.clamp(&BoneName::Head, rotation, rest.0.rotation, Vec3::Y)
+                }
+                _ => rotation,
+            };
+        }
+
+        if !full_body.procedural_feet {
+            continue;
+        }
+
+        let delta = time.delta_seconds();
+        let up = root.rotation * Vec3::Y;
+        let step_angle = full_body.step_angle;
+        let step_duration = full_body.step_duration;
+        let step_height = full_body.step_height;
+        let step_threshold = full_body.step_threshold;
+
+        for (index, foot) in [BoneName::LeftFoot, BoneName::RightFoot].iter().enumerate() {
+            let Some(foot_rest) = skeleton
+                .0
+                .get(foot)
+                .and_then(|foot| rest_pose(*foot, entity, &parents, &bone_rests))
+            else {
+                continue;
+            };
+
+            // Where the foot would be if the avatar stood still below its hips.
+            let offset = foot_rest.translation - hips_rest.translation;
+            let ideal = Transform {
+                translation: Vec3::new(hips_pose.translation.x, 0.0, hips_pose.translation.z)
+                    + yaw * Vec3::new(offset.x, 0.0, offset.z)
+                    + Vec3::Y * foot_rest.translation.y,
+                rotation: yaw * foot_rest.rotation,
+                scale: foot_rest.scale,
+            };
+            let ideal = root * ideal;
+
+            let other_stepping = full_body.feet[1 - index].step.is_some();
+            let state = &mut full_body.feet[index];
+
+            let pose = match state.step.as_mut() {
+                Some(step) => {
+                    step.elapsed += delta;
+                    let t = (step.elapsed / step_duration).clamp(0.0, 1.0);
+
+                    let lift = (t * PI).sin() * step_height;
+                    let pose = Transform {
+                        translation: step.from.translation.lerp(step.to.translation, t) + up * lift,
+                        rotation: step.from.rotation.slerp(step.to.rotation, t),
+                        scale: step.to.scale,
+                    };
+
+                    if t >= 1.0 {
+                        state.planted = Some(step.to);
+                        state.step = None;
+                    }
+
+                    pose
+                }
+                None => {
+                    let planted = *state.planted.get_or_insert(ideal);
+
+                    let horizontal =
+                        (ideal.translation - planted.translation).reject_from_normalized(up);
+                    let angle = planted.rotation.angle_between(ideal.rotation);
+
+                    // Only one foot steps at a time.
+                    if !other_stepping
+                        && (horizontal.length() > step_threshold || angle > step_angle)
+                    {
+                        state.step = Some(Step {
+                            from: planted,
+                            to: ideal,
+                            elapsed: 0.0,
+                        });
+                    }
+
+                    planted
+                }
+            };
+
+            let target = Some(IkTarget::transform(pose));
+
+            match foot {
+                BoneName::LeftFoot => targets.left_foot = target,
+                _ => targets.right_foot = target,
+            }
+        }
+    }
+}
+
+/// The rotation of `rotation` around the vertical axis.
+fn yaw(rotation: Quat) -> Quat {
+    let twist = Quat::from_xyzw(0.0, rotation.y, 0.0, rotation.w);
+
+    if twist.length_squared() <= f32::EPSILON {
+        Quat::IDENTITY
+    } else {
+        twist.normalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{
+        ik::IkTargetSource,
+        test_utils::{bone, propagate, spawn_skeleton},
+    };
+
+    fn solve(world: &mut World, head: Vec3) {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(0.1));
+
+        let root = world
+            .query_filtered::<Entity, With<FullBodyIk>>()
+            .single(world);
+        world.get_mut::<IkTargets>(root).unwrap().head =
+            Some(IkTarget::transform(Transform::from_translation(head)));
+
+        world.run_system_once(solve_full_body);
+        propagate(world);
+    }
+
+    fn foot_target(world: &World, root: Entity, index: usize) -> Vec3 {
+        let targets = world.get::<IkTargets>(root).unwrap();
+        let target = [&targets.left_foot, &targets.right_foot][index].unwrap();
+
+        match target.source {
+            IkTargetSource::Transform(transform) => transform.translation,
+            IkTargetSource::Entity(_) => unreachable!(),
+        }
+    }
+
+    fn setup() -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+
+        let root = spawn_skeleton(&mut world);
+        world
+            .entity_mut(root)
+            .insert((IkTargets::default(), FullBodyIk::default()));
+
+        (world, root)
+    }
+
+    #[test]
+    fn hips_follow_a_lowered_head() {
+        let (mut world, root) = setup();
+
+        solve(&mut world, Vec3::new(0.0, 1.35, 0.0));
+
+        let hips = bone(&world, root, BoneName::Hips);
+        let hips = world.get::<GlobalTransform>(hips).unwrap().translation();
+        assert!(hips.distance(Vec3::new(0.0, 0.8, 0.0)) < 0.001);
+
+        let head = bone(&world, root, BoneName::Head);
+        let head = world.get::<GlobalTransform>(head).unwrap().translation();
+        assert!(head.distance(Vec3::new(0.0, 1.35, 0.0)) < 0.001);
+    }
+
+    #[test]
+    fn feet_step_after_the_hips_move_past_the_threshold() {
+        let (mut world, root) = setup();
+        let head = Vec3::new(0.0, 1.55, 0.0);
+
+        solve(&mut world, head);
+        assert!(foot_target(&world, root, 0).distance(Vec3::new(0.1, 0.05, 0.0)) < 0.001);
+
+        // Within the threshold the feet stay planted.
+        solve(&mut world, head + Vec3::X * 0.2);
+        assert!(foot_target(&world, root, 0).distance(Vec3::new(0.1, 0.05, 0.0)) < 0.001);
+
+        // Past it the left foot steps first, then the right.
+        solve(&mut world, head + Vec3::X * 0.4);
+        solve(&mut world, head + Vec3::X * 0.4);
+        assert!(foot_target(&world, root, 0).y > 0.05);
+        assert!(foot_target(&world, root, 1).distance(Vec3::new(-0.1, 0.05, 0.0)) < 0.001);
+
+        for _ in 0..8 {
+            solve(&mut world, head + Vec3::X * 0.4);
+        }
+        assert!(foot_target(&world, root, 0).distance(Vec3::new(0.5, 0.05, 0.0)) < 0.001);
+        assert!(foot_target(&world, root, 1).distance(Vec3::new(0.3, 0.05, 0.0)) < 0.001);
+    }
+}
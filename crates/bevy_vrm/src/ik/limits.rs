@@ -0,0 +1,178 @@
+//! Per-bone joint limits shared by every solver.
+
+use bevy::{prelude::*, utils::HashMap};
+use gltf_kun_vrm::vrm0::weight::Humanoid;
+use serde_vrm::vrm0::BoneName;
+
+use crate::{loader::Vrm, HumanoidBones};
+
+use super::JointLimit;
+
+/// Twist distribution used by VRM exporters when a value is missing.
+const DEFAULT_TWIST: f32 = 0.5;
+
+/// How far each humanoid bone may rotate away from its rest rotation.
+/// Inserted on avatars by [`RenIkPlugin`](super::RenIkPlugin), from the VRM humanoid settings.
+/// Bones without a limit are free.
+#[derive(Component, Clone, Debug)]
+pub struct JointLimits(pub HashMap<BoneName, JointLimit>);
+
+impl Default for JointLimits {
+    fn default() -> Self {
+        Self::from_humanoid(&Humanoid::default())
+    }
+}
+
+impl JointLimits {
+    /// Limits for a typical human, with the arm and leg twist ranges
+    /// scaled by the humanoid's twist settings.
+    pub fn from_humanoid(humanoid: &Humanoid) -> Self {
+        let twist = |value: Option<f32>, degrees: f32| {
+            let scale = value.unwrap_or(DEFAULT_TWIST).max(0.0) / DEFAULT_TWIST;
+            (degrees.to_radians() * scale).min(std::f32::consts::PI)
+        };
+
+        let upper_arm = limit(135.0, twist(humanoid.upper_arm_twist, 90.0));
+        let lower_arm = limit(150.0, twist(humanoid.lower_arm_twist, 120.0));
+        let upper_leg = limit(120.0, twist(humanoid.upper_leg_twist, 60.0));
+        let lower_leg = limit(150.0, twist(humanoid.lower_leg_twist, 30.0));
+
+        let spine = degrees(30.0, 30.0);
+        let shoulder = degrees(30.0, 10.0);
+        let hand = degrees(80.0, 30.0);
+        let foot = degrees(50.0, 20.0);
+        let toes = degrees(40.0, 5.0);
+        let thumb = degrees(70.0, 30.0);
+        let finger = degrees(90.0, 10.0);
+
+        let mut limits = HashMap::new();
+
+        for (bone, limit) in [
+            (BoneName::Spine, spine),
+            (BoneName::Chest, spine),
+            (BoneName::UpperChest, spine),
+            (BoneName::Neck, degrees(40.0, 45.0)),
+            (BoneName::Head, degrees(45.0, 60.0)),
+            (BoneName::Jaw, degrees(30.0, 5.0)),
+            (BoneName::LeftEye, degrees(30.0, 0.0)),
+            (BoneName::RightEye, degrees(30.0, 0.0)),
+            (BoneName::LeftShoulder, shoulder),
+            (BoneName::RightShoulder, shoulder),
+            (BoneName::LeftUpperArm, upper_arm),
+            (BoneName::RightUpperArm, upper_arm),
+            (BoneName::LeftLowerArm, lower_arm),
+            (BoneName::RightLowerArm, lower_arm),
+            (BoneName::LeftHand, hand),
+            (BoneName::RightHand, hand),
+            (BoneName::LeftUpperLeg, upper_leg),
+            (BoneName::RightUpperLeg, upper_leg),
+            (BoneName::LeftLowerLeg, lower_leg),
+            (BoneName::RightLowerLeg, lower_leg),
+            (BoneName::LeftFoot, foot),
+            (BoneName::RightFoot, foot),
+            (BoneName::LeftToes, toes),
+            (BoneName::RightToes, toes),
+            (BoneName::LeftThumbProximal, thumb),
+            (BoneName::LeftThumbIntermediate, thumb),
+            (BoneName::LeftThumbDistal, thumb),
+            (BoneName::RightThumbProximal, thumb),
+            (BoneName::RightThumbIntermediate, thumb),
+            (BoneName::RightThumbDistal, thumb),
+            (BoneName::LeftIndexProximal, finger),
+            (BoneName::LeftIndexIntermediate, finger),
+            (BoneName::LeftIndexDistal, finger),
+            (BoneName::LeftMiddleProximal, finger),
+            (BoneName::LeftMiddleIntermediate, finger),
+            (BoneName::LeftMiddleDistal, finger),
+            (BoneName::LeftRingProximal, finger),
+            (BoneName::LeftRingIntermediate, finger),
+            (BoneName::LeftRingDistal, finger),
+            (BoneName::LeftLittleProximal, finger),
+            (BoneName::LeftLittleIntermediate, finger),
+            (BoneName::LeftLittleDistal, finger),
+            (BoneName::RightIndexProximal, finger),
+            (BoneName::RightIndexIntermediate, finger),
+            (BoneName::RightIndexDistal, finger),
+            (BoneName::RightMiddleProximal, finger),
+            (BoneName::RightMiddleIntermediate, finger),
+            (BoneName::RightMiddleDistal, finger),
+            (BoneName::RightRingProximal, finger),
+            (BoneName::RightRingIntermediate, finger),
+            (BoneName::RightRingDistal, finger),
+            (BoneName::RightLittleProximal, finger),
+            (BoneName::RightLittleIntermediate, finger),
+            (BoneName::RightLittleDistal, finger),
+        ] {
+            limits.insert(bone, limit);
+        }
+
+        Self(limits)
+    }
+
+    pub fn get(&self, bone: &BoneName) -> Option<JointLimit> {
+        self.0.get(bone).copied()
+    }
+
+    /// Clamps the local `rotation` of `bone`, see [`JointLimit::clamp_local`].
+    /// Returns `rotation` unchanged if the bone has no limit.
+    pub fn clamp(&self, bone: &BoneName, rotation: Quat, rest: Quat, axis: Vec3) -> Quat {
+        match self.0.get(bone) {
+            Some(limit) => limit.clamp_local(rotation, rest, axis),
+            None => rotation,
+        }
+    }
+}
+
+/// A limit with a symmetric twist range, in radians.
+fn limit(swing_degrees: f32, twist: f32) -> JointLimit {
+    JointLimit {
+        swing: swing_degrees.to_radians(),
+        min_twist: -twist,
+        max_twist: twist,
+    }
+}
+
+fn degrees(swing: f32, twist: f32) -> JointLimit {
+    limit(swing, twist.to_radians())
+}
+
+pub(super) fn add_joint_limits(
+    mut commands: Commands,
+    avatars: Query<(Entity, &Handle<Vrm>), (With<HumanoidBones>, Without<JointLimits>)>,
+    vrms: Res<Assets<Vrm>>,
+) {
+    for (entity, handle) in avatars.iter() {
+        let Some(vrm) = vrms.get(handle) else {
+            continue;
+        };
+
+        let humanoid = vrm.humanoid().unwrap_or_default();
+
+        commands
+            .entity(entity)
+            .insert(JointLimits::from_humanoid(&humanoid));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twist_follows_humanoid_settings() {
+        let limits = JointLimits::from_humanoid(&Humanoid {
+            upper_arm_twist: Some(1.0),
+            lower_leg_twist: Some(0.0),
+            ..default()
+        });
+
+        let upper_arm = limits.get(&BoneName::LeftUpperArm).unwrap();
+        assert!((upper_arm.max_twist - 180.0f32.to_radians()).abs() < 0.001);
+
+        let lower_leg = limits.get(&BoneName::RightLowerLeg).unwrap();
+        assert_eq!(lower_leg.max_twist, 0.0);
+
+        let lower_arm = limits.get(&BoneName::LeftLowerArm).unwrap();
+        assert!((lower_arm.max_twist - 120.0f32.to_radians()).abs() < 0.001);
+    }
+}
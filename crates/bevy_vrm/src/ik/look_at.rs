@@ -0,0 +1,252 @@
+//! Turning the spine and head towards a point, separate from the eyes.
+
+use bevy::prelude::*;
+use serde_vrm::vrm0::BoneName;
+
+use crate::{retargeting::VrmRetargetingInitialized, HumanoidBones};
+
+use super::{rest_pose, BoneRest, IkTargetSource, JointLimits};
+
+/// A bone turned by [`HeadLookAt`].
+#[derive(Clone, Debug)]
+pub struct LookAtBone {
+    pub bone: BoneName,
+    /// Share of the rotation given to this bone, normalized over all bones.
+    pub weight: f32,
+    /// Largest angle this bone turns by, in radians.
+    pub max_angle: f32,
+}
+
+impl LookAtBone {
+    pub fn new(bone: BoneName, weight: f32, max_angle: f32) -> Self {
+        Self {
+            bone,
+            weight,
+            max_angle,
+        }
+    }
+}
+
+/// Turns the avatar's face towards a target, spreading the rotation from the spine up to the head.
+/// Applied on top of animation and [`FullBodyIk`](super::FullBodyIk), before the limbs are solved.
+#[derive(Component, Clone, Debug)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+pub struct HeadLookAt {
+    /// Where to look, using the translation of the source.
+    /// Without a target the bones smoothly return to their animated pose.
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    pub target: Option<IkTargetSource>,
+    /// How far to turn towards the target, from 0 to 1.
+    pub weight: f32,
+    /// Bones from the hips up, each turned by its share of the rotation.
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    pub bones: Vec<LookAtBone>,
+    /// How quickly the rotation follows the target, per second. Zero follows instantly.
+    pub smoothing: f32,
+    /// Rotation currently applied, relative to the avatar.
+    #[cfg_attr(feature = "reflect", reflect(ignore))]
+    current: Quat,
+}
+
+impl Default for HeadLookAt {
+    fn default() -> Self {
+        Self {
+            target: None,
+            weight: 1.0,
+            bones: vec![
+                LookAtBone::new(BoneName::Spine, 0.1, 20.0f32.to_radians()),
+                LookAtBone::new(BoneName::Chest, 0.2, 25.0f32.to_radians()),
+                LookAtBone::new(BoneName::Neck, 0.3, 40.0f32.to_radians()),
+                LookAtBone::new(BoneName::Head, 0.4, 50.0f32.to_radians()),
+            ],
+            smoothing: 8.0,
+            current: Quat::IDENTITY,
+        }
+    }
+}
+
+impl HeadLookAt {
+    pub fn new(target: IkTargetSource) -> Self {
+        Self {
+            target: Some(target),
+            ..default()
+        }
+    }
+}
+
+pub(super) fn look_at(
+    time: Res<Time>,
+    mut skeletons: Query<
+        (
+            Entity,
+            &HumanoidBones,
+            &mut HeadLookAt,
+            Option<&JointLimits>,
+            &GlobalTransform,
+        ),
+        With<VrmRetargetingInitialized>,
+    >,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    global_transforms: Query<&GlobalTransform>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    for (entity, skeleton, mut look_at, limits, root_global_transform) in skeletons.iter_mut() {
+        let Some(head) = skeleton.0.get(&BoneName::Head) else {
+            continue;
+        };
+
+        let (Some(head_rest), Ok(head_global)) = (
+            rest_pose(*head, entity, &parents, &bone_rests),
+            global_transforms.get(*head),
+        ) else {
+            continue;
+        };
+
+        let root_rotation = root_global_transform.compute_transform().rotation;
+        let head_global = head_global.compute_transform();
+
+        let target = match look_at.target {
+            Some(IkTargetSource::Entity(target)) => global_transforms
+                .get(target)
+                .ok()
+                .map(|target| target.translation()),
+            Some(IkTargetSource::Transform(target)) => Some(target.translation),
+            None => None,
+        };
+
+        // The face points along +Z of the avatar at rest.
+        let forward = head_global.rotation * (head_rest.rotation.inverse() * Vec3::Z);
+
+        let desired =
+            match target.and_then(|target| (target - head_global.translation).try_normalize()) {
+                Some(direction) => {
+                    let rotation = Quat::from_rotation_arc(forward, direction);
+                    let rotation = root_rotation.inverse() * rotation * root_rotation;
+                    Quat::IDENTITY.slerp(rotation, look_at.weight.clamp(0.0, 1.0))
+                }
+                None => Quat::IDENTITY,
+            };
+
+        look_at.current = if look_at.smoothing > 0.0 {
+            let t = 1.0 - (-look_at.smoothing * time.delta_seconds()).exp();
+            look_at.current.slerp(desired, t)
+        } else {
+            desired
+        };
+
+        let total_weight = look_at
+            .bones
+            .iter()
+            .map(|bone| bone.weight.max(0.0))
+            .sum::<f32>();
+
+        if total_weight <= 0.0 {
+            continue;
+        }
+
+        // World rotation applied so far by the bones above, which also turns their children.
+        let mut applied = Quat::IDENTITY;
+
+        for bone in look_at.bones.iter() {
+            let Some(entity) = skeleton.0.get(&bone.bone) else {
+                continue;
+            };
+
+            let (Ok(global), Ok(parent)) = (
+                global_transforms.get(*entity),
+                parents
+                    .get(*entity)
+                    .and_then(|parent| global_transforms.get(parent.get())),
+            ) else {
+                continue;
+            };
+
+            let mut part =
+                Quat::IDENTITY.slerp(look_at.current, bone.weight.max(0.0) / total_weight);
+
+            let (axis, angle) = part.to_axis_angle();
+            if angle > bone.max_angle {
+                part = Quat::from_axis_angle(axis, bone.max_angle);
+            }
+
+            let part = root_rotation * part * root_rotation.inverse();
+
+            let global = global.compute_transform().rotation;
+            let parent = parent.compute_transform().rotation;
+
+            // The parent was already turned by `applied`, add this part on top.
+            let mut rotation = parent.inverse() * applied.inverse() * part * applied * global;
+
+            if let (Some(limits), Ok(rest)) = (limits, bone_rests.get(*entity)) {
+                rotation = limits.clamp(&bone.bone, rotation, rest.0.rotation, Vec3::Y);
+            }
+
+            if let Ok(mut transform) = local_transforms.get_mut(*entity) {
+                transform.rotation = rotation;
+            }
+
+            applied = applied * parent * rotation * global.inverse();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use crate::test_utils::{bone, spawn_skeleton};
+
+    use super::*;
+
+    const BONES: [BoneName; 4] = [
+        BoneName::Spine,
+        BoneName::Chest,
+        BoneName::Neck,
+        BoneName::Head,
+    ];
+
+    /// Looks towards `yaw` degrees to the side and returns how far each bone turned, in degrees.
+    fn look(yaw: f32) -> [f32; 4] {
+        let mut world = World::new();
+        world.init_resource::<Time>();
+
+        let root = spawn_skeleton(&mut world);
+        let direction = Quat::from_rotation_y(yaw.to_radians()) * Vec3::Z;
+        let target = Transform::from_translation(Vec3::new(0.0, 1.55, 0.0) + direction);
+
+        world.entity_mut(root).insert(HeadLookAt {
+            smoothing: 0.0,
+            ..HeadLookAt::new(IkTargetSource::Transform(target))
+        });
+
+        world.run_system_once(look_at);
+
+        BONES.map(|name| {
+            let transform = world.get::<Transform>(bone(&world, root, name)).unwrap();
+            transform
+                .rotation
+                .angle_between(Quat::IDENTITY)
+                .to_degrees()
+        })
+    }
+
+    #[test]
+    fn rotation_is_split_by_weight() {
+        let angles = look(60.0);
+
+        for (angle, expected) in angles.into_iter().zip([6.0, 12.0, 18.0, 24.0]) {
+            assert!((angle - expected).abs() < 0.01, "{angles:?}");
+        }
+    }
+
+    #[test]
+    fn bones_are_clamped_to_their_max_angle() {
+        let angles = look(150.0);
+
+        // Spine is within its 20 degrees, the others are clamped.
+        for (angle, expected) in angles.into_iter().zip([15.0, 25.0, 40.0, 50.0]) {
+            assert!((angle - expected).abs() < 0.01, "{angles:?}");
+        }
+    }
+}
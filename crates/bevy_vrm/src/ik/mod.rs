@@ -1,63 +1,152 @@
 use bevy::app::App;
 use std::f32::consts::{PI, TAU};
 
-use crate::HumanoidBones;
-use bevy::math::EulerRot::XYZ;
+use crate::{HumanoidBones, VrmSet};
+use bevy::animation::animation_player;
 use bevy::prelude::*;
-use bevy_inspector_egui::prelude::ReflectInspectorOptions;
-use bevy_inspector_egui::InspectorOptions;
+use bevy::transform::systems::{propagate_transforms, sync_simple_transforms};
+use bevy::transform::TransformSystem;
+#[cfg(feature = "inspector")]
+use bevy_inspector_egui::{prelude::ReflectInspectorOptions, InspectorOptions};
 use serde_vrm::vrm0::BoneName;
 
 use crate::retargeting::{RunBoneRestEvent, VrmRetargetingInitialized};
 
+mod chain;
+#[cfg(feature = "debug-ik")]
+mod debug;
+mod foot_placement;
+mod full_body;
+mod limits;
+mod look_at;
+mod targets;
+
+pub use chain::{
+    humanoid_chain, Ccd, ChainJoint, Fabrik, IkChain, IkChainPose, IkSolver, JointLimit,
+};
+#[cfg(feature = "debug-ik")]
+pub use debug::RenIkDebugPlugin;
+pub use foot_placement::{FootPlacement, FootPlacementPlugin, GroundHit, GroundQuery};
+pub use full_body::FullBodyIk;
+pub use limits::JointLimits;
+pub use look_at::{HeadLookAt, LookAtBone};
+pub use targets::{IkTarget, IkTargetSource, IkTargets};
+
 pub struct RenIkPlugin;
 
 impl Plugin for RenIkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, perform_hand_left_ik);
         app.add_systems(PreUpdate, add_bone_rest);
-        app.add_systems(Update, add_target);
-        //app.add_systems(Update, update_test);
+        app.add_systems(Update, limits::add_joint_limits.after(VrmSet::Bones));
+        // Solved on top of the animated pose, before it is propagated.
+        app.configure_sets(
+            PostUpdate,
+            (IkSet::Body, IkSet::LookAt, IkSet::Feet, IkSet::Limbs)
+                .chain()
+                .after(animation_player)
+                .before(TransformSystem::TransformPropagate),
+        );
+        app.add_systems(
+            PostUpdate,
+            (
+                (full_body::solve_full_body, apply_root_targets)
+                    .chain()
+                    .in_set(IkSet::Body),
+                (
+                    propagate_transforms,
+                    sync_simple_transforms,
+                    look_at::look_at,
+                )
+                    .chain()
+                    .in_set(IkSet::LookAt),
+                (
+                    // Limbs are solved from the updated spine.
+                    propagate_transforms,
+                    sync_simple_transforms,
+                    perform_limb_ik,
+                    chain::solve_chains,
+                )
+                    .chain()
+                    .in_set(IkSet::Limbs),
+            ),
+        );
+
+        #[cfg(feature = "reflect")]
+        app.register_type::<RenikLimb>()
+            .register_type::<FullBodyIk>()
+            .register_type::<HeadLookAt>();
     }
 }
 
-#[derive(Component)]
-struct DrawableTarget;
+/// Stages of the [`RenIkPlugin`] solve, run in order in the [`PostUpdate`] schedule,
+/// after animations are applied and before transforms are propagated.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IkSet {
+    /// Places the hips, spine and head.
+    Body,
+    /// Turns the spine and head towards a point, see [`HeadLookAt`].
+    LookAt,
+    /// Adjusts the hips and foot targets to the ground, see [`FootPlacementPlugin`].
+    Feet,
+    /// Solves the arms and legs, then any [`IkChain`]s, towards their targets.
+    Limbs,
+}
 
-fn add_target(
-    mut commands: Commands,
-    skeletons: Query<(Entity, &HumanoidBones), (Without<Target>, With<VrmRetargetingInitialized>)>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    for (entity, _) in skeletons.iter() {
-        let id = commands
-            .spawn((
-                PbrBundle {
-                    mesh: meshes.add(Sphere::new(0.05)),
-                    material: materials.add(StandardMaterial {
-                        base_color: Color::rgba(0.8, 0.8, 0.8, 1.0),
-                        alpha_mode: AlphaMode::Blend,
-                        unlit: true,
-                        ..default()
-                    }),
-                    transform: Transform::from_scale(Vec3::splat(1.0))
-                        .with_translation(Vec3::new(0.136, 1.015, 0.056)),
-                    ..Default::default()
-                },
-                bevy_mod_picking::PickableBundle::default(),
-                bevy_transform_gizmo::GizmoTransformable,
-                DrawableTarget,
-            ))
-            .id();
-        commands.entity(entity).insert(Target { left_hand: id });
-        commands.entity(entity).insert(RenikLimb::default());
-    }
+/// The limbs solved by [`RenIkPlugin`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Limb {
+    LeftArm,
+    RightArm,
+    LeftLeg,
+    RightLeg,
 }
 
-#[derive(Component, Clone, Copy)]
-pub struct Target {
-    pub left_hand: Entity,
+impl Limb {
+    pub const ALL: [Limb; 4] = [Limb::LeftArm, Limb::RightArm, Limb::LeftLeg, Limb::RightLeg];
+
+    /// The upper, lower and leaf bones of the limb.
+    pub fn bones(&self) -> [BoneName; 3] {
+        match self {
+            Limb::LeftArm => [
+                BoneName::LeftUpperArm,
+                BoneName::LeftLowerArm,
+                BoneName::LeftHand,
+            ],
+            Limb::RightArm => [
+                BoneName::RightUpperArm,
+                BoneName::RightLowerArm,
+                BoneName::RightHand,
+            ],
+            Limb::LeftLeg => [
+                BoneName::LeftUpperLeg,
+                BoneName::LeftLowerLeg,
+                BoneName::LeftFoot,
+            ],
+            Limb::RightLeg => [
+                BoneName::RightUpperLeg,
+                BoneName::RightLowerLeg,
+                BoneName::RightFoot,
+            ],
+        }
+    }
+
+    /// The shoulder bone rotated towards the target before solving, for arms.
+    pub fn shoulder(&self) -> Option<BoneName> {
+        match self {
+            Limb::LeftArm => Some(BoneName::LeftShoulder),
+            Limb::RightArm => Some(BoneName::RightShoulder),
+            Limb::LeftLeg | Limb::RightLeg => None,
+        }
+    }
+
+    fn default_settings(&self) -> RenikLimb {
+        match self {
+            Limb::LeftArm => RenikLimb::left_arm(),
+            Limb::RightArm => RenikLimb::right_arm(),
+            Limb::LeftLeg => RenikLimb::left_leg(),
+            Limb::RightLeg => RenikLimb::right_leg(),
+        }
+    }
 }
 
 #[derive(Component)]
@@ -65,135 +154,244 @@ pub struct BoneRest(pub Transform);
 
 fn add_bone_rest(
     mut commands: Commands,
-    query: Query<(Entity, &Transform), Without<BoneRest>>,
+    query: Query<&Transform, Without<BoneRest>>,
+    children: Query<&Children>,
     mut event_reader: EventReader<RunBoneRestEvent>,
 ) {
-    for _e in event_reader.read() {
-        println!("setting event rest");
-        for (entity, transform) in query.iter() {
-            commands.entity(entity).insert(BoneRest(*transform));
+    for RunBoneRestEvent(avatar) in event_reader.read() {
+        for entity in std::iter::once(*avatar).chain(children.iter_descendants(*avatar)) {
+            if let Ok(transform) = query.get(entity) {
+                commands.entity(entity).insert(BoneRest(*transform));
+            }
         }
     }
 }
-const LEFT_SHOULDER_OFFSET: Quat = Quat::IDENTITY;
+
+/// Rest pose of `entity` relative to `root`, from the [`BoneRest`] of it and its ancestors.
+pub(crate) fn rest_pose(
+    entity: Entity,
+    root: Entity,
+    parents: &Query<&Parent>,
+    bone_rests: &Query<&BoneRest>,
+) -> Option<Transform> {
+    let mut pose = bone_rests.get(entity).ok()?.0;
+    let mut current = entity;
+
+    loop {
+        let parent = parents.get(current).ok()?.get();
+
+        if parent == root {
+            return Some(pose);
+        }
+
+        pose = bone_rests.get(parent).ok()?.0 * pose;
+        current = parent;
+    }
+}
 
 const ARM_SHOULDER_INFLUENCE: f32 = 0.25;
 
-fn perform_hand_left_ik(
-    mut event_reader: EventReader<RunBoneRestEvent>,
-    mut run: Local<bool>,
+/// Moves the hips and turns the head towards their targets.
+/// Avatars with [`FullBodyIk`] place these themselves.
+fn apply_root_targets(
     skeletons: Query<
-        (&HumanoidBones, &Target, &GlobalTransform, &RenikLimb),
-        With<VrmRetargetingInitialized>,
+        (
+            &HumanoidBones,
+            &IkTargets,
+            Option<&JointLimits>,
+            &GlobalTransform,
+        ),
+        (With<VrmRetargetingInitialized>, Without<FullBodyIk>),
     >,
     parents: Query<&Parent>,
     bone_rests: Query<&BoneRest>,
     global_transforms: Query<&GlobalTransform>,
     mut local_transforms: Query<&mut Transform>,
 ) {
-    let mut temp = false;
-    for _e in event_reader.read() {
-        *run = true;
-        temp = true;
-    }
-    if !*run || temp {
-        return;
-    }
+    for (skeleton, targets, limits, root_global_transform) in skeletons.iter() {
+        for (bone, target) in [
+            (BoneName::Hips, &targets.hips),
+            (BoneName::Head, &targets.head),
+        ] {
+            let Some(target) = target else {
+                continue;
+            };
 
-    for (skeleton, target, root_global_transform, renik_limb) in skeletons.iter() {
-        let Some(left_upper_arm) = skeleton.0.get(&BoneName::LeftUpperArm) else {
-            continue;
-        };
-        let left_upper_arm_parent = parents
-            .get(*left_upper_arm)
-            .expect("missing parent of left upper arm")
-            .get();
-
-        let target = global_transforms
-            .get(target.left_hand)
-            .unwrap()
-            .compute_transform();
-
-        //println!("{}", root_global_transform.translation());
-        //println!("{}", root_global_transform.to_scale_rotation_translation().1);
-        //println!("{}", target.translation);
-        //println!("{}", target.rotation);
-
-        let target = Transform::from_matrix(
-            root_global_transform.compute_matrix().inverse() * target.compute_matrix(),
-        );
+            let Some(entity) = skeleton.0.get(&bone) else {
+                continue;
+            };
 
-        //let target = Transform::from_matrix(root_global_transform.compute_matrix().inverse() * global_transforms.get(target.left_hand).unwrap().compute_matrix());
+            let Some(pose) = target.resolve(&global_transforms, root_global_transform, *entity)
+            else {
+                continue;
+            };
 
-        //println!("target: {}", target.translation);
+            // The target is in world space, the bone is relative to its parent.
+            let parent_global = match parents
+                .get(*entity)
+                .and_then(|parent| global_transforms.get(parent.get()))
+            {
+                Ok(parent) => parent.compute_matrix(),
+                Err(_) => root_global_transform.compute_matrix(),
+            };
 
-        let root = Transform::from_translation(Vec3::new(0.0, 1.129259, 0.006192))
-            .with_rotation(Quat::from_euler(XYZ, -0.054638, 0.0, 0.0))
-            .with_scale(Vec3::splat(1.0));
+            let local = Transform::from_matrix(parent_global.inverse() * pose.compute_matrix());
 
-        let root = root
-            * match bone_rests.get(left_upper_arm_parent) {
-                Ok(a) => a.0,
-                Err(_) => {
-                    continue;
+            let Ok(mut transform) = local_transforms.get_mut(*entity) else {
+                continue;
+            };
+
+            // The head is only rotated, the neck keeps it attached to the body.
+            if bone == BoneName::Hips {
+                transform.translation = local.translation;
+            }
+
+            transform.rotation = match (limits, bone_rests.get(*entity)) {
+                (Some(limits), Ok(rest)) => {
+                    limits.clamp(&bone, local.rotation, rest.0.rotation, Vec3::Y)
                 }
+                _ => local.rotation,
             };
+        }
+    }
+}
 
-        //println!("origin: {}", root.translation);
-        //println!("rotation: {:?}", root.rotation.to_euler(XYZ));
+fn perform_limb_ik(
+    mut commands: Commands,
+    skeletons: Query<
+        (
+            &HumanoidBones,
+            Option<&IkTargets>,
+            Option<&FootPlacement>,
+            Option<&JointLimits>,
+            &GlobalTransform,
+        ),
+        With<VrmRetargetingInitialized>,
+    >,
+    mut limbs: Query<&mut RenikLimb>,
+    parents: Query<&Parent>,
+    bone_rests: Query<&BoneRest>,
+    global_transforms: Query<&GlobalTransform>,
+    mut local_transforms: Query<&mut Transform>,
+) {
+    for (skeleton, targets, placement, joint_limits, root_global_transform) in skeletons.iter() {
+        let root_inverse = root_global_transform.compute_matrix().inverse();
 
-        let target_vector = root
-            .compute_matrix()
-            .inverse()
-            .transform_point3(target.translation);
-        //println!("target vector: {}", target_vector);
+        for limb_name in Limb::ALL {
+            // Feet placed on the ground take priority over their targets.
+            let placed = placement.and_then(|placement| placement.placed(limb_name));
+            let target = targets.and_then(|targets| targets.limb(limb_name));
 
-        let offset_quat = renik_limb.left_shoulder_offset;
-        let pole_offset = renik_limb.shoulder_pole_offset;
-        //println!("a: {:?}, b: {:?}", offset_quat.to_euler(XYZ), pole_offset.to_euler(XYZ));
-        let pole_offset_scaled = pole_offset.slerp(Quat::IDENTITY, 1.0 - ARM_SHOULDER_INFLUENCE);
+            if placed.is_none() && target.is_none() {
+                continue;
+            }
 
-        //println!("{:?}", pole_offset_scaled.to_euler(XYZ));
+            let names = limb_name.bones();
+            let (Some(upper), Some(lower), Some(leaf)) = (
+                skeleton.0.get(&names[0]),
+                skeleton.0.get(&names[1]),
+                skeleton.0.get(&names[2]),
+            ) else {
+                continue;
+            };
+            let bones = [*upper, *lower, *leaf];
+            let limits = names.map(|name| joint_limits.and_then(|limits| limits.get(&name)));
 
-        //println!("pole offset scaled: {:?}, offset_quat: {:?}, target_vector: {:?}, arm_shoulder_influence: {:?}, pole_offset: {:?}",
-        //pole_offset_scaled.to_euler(XYZ), offset_quat.to_euler(XYZ), target_vector, ARM_SHOULDER_INFLUENCE, pole_offset.to_euler(XYZ));
+            // Bone rests are added the frame after retargeting.
+            if bones.iter().any(|bone| bone_rests.get(*bone).is_err()) {
+                continue;
+            }
 
-        let quat_align_to_target = pole_offset_scaled
-            * align_vectors(
-                Vec3::new(0.0, 1.0, 0.0),
-                pole_offset.inverse() * (offset_quat.inverse() * target_vector),
-                1.0,
-            )
-            .slerp(Quat::IDENTITY, 1.0 - ARM_SHOULDER_INFLUENCE);
+            let Ok(mut limb) = limbs.get_mut(bones[0]) else {
+                commands
+                    .entity(bones[0])
+                    .insert(limb_name.default_settings());
+                continue;
+            };
 
-        //println!("quat align to target: {:?}", quat_align_to_target.to_euler(XYZ));
+            let target = match placed
+                .or_else(|| target?.resolve(&global_transforms, root_global_transform, bones[2]))
+            {
+                Some(target) => Transform::from_matrix(root_inverse * target.compute_matrix()),
+                None => continue,
+            };
 
-        let custom_pose = Transform::from_rotation(offset_quat * quat_align_to_target);
-        let mut current_transform = local_transforms.get_mut(left_upper_arm_parent).unwrap();
-        let rest_transform = bone_rests.get(left_upper_arm_parent).unwrap().0;
+            // The solved chain starts at the parent of the shoulder for arms,
+            // or at the parent of the upper leg for legs.
+            let first = match limb_name.shoulder() {
+                Some(shoulder) => match skeleton.0.get(&shoulder) {
+                    Some(shoulder) => *shoulder,
+                    None => continue,
+                },
+                None => bones[0],
+            };
 
-        current_transform.rotation = rest_transform.rotation * offset_quat * quat_align_to_target;
+            let Ok(parent) = parents.get(first) else {
+                continue;
+            };
 
-        let root = root * custom_pose;
+            let mut root = match global_transforms.get(parent.get()) {
+                Ok(parent) => Transform::from_matrix(root_inverse * parent.compute_matrix()),
+                Err(_) => continue,
+            };
 
-        //println!("origin: {}", root.translation);
-        //println!("rotation: {:?}", root.rotation.to_euler(XYZ));
+            if limb_name.shoulder().is_some() {
+                let Ok(shoulder_rest) = bone_rests.get(first) else {
+                    continue;
+                };
 
-        //println!("{}", target.translation);
+                root = root * shoulder_rest.0;
 
-        do_ik_bullshit(
-            renik_limb.clone(),
-            root,
-            target,
-            &bone_rests,
-            skeleton,
-            &mut local_transforms,
-        );
+                let mut shoulder_rotation = shoulder_rotation(&limb, root, target);
+
+                let shoulder_limit = limb_name
+                    .shoulder()
+                    .and_then(|shoulder| joint_limits?.get(&shoulder));
+
+                if let (Some(limit), Ok(upper_rest)) = (shoulder_limit, bone_rests.get(bones[0])) {
+                    shoulder_rotation = limit.clamp(shoulder_rotation, upper_rest.0.translation);
+                }
+
+                if let Ok(mut shoulder) = local_transforms.get_mut(first) {
+                    shoulder.rotation = shoulder_rest.0.rotation * shoulder_rotation;
+                }
+
+                root = root * Transform::from_rotation(shoulder_rotation);
+            }
+
+            solve_limb(
+                &mut limb,
+                root,
+                target,
+                bones,
+                limits,
+                &bone_rests,
+                &mut local_transforms,
+            );
+        }
     }
 }
 
-pub trait PoleOffset {
-    fn pole_offset(&self) -> Quat;
+/// Rotation of the shoulder, relative to its rest, that points the arm towards the target.
+fn shoulder_rotation(limb: &RenikLimb, root: Transform, target: Transform) -> Quat {
+    let target_vector = root
+        .compute_matrix()
+        .inverse()
+        .transform_point3(target.translation);
+
+    let offset_quat = limb.shoulder_offset;
+    let pole_offset = limb.shoulder_pole_offset;
+    let pole_offset_scaled = pole_offset.slerp(Quat::IDENTITY, 1.0 - ARM_SHOULDER_INFLUENCE);
+
+    let quat_align_to_target = pole_offset_scaled
+        * align_vectors(
+            Vec3::new(0.0, 1.0, 0.0),
+            pole_offset.inverse() * (offset_quat.inverse() * target_vector),
+            1.0,
+        )
+        .slerp(Quat::IDENTITY, 1.0 - ARM_SHOULDER_INFLUENCE);
+
+    offset_quat * quat_align_to_target
 }
 
 pub fn left_arm_pole_offset() -> Quat {
@@ -205,8 +403,15 @@ pub fn left_arm_pole_offset() -> Quat {
     )
 }
 
-#[derive(Debug, Clone, Component, Reflect, InspectorOptions)]
-#[reflect(InspectorOptions)]
+/// Settings and solver state for a single limb.
+/// Inserted on the limb's upper bone.
+#[derive(Debug, Clone, Component)]
+#[cfg_attr(feature = "reflect", derive(Reflect))]
+#[cfg_attr(
+    feature = "inspector",
+    derive(InspectorOptions),
+    reflect(InspectorOptions)
+)]
 pub struct RenikLimb {
     upper_twist_offset: f32,
     lower_twist_offset: f32,
@@ -219,14 +424,19 @@ pub struct RenikLimb {
     pole_offset: Quat,
     target_position_influence: Vec3,
     pub shoulder_pole_offset: Quat,
-    a: Vec3,
-    b: Vec3,
-    c: Vec3,
-    left_shoulder_offset: Quat,
+    shoulder_offset: Quat,
+    /// Which side of the twist inflection point the limb is on, kept between frames.
+    overflow_state: f32,
 }
 
 impl Default for RenikLimb {
     fn default() -> Self {
+        Self::left_arm()
+    }
+}
+
+impl RenikLimb {
+    pub fn left_arm() -> Self {
         RenikLimb {
             upper_twist_offset: -0.27777 * PI,
             lower_twist_offset: -0.27777 * PI,
@@ -236,102 +446,110 @@ impl Default for RenikLimb {
             twist_inflection_point_offset: 20.0_f32.to_radians(),
             twist_overflow: 45.0_f32.to_radians(),
             target_rotation_influence: 0.33,
-            pole_offset: Quat::from_euler(
-                EulerRot::XYZ,
-                15.0_f32.to_radians(),
-                0.0,
-                60.0_f32.to_radians()
-            ),
-            shoulder_pole_offset:/* Quat::from_euler(XYZ, 0.0, 0.0, 78f32.to_radians())*/ Quat::IDENTITY,
-            a: Vec3::new(0.0, 1.0, 0.0),
-            b: Vec3::new(1.0, 0.0, 0.0),
+            pole_offset: left_arm_pole_offset(),
             target_position_influence: Vec3::new(2.0, -1.5, -1.0),
-            c: Vec3::new(0.0, 1.0, 0.0),
-            left_shoulder_offset: Quat::IDENTITY,
+            shoulder_pole_offset: Quat::IDENTITY,
+            shoulder_offset: Quat::IDENTITY,
+            overflow_state: 0.0,
         }
     }
-}
 
-static mut LEFT_ARM_OVERFLOW_STATE: f32 = 0.0;
+    pub fn right_arm() -> Self {
+        Self::left_arm().mirrored()
+    }
+
+    pub fn left_leg() -> Self {
+        RenikLimb {
+            upper_twist_offset: 0.0,
+            lower_twist_offset: 0.0,
+            roll_offset: 0.0,
+            upper_limb_twist: 0.25,
+            lower_limb_twist: 0.25,
+            twist_inflection_point_offset: 0.0,
+            twist_overflow: 45.0_f32.to_radians(),
+            target_rotation_influence: 0.5,
+            pole_offset: Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, PI),
+            target_position_influence: Vec3::new(0.0, 0.0, 0.0),
+            shoulder_pole_offset: Quat::IDENTITY,
+            shoulder_offset: Quat::IDENTITY,
+            overflow_state: 0.0,
+        }
+    }
+
+    pub fn right_leg() -> Self {
+        Self::left_leg().mirrored()
+    }
 
-fn do_ik_bullshit(
-    limb: RenikLimb,
+    /// Mirrors the settings across the YZ plane, for the other side of the body.
+    pub fn mirrored(&self) -> Self {
+        let mirror = |q: Quat| {
+            let (x, y, z) = q.to_euler(EulerRot::XYZ);
+            Quat::from_euler(EulerRot::XYZ, x, -y, -z)
+        };
+
+        RenikLimb {
+            upper_twist_offset: -self.upper_twist_offset,
+            lower_twist_offset: -self.lower_twist_offset,
+            roll_offset: -self.roll_offset,
+            twist_inflection_point_offset: -self.twist_inflection_point_offset,
+            pole_offset: mirror(self.pole_offset),
+            target_position_influence: self.target_position_influence * Vec3::new(1.0, -1.0, -1.0),
+            shoulder_pole_offset: mirror(self.shoulder_pole_offset),
+            shoulder_offset: mirror(self.shoulder_offset),
+            overflow_state: 0.0,
+            ..self.clone()
+        }
+    }
+}
+
+/// Solves a two bone limb towards `local_target`.
+/// `root` and `local_target` are relative to the avatar root,
+/// `bones` are the upper, lower and leaf bones, each clamped to its limit in `limits`.
+fn solve_limb(
+    limb: &mut RenikLimb,
     root: Transform,
     local_target: Transform,
+    bones: [Entity; 3],
+    limits: [Option<JointLimit>; 3],
     bone_rests: &Query<&BoneRest>,
-    skeleton: &HumanoidBones,
     local_transforms: &mut Query<&mut Transform>,
 ) {
-    let true_root = root
-        * Transform::from_translation(
-            bone_rests
-                .get(*skeleton.0.get(&BoneName::LeftUpperArm).unwrap())
-                .unwrap()
-                .0
-                .translation,
-        );
+    let [upper, lower, leaf] = bones;
+
+    let rest_translation = |entity: Entity| match bone_rests.get(entity) {
+        Ok(rest) => rest.0.translation,
+        Err(_) => Vec3::ZERO,
+    };
 
-    //println!("{}, {}", local_target.rotation, local_target.translation);
+    let true_root = root * Transform::from_translation(rest_translation(upper));
 
     let local_target = Transform::from_matrix(
         true_root.compute_matrix().inverse() * local_target.compute_matrix(),
     );
 
-    let full_upper = Transform::from_translation(
-        bone_rests
-            .get(*skeleton.0.get(&BoneName::LeftUpperArm).unwrap())
-            .unwrap()
-            .0
-            .translation,
-    );
-    let full_lower = Transform::from_translation(
-        bone_rests
-            .get(*skeleton.0.get(&BoneName::LeftLowerArm).unwrap())
-            .unwrap()
-            .0
-            .translation,
-    );
-    let leaf = Transform::from_translation(
-        bone_rests
-            .get(*skeleton.0.get(&BoneName::LeftHand).unwrap())
-            .unwrap()
-            .0
-            .translation,
-    );
+    let full_upper = Transform::from_translation(rest_translation(upper));
+    let full_lower = Transform::from_translation(rest_translation(lower));
+    let leaf_rest = Transform::from_translation(rest_translation(leaf));
 
     let upper_vector = full_lower.translation;
-    let lower_vector = leaf.translation;
+    let lower_vector = leaf_rest.translation;
 
     let mut target_vector = local_target.translation;
 
-    //println!("{}", target_vector);
-
     let normalized_target_vector = target_vector.normalize();
-    //TODO remove this
-    //normalized_target_vector = Vec3::new(0.213173, 0.518952, -0.827796);
 
     let limb_length = upper_vector.length() + lower_vector.length();
     if target_vector.length() > upper_vector.length() + lower_vector.length() {
         target_vector = normalized_target_vector * limb_length;
     }
 
-    //println!("{}", target_vector);
-
     let angles = trig_angles(upper_vector, lower_vector, target_vector);
 
-    //println!("{}", angles);
-
-    let starting_pole = limb.pole_offset * /*Vec3::new(0.0, 1.0, 0.0)*/ limb.a;
+    let starting_pole = limb.pole_offset * Vec3::new(0.0, 1.0, 0.0);
     let mut joint_axis = align_vectors(starting_pole, target_vector, 1.0)
         * (limb.pole_offset * Vec3::new(1.0, 0.0, 0.0));
 
-    //println!("{}", starting_pole);
-
-    //joint_axis = Vec3::new(0.741801, 0.465429, 0.482809);
-
-    //println!("{}", joint_axis);
-
-    let leaf_rest_vector = full_upper.rotation * (full_lower * leaf.translation);
+    let leaf_rest_vector = full_upper.rotation * (full_lower * leaf_rest.translation);
     let positional_offset = limb
         .target_position_influence
         .dot(target_vector - leaf_rest_vector);
@@ -341,7 +559,6 @@ fn do_ik_bullshit(
     )
     .mul_vec3(joint_axis);
 
-    //println!("{}", joint_axis);
     let local_leaf_vector = local_target.rotation * Vec3::new(0.0, 1.0, 0.0);
     let local_lower_vector = Quat::from_axis_angle(joint_axis, angles.x - angles.y)
         .mul_vec3(normalized_target_vector)
@@ -370,13 +587,9 @@ fn do_ik_bullshit(
         joint_roll_amount *= -1.0;
     }
 
-    //println!("{}", joint_roll_amount);
-
     joint_axis =
         Quat::from_axis_angle(normalized_target_vector, joint_roll_amount).mul_vec3(joint_axis);
 
-    //println!("{}", joint_axis);
-
     let total_roll = joint_roll_amount + positional_offset + limb.roll_offset;
 
     let leaf_x = align_vectors(
@@ -396,19 +609,15 @@ fn do_ik_bullshit(
 
     let mut inflection_point =
         if twist_angle > 0.0 { PI } else { -PI } - limb.twist_inflection_point_offset;
-    let overflow_area = unsafe { LEFT_ARM_OVERFLOW_STATE } * limb.twist_overflow;
+    let overflow_area = limb.overflow_state * limb.twist_overflow;
     let inflection_distance = twist_angle - inflection_point;
 
     if inflection_distance.abs() < limb.twist_overflow {
-        if unsafe { LEFT_ARM_OVERFLOW_STATE } == 0.0 {
-            if inflection_distance < 0.0 {
-                unsafe { LEFT_ARM_OVERFLOW_STATE = 1.0 }
-            } else {
-                unsafe { LEFT_ARM_OVERFLOW_STATE = -1.0 }
-            }
+        if limb.overflow_state == 0.0 {
+            limb.overflow_state = if inflection_distance < 0.0 { 1.0 } else { -1.0 };
         }
     } else {
-        unsafe { LEFT_ARM_OVERFLOW_STATE = 0.0 }
+        limb.overflow_state = 0.0;
     }
 
     inflection_point += overflow_area;
@@ -429,24 +638,13 @@ fn do_ik_bullshit(
         twist_angle * limb.target_rotation_influence,
     )
     .mul_vec3(joint_axis);
-    // Rebuild the rotations
-
-    //joint_axis = Vec3::new(0.017751, 0.845073, 0.534354);
-    //normalized_target_vector = Vec3::new(0.213173, 0.518952, -0.827796);
-    //angles.x = 0.94497638940811;
-    //angles.y = 1.8366014957428;
-    //println!("{}", angles.y);
-    //println!("{}", angles.x);
-
-    //println!("{}", joint_axis);
-    //println!("{}", normalized_target_vector);
 
+    // Rebuild the rotations
     let upper_joint_vector =
         Quat::from_axis_angle(joint_axis, angles.x).mul_vec3(normalized_target_vector);
     let a = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -limb.roll_offset);
 
     let rolled_lower_joint_axis = a * Vec3::new(1.0, 0.0, 0.0);
-    //println!("{}", rolled_lower_joint_axis);
     let lower_joint_vector =
         Quat::from_axis_angle(rolled_lower_joint_axis, angles.y).mul_vec3(Vec3::new(0.0, 1.0, 0.0));
     let twisted_joint_axis =
@@ -456,69 +654,42 @@ fn do_ik_bullshit(
         upper_joint_vector,
         twisted_joint_axis.cross(upper_joint_vector),
     ));
-    //println!("{:?}", lower_joint_vector);
     let mut lower_basis = Quat::from_mat3(&Mat3::from_cols(
         rolled_lower_joint_axis,
         lower_joint_vector,
         rolled_lower_joint_axis.cross(lower_joint_vector),
     ));
 
-    //println!("{}", lower_joint_vector);
-
-    //println!("{:?}", lower_basis.to_euler(XYZ));
-
     lower_basis = transpose_quat(lower_basis);
-
-    //println!("{:?}", lower_basis.to_euler(XYZ));
-
     lower_basis *= Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), lower_twist);
-
-    //println!("{:?}", lower_basis.to_euler(XYZ));
-
-    //println!("{:?}", upper_twist);
-
     lower_basis = Quat::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), -upper_twist) * lower_basis;
 
-    //rotate_quat(lower_basis, Vec3::new(0.0, 1.0, 0.0), -upper_twist);
-    //println!("{:?}", lower_basis.to_euler(XYZ));
-
-    //println!("{:?}", full_lower.rotation);
-
     let upper_transform = (full_upper.rotation.inverse() * upper_basis).normalize();
     let lower_transform = (full_lower.rotation.inverse() * lower_basis).normalize();
-    let leaf_transform = leaf.rotation.inverse()
+    let leaf_transform = leaf_rest.rotation.inverse()
         * (upper_basis * lower_basis).inverse()
         * local_target.rotation
-        * leaf.rotation;
-
-    //println!("upper transform: {:?}", upper_transform.to_euler(XYZ));
-    //println!("lower transform: {:?}", lower_transform.to_euler(XYZ));
-    //println!("leaf_transform: {:?}", leaf_transform.to_euler(XYZ));
-
-    local_transforms
-        .get_mut(*skeleton.0.get(&BoneName::LeftUpperArm).unwrap())
-        .unwrap()
-        .rotation = upper_transform;
-    local_transforms
-        .get_mut(*skeleton.0.get(&BoneName::LeftLowerArm).unwrap())
-        .unwrap()
-        .rotation = lower_transform;
-    local_transforms
-        .get_mut(*skeleton.0.get(&BoneName::LeftHand).unwrap())
-        .unwrap()
-        .rotation = leaf_transform;
-}
-
-/*fn rotate_quat(lower_basis: Quat, axis: Vec3, angle_in_degrees: f32) -> Quat {
-    // Convert degrees to radians
-    let angle_in_radians = angle_in_degrees.to_radians();
-
-    // Create a rotation quaternion around the specified axis and angle
-    let rotation_quat = Quat::from_axis_angle(axis.normalize(), angle_in_radians);
+        * leaf_rest.rotation;
+
+    // Each bone points towards its child, the leaf along its own Y axis.
+    for ((entity, rotation, axis), limit) in [
+        (upper, upper_transform, full_lower.translation),
+        (lower, lower_transform, leaf_rest.translation),
+        (leaf, leaf_transform, Vec3::Y),
+    ]
+    .into_iter()
+    .zip(limits)
+    {
+        let rotation = match (limit, bone_rests.get(entity)) {
+            (Some(limit), Ok(rest)) => limit.clamp_local(rotation, rest.0.rotation, axis),
+            _ => rotation,
+        };
 
-    // Apply the rotation by multiplying the quaternions
-    rotation_quat * lower_basis
-}*/
+        if let Ok(mut transform) = local_transforms.get_mut(entity) {
+            transform.rotation = rotation;
+        }
+    }
+}
 
 fn transpose_quat(quat: Quat) -> Quat {
     // Convert the quaternion to a matrix
@@ -561,7 +732,7 @@ fn safe_acos(f: f32) -> f32 {
     f.clamp(-1.0, 1.0).acos()
 }
 
-/// Default of 1
+/// Rotation turning `a` towards `b`, scaled by `influence` from 0 (none) to 1 (fully aligned).
 fn align_vectors(mut a: Vec3, mut b: Vec3, influence: f32) -> Quat {
     if a.length() == 0.0 || b.length() == 0.0 {
         return Quat::IDENTITY;
@@ -577,8 +748,8 @@ fn align_vectors(mut a: Vec3, mut b: Vec3, influence: f32) -> Quat {
         if perpendicular.length_squared() == 0.0 {
             perpendicular = get_perpendicular_vector(a);
         }
-        let a = perpendicular.normalize().normalize();
-        let ret = Quat::from_axis_angle(a, angle_diff);
+        let axis = perpendicular.normalize();
+        let ret = Quat::from_axis_angle(axis, angle_diff);
 
         ret.normalize()
     } else {
@@ -593,3 +764,52 @@ fn get_perpendicular_vector(v: Vec3) -> Vec3 {
         Vec3::new(1.0, 0.0, 0.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    /// Solves a two bone chain, with bones of 0.4 along Y, and returns where its leaf ends up.
+    fn solve_two_bones(target: Vec3) -> Vec3 {
+        let mut world = World::new();
+
+        let bones = [Vec3::ZERO, Vec3::Y * 0.4, Vec3::Y * 0.4].map(|translation| {
+            let rest = Transform::from_translation(translation);
+            world.spawn((rest, BoneRest(rest))).id()
+        });
+
+        world.run_system_once(
+            move |bone_rests: Query<&BoneRest>, mut local_transforms: Query<&mut Transform>| {
+                solve_limb(
+                    &mut RenikLimb::left_leg(),
+                    Transform::IDENTITY,
+                    Transform::from_translation(target),
+                    bones,
+                    [None; 3],
+                    &bone_rests,
+                    &mut local_transforms,
+                );
+            },
+        );
+
+        let [upper, lower, leaf] = bones.map(|bone| *world.get::<Transform>(bone).unwrap());
+        (upper * lower).transform_point(leaf.translation)
+    }
+
+    #[test]
+    fn limb_reaches_target() {
+        let target = Vec3::new(0.2, 0.5, 0.1);
+        assert!(solve_two_bones(target).distance(target) < 0.001);
+    }
+
+    #[test]
+    fn limb_stretches_towards_target_out_of_reach() {
+        let target = Vec3::new(1.0, 2.0, -0.5);
+        let leaf = solve_two_bones(target);
+
+        assert!((leaf.length() - 0.8).abs() < 0.001);
+        assert!(leaf.normalize().dot(target.normalize()) > 0.999);
+    }
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+
+use super::Limb;
+
+/// Where an [`IkTarget`] reads its pose from.
+#[derive(Clone, Copy, Debug)]
+pub enum IkTargetSource {
+    /// The [`GlobalTransform`] of an entity, such as a tracked VR controller.
+    Entity(Entity),
+    /// A fixed pose in world space.
+    Transform(Transform),
+}
+
+/// A pose for a bone to reach, blended with the bone's current pose by the weights.
+#[derive(Clone, Copy, Debug)]
+pub struct IkTarget {
+    pub source: IkTargetSource,
+    /// How far the bone moves towards the target position, from 0 to 1.
+    pub position_weight: f32,
+    /// How far the bone turns towards the target rotation, from 0 to 1.
+    pub rotation_weight: f32,
+}
+
+impl IkTarget {
+    pub fn entity(entity: Entity) -> Self {
+        Self::new(IkTargetSource::Entity(entity))
+    }
+
+    pub fn transform(transform: Transform) -> Self {
+        Self::new(IkTargetSource::Transform(transform))
+    }
+
+    fn new(source: IkTargetSource) -> Self {
+        Self {
+            source,
+            position_weight: 1.0,
+            rotation_weight: 1.0,
+        }
+    }
+
+    pub fn with_position_weight(mut self, weight: f32) -> Self {
+        self.position_weight = weight;
+        self
+    }
+
+    pub fn with_rotation_weight(mut self, weight: f32) -> Self {
+        self.rotation_weight = weight;
+        self
+    }
+
+    /// The world space pose of the target, blended with the current pose of `bone`.
+    /// Returns [`None`] if the target has no influence or its entity is missing.
+    pub(crate) fn resolve(
+        &self,
+        global_transforms: &Query<&GlobalTransform>,
+        root: &GlobalTransform,
+        bone: Entity,
+    ) -> Option<Transform> {
+        let position_weight = self.position_weight.clamp(0.0, 1.0);
+        let rotation_weight = self.rotation_weight.clamp(0.0, 1.0);
+
+        if position_weight == 0.0 && rotation_weight == 0.0 {
+            return None;
+        }
+
+        let target = match self.source {
+            IkTargetSource::Entity(entity) => {
+                global_transforms.get(entity).ok()?.compute_transform()
+            }
+            IkTargetSource::Transform(transform) => transform,
+        };
+
+        let current = match global_transforms.get(bone) {
+            Ok(global) => global.compute_transform(),
+            Err(_) => root.compute_transform(),
+        };
+
+        Some(
+            target
+                .with_translation(
+                    current
+                        .translation
+                        .lerp(target.translation, position_weight),
+                )
+                .with_rotation(current.rotation.slerp(target.rotation, rotation_weight)),
+        )
+    }
+}
+
+/// Targets for the avatar's [`RenIkPlugin`](super::RenIkPlugin) solve.
+/// Bones without a target keep their animated pose.
+#[derive(Component, Clone, Debug, Default)]
+pub struct IkTargets {
+    pub head: Option<IkTarget>,
+    pub hips: Option<IkTarget>,
+    pub left_hand: Option<IkTarget>,
+    pub right_hand: Option<IkTarget>,
+    pub left_foot: Option<IkTarget>,
+    pub right_foot: Option<IkTarget>,
+}
+
+impl IkTargets {
+    /// The target for the leaf bone of `limb`.
+    pub fn limb(&self, limb: Limb) -> Option<&IkTarget> {
+        match limb {
+            Limb::LeftArm => self.left_hand.as_ref(),
+            Limb::RightArm => self.right_hand.as_ref(),
+            Limb::LeftLeg => self.left_foot.as_ref(),
+            Limb::RightLeg => self.right_foot.as_ref(),
+        }
+    }
+
+    pub fn limb_mut(&mut self, limb: Limb) -> &mut Option<IkTarget> {
+        match limb {
+            Limb::LeftArm => &mut self.left_hand,
+            Limb::RightArm => &mut self.right_hand,
+            Limb::LeftLeg => &mut self.left_foot,
+            Limb::RightLeg => &mut self.right_foot,
+        }
+    }
+}
@@ -1,18 +1,36 @@
 //! [Bevy](https://bevyengine.org/) plugin for loading [VRM](https://vrm.dev/en/) avatars.
 //! Aims to support both the VRM 0.0 and VRM 1.0 standards.
+//!
+//! Plugins that pose avatars, such as [`animation::VrmAnimationPlugin`],
+//! [`pose::PoseStackPlugin`], [`hand_pose::HandPosePlugin`] and the [`vmc`] plugins,
+//! work relative to the rest pose, so they also require
+//! [`retargeting::VrmRetargetingPlugin`] and [`ik::RenIkPlugin`].
 
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{animation::animation_player, prelude::*, utils::HashMap};
 use bevy_gltf_kun::import::gltf::GltfAssetPlugin;
 use bevy_shader_mtoon::MtoonPlugin;
+use expressions::VrmExpressions;
 use loader::{Vrm, VrmLoader};
 
+pub mod animation;
+pub mod arkit;
 mod auto_scene;
+pub mod expressions;
 pub mod extensions;
+pub mod hand_pose;
 mod humanoid_bones;
 pub mod ik;
+pub mod license;
+mod lifecycle;
+pub mod lip_sync;
 pub mod loader;
+mod nodes;
+pub mod pose;
 pub mod retargeting;
 mod spring_bones;
+#[cfg(test)]
+mod test_utils;
+pub mod vmc;
 
 pub mod mtoon {
     pub use bevy_shader_mtoon::*;
@@ -27,20 +45,93 @@ impl Plugin for VrmPlugin {
         app.add_plugins((GltfAssetPlugin, MtoonPlugin))
             .init_asset::<Vrm>()
             .init_asset_loader::<VrmLoader>()
+            .register_type::<GltfNodeIndex>()
+            .add_event::<VrmLoaded>()
+            .add_event::<VrmBonesMapped>()
+            .add_event::<VrmReady>()
+            .add_event::<VrmDespawned>()
+            .configure_sets(
+                Update,
+                (
+                    VrmSet::Scene,
+                    VrmSet::Bones,
+                    VrmSet::Retarget,
+                    VrmSet::Ready,
+                )
+                    .chain(),
+            )
             .add_systems(
                 Update,
                 (
-                    auto_scene::set_vrm_scene,
-                    humanoid_bones::set_humanoid_bones,
-                    spring_bones::set_spring_bones,
+                    (lifecycle::reload_vrms, auto_scene::set_vrm_scene)
+                        .chain()
+                        .in_set(VrmSet::Scene),
+                    (
+                        humanoid_bones::set_humanoid_bones,
+                        spring_bones::set_spring_bones,
+                        expressions::set_expression_binds,
+                    )
+                        .chain()
+                        .in_set(VrmSet::Bones),
+                    (lifecycle::send_vrm_ready, lifecycle::send_vrm_despawned)
+                        .in_set(VrmSet::Ready),
                 ),
+            )
+            .add_systems(
+                PostUpdate,
+                expressions::apply_expressions
+                    .after(animation_player)
+                    .before(bevy::render::mesh::morph::inherit_weights),
             );
     }
 }
 
+/// Stages of avatar setup, run in order in the [`Update`] schedule.
+#[derive(SystemSet, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum VrmSet {
+    /// Sets and spawns the VRM scene. Hot-reloaded avatars are reset here.
+    Scene,
+    /// Maps the spawned nodes to humanoid and spring bones.
+    Bones,
+    /// Used by [`retargeting::VrmRetargetingPlugin`], if added.
+    Retarget,
+    /// Marks avatars that are ready for animation.
+    Ready,
+}
+
+/// Sent once the avatar's scene has spawned.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VrmLoaded {
+    pub entity: Entity,
+}
+
+/// Sent once the avatar's [`HumanoidBones`] have been mapped.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VrmBonesMapped {
+    pub entity: Entity,
+}
+
+/// Sent once the avatar is fully set up and ready for animation.
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VrmReady {
+    pub entity: Entity,
+}
+
+/// Sent when an avatar entity is despawned or loses its [`Handle<Vrm>`].
+#[derive(Event, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VrmDespawned {
+    pub entity: Entity,
+}
+
+/// Marks an avatar that has sent [`VrmReady`].
+/// Removed again when the [`Vrm`] asset is hot-reloaded.
+#[derive(Component)]
+pub struct VrmInitialized;
+
 #[derive(Bundle, Default)]
 pub struct VrmBundle {
     pub auto_scene: AutoScene,
+    pub expressions: VrmExpressions,
     pub humanoid_bones: HumanoidBones,
     pub scene_bundle: SceneBundle,
     pub spring_bones: SpringBones,
@@ -51,6 +142,12 @@ pub struct VrmBundle {
 #[derive(Component, Default)]
 pub struct AutoScene;
 
+/// Index of the glTF node an entity was spawned from.
+/// Inserted on every node entity when a VRM scene is imported.
+#[derive(Component, Reflect, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[reflect(Component)]
+pub struct GltfNodeIndex(pub usize);
+
 #[derive(Component, Default)]
 pub struct HumanoidBones(pub HashMap<BoneName, Entity>);
 
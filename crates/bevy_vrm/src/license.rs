@@ -0,0 +1,361 @@
+//! Normalized view of the usage permissions in a VRM's meta.
+//!
+//! VRM 0.0 and VRM 1.0 describe licensing differently. [`VrmLicense`] maps both
+//! onto the VRM 1.0 model, so callers only need to ask [`VrmLicense::permits`].
+//! Missing or unknown values always fall back to the most restrictive option.
+
+use serde_vrm::{
+    vrm0::{self, Allow, AllowedUserName},
+    vrm1::vrmc_vrm,
+};
+
+/// Who is allowed to perform as the avatar.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum AvatarPermission {
+    #[default]
+    OnlyAuthor,
+    OnlySeparatelyLicensedPerson,
+    Everyone,
+}
+
+/// The highest level of commercial use that is allowed.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CommercialUsage {
+    #[default]
+    PersonalNonProfit,
+    PersonalProfit,
+    Corporation,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum CreditNotation {
+    #[default]
+    Required,
+    Unnecessary,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Modification {
+    #[default]
+    Prohibited,
+    AllowModification,
+    AllowModificationRedistribution,
+}
+
+/// A use of the avatar that a license may or may not permit.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Usage {
+    /// Performing as the avatar by anyone other than the author.
+    Personation,
+    /// Use by a person for profit.
+    PersonalCommercial,
+    /// Use by a corporation or other organization.
+    Commercial,
+    ExcessivelyViolent,
+    ExcessivelySexual,
+    PoliticalOrReligious,
+    AntisocialOrHate,
+    /// Redistributing the unmodified model.
+    Redistribution,
+    Modification,
+    /// Redistributing a modified model.
+    ModifiedRedistribution,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VrmLicense {
+    pub avatar_permission: AvatarPermission,
+    pub commercial_usage: CommercialUsage,
+    pub credit_notation: CreditNotation,
+    pub modification: Modification,
+    pub allow_excessively_violent_usage: bool,
+    pub allow_excessively_sexual_usage: bool,
+    pub allow_political_or_religious_usage: bool,
+    pub allow_antisocial_or_hate_usage: bool,
+    pub allow_redistribution: bool,
+    /// The VRM 1.0 license document. VRM 0.0 only names its license.
+    pub license_url: Option<String>,
+    pub other_license_url: Option<String>,
+    /// Further permissions granted by a VRM 0.0 avatar, outside its license.
+    pub other_permission_url: Option<String>,
+}
+
+impl VrmLicense {
+    pub fn permits(&self, usage: Usage) -> bool {
+        match usage {
+            Usage::Personation => self.avatar_permission == AvatarPermission::Everyone,
+            Usage::PersonalCommercial => self.commercial_usage >= CommercialUsage::PersonalProfit,
+            Usage::Commercial => self.commercial_usage == CommercialUsage::Corporation,
+            Usage::ExcessivelyViolent => self.allow_excessively_violent_usage,
+            Usage::ExcessivelySexual => self.allow_excessively_sexual_usage,
+            Usage::PoliticalOrReligious => self.allow_political_or_religious_usage,
+            Usage::AntisocialOrHate => self.allow_antisocial_or_hate_usage,
+            Usage::Redistribution => self.allow_redistribution,
+            Usage::Modification => self.modification >= Modification::AllowModification,
+            Usage::ModifiedRedistribution => {
+                self.modification == Modification::AllowModificationRedistribution
+            }
+        }
+    }
+
+    /// Returns the usages from `usages` that the license does not permit.
+    pub fn forbidden(&self, usages: &[Usage]) -> Vec<Usage> {
+        usages
+            .iter()
+            .copied()
+            .filter(|usage| !self.permits(*usage))
+            .collect()
+    }
+}
+
+fn allow(value: &Option<Allow>) -> bool {
+    matches!(value, Some(Allow::Allow))
+}
+
+impl From<&vrm0::Meta> for VrmLicense {
+    fn from(meta: &vrm0::Meta) -> Self {
+        from_vrm0(
+            &meta.allowed_user_name,
+            &meta.violent_usage_name,
+            &meta.sexual_usage_name,
+            &meta.commercial_usage_name,
+            meta.license_name.as_deref(),
+            &meta.other_permission_url,
+            &meta.other_license_url,
+        )
+    }
+}
+
+impl From<&gltf_kun_vrm::vrm0::weight::Meta> for VrmLicense {
+    fn from(meta: &gltf_kun_vrm::vrm0::weight::Meta) -> Self {
+        from_vrm0(
+            &meta.allowed_user_name,
+            &meta.violent_usage_name,
+            &meta.sexual_usage_name,
+            &meta.commercial_usage_name,
+            meta.license_name.as_deref(),
+            &meta.other_permission_url,
+            &meta.other_license_url,
+        )
+    }
+}
+
+fn from_vrm0(
+    allowed_user_name: &Option<AllowedUserName>,
+    violent_usage_name: &Option<Allow>,
+    sexual_usage_name: &Option<Allow>,
+    commercial_usage_name: &Option<Allow>,
+    license_name: Option<&str>,
+    other_permission_url: &Option<String>,
+    other_license_url: &Option<String>,
+) -> VrmLicense {
+    let avatar_permission = match allowed_user_name {
+        Some(AllowedUserName::Everyone) => AvatarPermission::Everyone,
+        Some(AllowedUserName::ExplicitlyLicensedPerson) => {
+            AvatarPermission::OnlySeparatelyLicensedPerson
+        }
+        Some(AllowedUserName::OnlyAuthor) | None => AvatarPermission::OnlyAuthor,
+    };
+
+    let mut commercial_usage = if allow(commercial_usage_name) {
+        CommercialUsage::Corporation
+    } else {
+        CommercialUsage::PersonalNonProfit
+    };
+
+    // VRM 0.0 expresses redistribution and modification through the license name.
+    let (credit_notation, modification, allow_redistribution, non_commercial) = match license_name {
+        Some("CC0") => (
+            CreditNotation::Unnecessary,
+            Modification::AllowModificationRedistribution,
+            true,
+            false,
+        ),
+        Some("CC_BY") | Some("CC_BY_SA") => (
+            CreditNotation::Required,
+            Modification::AllowModificationRedistribution,
+            true,
+            false,
+        ),
+        Some("CC_BY_NC") | Some("CC_BY_NC_SA") => (
+            CreditNotation::Required,
+            Modification::AllowModificationRedistribution,
+            true,
+            true,
+        ),
+        Some("CC_BY_ND") => (
+            CreditNotation::Required,
+            Modification::Prohibited,
+            true,
+            false,
+        ),
+        Some("CC_BY_NC_ND") => (
+            CreditNotation::Required,
+            Modification::Prohibited,
+            true,
+            true,
+        ),
+        _ => (
+            CreditNotation::Required,
+            Modification::Prohibited,
+            false,
+            false,
+        ),
+    };
+
+    if non_commercial {
+        commercial_usage = CommercialUsage::PersonalNonProfit;
+    }
+
+    VrmLicense {
+        avatar_permission,
+        commercial_usage,
+        credit_notation,
+        modification,
+        allow_excessively_violent_usage: allow(violent_usage_name),
+        allow_excessively_sexual_usage: allow(sexual_usage_name),
+        allow_political_or_religious_usage: false,
+        allow_antisocial_or_hate_usage: false,
+        allow_redistribution,
+        license_url: None,
+        other_license_url: other_license_url.clone(),
+        other_permission_url: other_permission_url.clone(),
+    }
+}
+
+impl From<&vrmc_vrm::Meta> for VrmLicense {
+    fn from(meta: &vrmc_vrm::Meta) -> Self {
+        let avatar_permission = match meta.avatar_permission.as_str() {
+            "everyone" => AvatarPermission::Everyone,
+            "onlySeparatelyLicensedPerson" => AvatarPermission::OnlySeparatelyLicensedPerson,
+            _ => AvatarPermission::OnlyAuthor,
+        };
+
+        let commercial_usage = match meta.commercial_usage.as_deref() {
+            Some("corporation") => CommercialUsage::Corporation,
+            Some("personalProfit") => CommercialUsage::PersonalProfit,
+            _ => CommercialUsage::PersonalNonProfit,
+        };
+
+        let credit_notation = match meta.credit_notation.as_deref() {
+            Some("unnecessary") => CreditNotation::Unnecessary,
+            _ => CreditNotation::Required,
+        };
+
+        let modification = match meta.modification.as_deref() {
+            Some("allowModificationRedistribution") => {
+                Modification::AllowModificationRedistribution
+            }
+            Some("allowModification") => Modification::AllowModification,
+            _ => Modification::Prohibited,
+        };
+
+        Self {
+            avatar_permission,
+            commercial_usage,
+            credit_notation,
+            modification,
+            allow_excessively_violent_usage: meta
+                .allow_excessively_violent_usage
+                .unwrap_or_default(),
+            allow_excessively_sexual_usage: meta.allow_excessively_sexual_usage.unwrap_or_default(),
+            allow_political_or_religious_usage: meta
+                .allow_political_or_religious_usage
+                .unwrap_or_default(),
+            allow_antisocial_or_hate_usage: meta.allow_antisocial_or_hate_usage.unwrap_or_default(),
+            allow_redistribution: meta.allow_redistribution.unwrap_or_default(),
+            license_url: Some(meta.license_url.clone()),
+            other_license_url: meta.other_license_url.clone(),
+            other_permission_url: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_restrictive() {
+        let license = VrmLicense::from(&vrm0::Meta::default());
+
+        assert!(!license.permits(Usage::Personation));
+        assert!(!license.permits(Usage::Commercial));
+        assert!(!license.permits(Usage::PersonalCommercial));
+        assert!(!license.permits(Usage::ExcessivelyViolent));
+        assert!(!license.permits(Usage::ExcessivelySexual));
+        assert!(!license.permits(Usage::Redistribution));
+        assert!(!license.permits(Usage::Modification));
+    }
+
+    #[test]
+    fn vrm0_permissions() {
+        let meta = vrm0::Meta {
+            allowed_user_name: Some(AllowedUserName::Everyone),
+            commercial_usage_name: Some(Allow::Allow),
+            violent_usage_name: Some(Allow::Disallow),
+            license_name: Some("CC_BY".to_string()),
+            ..Default::default()
+        };
+        let license = VrmLicense::from(&meta);
+
+        assert!(license.permits(Usage::Personation));
+        assert!(license.permits(Usage::Commercial));
+        assert!(license.permits(Usage::PersonalCommercial));
+        assert!(license.permits(Usage::ModifiedRedistribution));
+        assert!(!license.permits(Usage::ExcessivelyViolent));
+        assert_eq!(license.credit_notation, CreditNotation::Required);
+    }
+
+    #[test]
+    fn vrm0_permission_url_is_not_the_license() {
+        let meta = vrm0::Meta {
+            other_permission_url: Some("https://example.com/permissions".to_string()),
+            ..Default::default()
+        };
+        let license = VrmLicense::from(&meta);
+
+        assert_eq!(license.license_url, None);
+        assert_eq!(
+            license.other_permission_url.as_deref(),
+            Some("https://example.com/permissions")
+        );
+    }
+
+    #[test]
+    fn vrm0_non_commercial_license() {
+        let meta = vrm0::Meta {
+            commercial_usage_name: Some(Allow::Allow),
+            license_name: Some("CC_BY_NC_ND".to_string()),
+            ..Default::default()
+        };
+        let license = VrmLicense::from(&meta);
+
+        assert!(!license.permits(Usage::Commercial));
+        assert!(license.permits(Usage::Redistribution));
+        assert!(!license.permits(Usage::Modification));
+    }
+
+    #[test]
+    fn vrm1_permissions() {
+        let meta = vrmc_vrm::Meta {
+            avatar_permission: "onlySeparatelyLicensedPerson".to_string(),
+            commercial_usage: Some("personalProfit".to_string()),
+            allow_political_or_religious_usage: Some(true),
+            modification: Some("allowModification".to_string()),
+            ..Default::default()
+        };
+        let license = VrmLicense::from(&meta);
+
+        assert!(!license.permits(Usage::Personation));
+        assert!(license.permits(Usage::PersonalCommercial));
+        assert!(!license.permits(Usage::Commercial));
+        assert!(license.permits(Usage::PoliticalOrReligious));
+        assert!(license.permits(Usage::Modification));
+        assert!(!license.permits(Usage::ModifiedRedistribution));
+        assert_eq!(
+            license.forbidden(&[Usage::Commercial, Usage::PersonalCommercial]),
+            vec![Usage::Commercial]
+        );
+    }
+}
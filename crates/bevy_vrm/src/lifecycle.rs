@@ -0,0 +1,84 @@
+use bevy::prelude::*;
+
+use crate::{
+    arkit::ArkitTargets,
+    expressions::ExpressionBinds,
+    humanoid_bones::HumanoidBonesInitialized,
+    loader::Vrm,
+    retargeting::{VrmFlipTimer, VrmFlipped, VrmRetargetingInitialized},
+    spring_bones::SpringBonesInitialized,
+    HumanoidBones, SpringBones, VrmDespawned, VrmInitialized, VrmReady,
+};
+
+/// Resets the setup of avatars whose [`Vrm`] asset was modified, and respawns their scene.
+pub fn reload_vrms(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<Vrm>>,
+    mut avatars: Query<(
+        Entity,
+        &Handle<Vrm>,
+        &mut Handle<Scene>,
+        Option<&mut HumanoidBones>,
+        Option<&mut SpringBones>,
+    )>,
+) {
+    for event in events.read() {
+        let AssetEvent::Modified { id } = event else {
+            continue;
+        };
+
+        for (entity, handle, mut scene, humanoid_bones, spring_bones) in avatars.iter_mut() {
+            if handle.id() != *id {
+                continue;
+            }
+
+            if let Some(mut humanoid_bones) = humanoid_bones {
+                humanoid_bones.0.clear();
+            }
+
+            if let Some(mut spring_bones) = spring_bones {
+                spring_bones.0.clear();
+            }
+
+            scene.set_changed();
+
+            commands.entity(entity).remove::<(
+                ArkitTargets,
+                ExpressionBinds,
+                HumanoidBonesInitialized,
+                SpringBonesInitialized,
+                VrmFlipTimer,
+                VrmFlipped,
+                VrmRetargetingInitialized,
+                VrmInitialized,
+            )>();
+        }
+    }
+}
+
+pub fn send_vrm_ready(
+    mut commands: Commands,
+    avatars: Query<
+        Entity,
+        (
+            With<HumanoidBonesInitialized>,
+            With<SpringBonesInitialized>,
+            Without<VrmInitialized>,
+        ),
+    >,
+    mut writer: EventWriter<VrmReady>,
+) {
+    for entity in avatars.iter() {
+        commands.entity(entity).insert(VrmInitialized);
+        writer.send(VrmReady { entity });
+    }
+}
+
+pub fn send_vrm_despawned(
+    mut removed: RemovedComponents<Handle<Vrm>>,
+    mut writer: EventWriter<VrmDespawned>,
+) {
+    for entity in removed.read() {
+        writer.send(VrmDespawned { entity });
+    }
+}
@@ -0,0 +1,284 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::expressions::VrmExpressions;
+
+use super::{set_vowels, VowelWeights};
+
+/// Length of each analyzed frame, in seconds.
+const FRAME_SECONDS: f32 = 0.032;
+
+/// Sample rate the audio is decimated towards before formant estimation.
+const ANALYSIS_RATE: u32 = 8000;
+
+/// Highest frequency searched for formants.
+const MAX_FORMANT: f32 = 4000.0;
+
+/// First and second formants of each vowel, in Hz, in A, I, U, E, O order.
+const VOWEL_FORMANTS: [(f32, f32); 5] = [
+    (800.0, 1250.0),
+    (300.0, 2300.0),
+    (350.0, 1400.0),
+    (500.0, 1900.0),
+    (500.0, 850.0),
+];
+
+/// Lip sync from mono PCM audio, such as a decoded wav file or a microphone stream.
+/// Samples are analyzed as they are pushed, so silence has to be pushed too
+/// for the mouth to close.
+#[derive(Component, Clone, Debug)]
+pub struct AudioLipSync {
+    pub sample_rate: u32,
+    /// Time for the weights to move most of the way to a new vowel, in seconds.
+    pub smoothing: f32,
+    /// Volume at which the mouth starts to open, in dBFS.
+    pub min_volume: f32,
+    /// Volume at which the mouth is fully open, in dBFS.
+    pub max_volume: f32,
+    buffer: Vec<f32>,
+    weights: VowelWeights,
+}
+
+impl AudioLipSync {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            smoothing: 0.08,
+            min_volume: -45.0,
+            max_volume: -15.0,
+            buffer: Vec::new(),
+            weights: VowelWeights::default(),
+        }
+    }
+
+    /// Adds samples from -1 to 1, analyzing every complete frame.
+    pub fn push_samples(&mut self, samples: &[f32]) {
+        self.buffer.extend_from_slice(samples);
+
+        let frame_len = ((self.sample_rate as f32 * FRAME_SECONDS) as usize).max(1);
+        let alpha = 1.0 - (-FRAME_SECONDS / self.smoothing.max(f32::EPSILON)).exp();
+
+        while self.buffer.len() >= frame_len {
+            let target = estimate_vowels(
+                &self.buffer[..frame_len],
+                self.sample_rate,
+                self.min_volume,
+                self.max_volume,
+            );
+            self.buffer.drain(..frame_len);
+
+            for (weight, target) in self.weights.iter_mut().zip(target) {
+                *weight += (target - *weight) * alpha;
+            }
+        }
+    }
+
+    /// The smoothed weight of each vowel.
+    pub fn weights(&self) -> VowelWeights {
+        self.weights
+    }
+}
+
+/// Estimates the vowel spoken in a frame of samples, from its volume and its first
+/// two formants. Weights sum to the volume, mapped from `min_volume`..`max_volume` dBFS to 0..1.
+pub fn estimate_vowels(
+    samples: &[f32],
+    sample_rate: u32,
+    min_volume: f32,
+    max_volume: f32,
+) -> VowelWeights {
+    if samples.is_empty() {
+        return VowelWeights::default();
+    }
+
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt();
+    let db = 20.0 * rms.max(1e-10).log10();
+    let volume = ((db - min_volume) / (max_volume - min_volume)).clamp(0.0, 1.0);
+
+    if volume == 0.0 {
+        return VowelWeights::default();
+    }
+
+    let Some((f1, f2)) = formants(samples, sample_rate) else {
+        return VowelWeights::default();
+    };
+
+    // Vowels are compared on a log scale, as heard.
+    let mut weights = VOWEL_FORMANTS.map(|(v1, v2)| {
+        let d1 = (f1 / v1).ln() / 0.25;
+        let d2 = (f2 / v2).ln() / 0.2;
+        (-(d1 * d1 + d2 * d2)).exp()
+    });
+
+    let total = weights.iter().sum::<f32>();
+    if total <= f32::EPSILON {
+        return VowelWeights::default();
+    }
+
+    for weight in weights.iter_mut() {
+        *weight *= volume / total;
+    }
+
+    weights
+}
+
+/// The first two formants of a frame, from the peaks of its LPC spectrum.
+fn formants(samples: &[f32], sample_rate: u32) -> Option<(f32, f32)> {
+    // Averaging neighbours filters out most of what decimation would alias.
+    let factor = (sample_rate / ANALYSIS_RATE).max(1) as usize;
+    let rate = sample_rate as f32 / factor as f32;
+
+    let decimated = samples
+        .chunks_exact(factor)
+        .map(|chunk| chunk.iter().sum::<f32>() / factor as f32)
+        .collect::<Vec<_>>();
+
+    if decimated.len() < 2 {
+        return None;
+    }
+
+    // Pre-emphasis and a Hamming window.
+    let n = decimated.len();
+    let frame = (0..n)
+        .map(|i| {
+            let previous = if i > 0 { decimated[i - 1] } else { 0.0 };
+            let window = 0.54 - 0.46 * (2.0 * PI * i as f32 / (n - 1) as f32).cos();
+            (decimated[i] - 0.97 * previous) * window
+        })
+        .collect::<Vec<_>>();
+
+    let order = (rate / 1000.0) as usize + 2;
+    let lpc = lpc(&frame, order)?;
+
+    let envelope = |frequency: f32| {
+        let w = 2.0 * PI * frequency / rate;
+        let (re, im) = lpc.iter().enumerate().fold((0.0, 0.0), |(re, im), (k, a)| {
+            (re + a * (w * k as f32).cos(), im - a * (w * k as f32).sin())
+        });
+        1.0 / (re * re + im * im)
+    };
+
+    let step = 10.0;
+    let max = MAX_FORMANT.min(rate / 2.0);
+    let spectrum = (0..(max / step) as usize)
+        .map(|i| {
+            let frequency = i as f32 * step;
+            (frequency, envelope(frequency))
+        })
+        .collect::<Vec<_>>();
+
+    let peaks = spectrum
+        .windows(3)
+        .filter(|w| w[1].1 > w[0].1 && w[1].1 >= w[2].1 && w[1].0 >= 200.0)
+        .map(|w| w[1])
+        .collect::<Vec<_>>();
+
+    // Pitch harmonics leave small ripples in the envelope, well below the formants.
+    let loudest = peaks.iter().fold(0.0f32, |max, (_, power)| max.max(*power));
+    let mut peaks = peaks
+        .into_iter()
+        .filter(|(_, power)| *power >= loudest / 16.0)
+        .map(|(frequency, _)| frequency);
+
+    let f1 = peaks.next()?;
+    let f2 = peaks.next().unwrap_or(f1);
+
+    Some((f1, f2))
+}
+
+/// Linear prediction coefficients, starting with 1, by the Levinson-Durbin recursion.
+fn lpc(frame: &[f32], order: usize) -> Option<Vec<f32>> {
+    let r = (0..=order)
+        .map(|lag| {
+            frame
+                .iter()
+                .zip(frame.iter().skip(lag))
+                .map(|(a, b)| a * b)
+                .sum::<f32>()
+        })
+        .collect::<Vec<_>>();
+
+    if r[0] <= f32::EPSILON {
+        return None;
+    }
+
+    let mut a = vec![0.0; order + 1];
+    a[0] = 1.0;
+    let mut error = r[0];
+
+    for i in 1..=order {
+        let k = -(0..i).map(|j| a[j] * r[i - j]).sum::<f32>() / error;
+
+        let previous = a.clone();
+        for j in 1..i {
+            a[j] = previous[j] + k * previous[i - j];
+        }
+        a[i] = k;
+
+        error *= 1.0 - k * k;
+        if error <= f32::EPSILON {
+            break;
+        }
+    }
+
+    Some(a)
+}
+
+pub(crate) fn apply_audio_lip_sync(mut avatars: Query<(&AudioLipSync, &mut VrmExpressions)>) {
+    for (lip_sync, mut expressions) in avatars.iter_mut() {
+        set_vowels(&mut expressions, lip_sync.weights());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16000;
+
+    /// A voiced vowel: a 120 Hz pulse train through two formant resonators.
+    fn vowel(f1: f32, f2: f32, seconds: f32) -> Vec<f32> {
+        let len = (RATE as f32 * seconds) as usize;
+        let period = RATE as usize / 120;
+
+        let mut samples = (0..len)
+            .map(|i| if i % period == 0 { 1.0 } else { 0.0 })
+            .collect::<Vec<_>>();
+
+        for formant in [f1, f2] {
+            let r = (-PI * 80.0 / RATE as f32).exp();
+            let c = 2.0 * r * (2.0 * PI * formant / RATE as f32).cos();
+            let (mut y1, mut y2) = (0.0, 0.0);
+
+            for sample in samples.iter_mut() {
+                let y = *sample + c * y1 - r * r * y2;
+                (y2, y1) = (y1, y);
+                *sample = y;
+            }
+        }
+
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        samples.iter().map(|s| s / peak * 0.5).collect()
+    }
+
+    fn loudest(weights: VowelWeights) -> usize {
+        (0..5)
+            .max_by(|a, b| weights[*a].total_cmp(&weights[*b]))
+            .unwrap()
+    }
+
+    #[test]
+    fn vowels_are_estimated_from_formants() {
+        let mut lip_sync = AudioLipSync::new(RATE);
+
+        for (i, (f1, f2)) in VOWEL_FORMANTS.into_iter().enumerate() {
+            lip_sync.push_samples(&vowel(f1, f2, 0.5));
+            assert_eq!(loudest(lip_sync.weights()), i);
+            assert!(lip_sync.weights()[i] > 0.5);
+        }
+
+        lip_sync.push_samples(&vec![0.0; RATE as usize / 2]);
+        assert!(lip_sync.weights().iter().all(|w| *w < 0.01));
+    }
+}
@@ -0,0 +1,43 @@
+//! Lip sync, driving the [`Expression::VOWELS`] of an avatar from speech audio
+//! or from timed phonemes.
+
+use bevy::{animation::animation_player, prelude::*};
+
+use crate::{
+    animation::play_vrm_animations,
+    arkit::map_arkit_expressions,
+    expressions::{apply_expressions, Expression, VrmExpressions},
+    vmc::receive_vmc,
+};
+
+mod audio;
+mod timeline;
+
+pub use audio::{estimate_vowels, AudioLipSync};
+pub use timeline::{TimedPhoneme, Viseme, VisemePlayer, VisemeTimeline};
+
+/// Weight of each vowel, in A, I, U, E, O order.
+pub type VowelWeights = [f32; 5];
+
+/// Drives the mouths of avatars with an [`AudioLipSync`] or a [`VisemePlayer`].
+pub struct LipSyncPlugin;
+
+impl Plugin for LipSyncPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            (audio::apply_audio_lip_sync, timeline::play_visemes)
+                .after(animation_player)
+                .after(play_vrm_animations)
+                .after(receive_vmc)
+                .after(map_arkit_expressions)
+                .before(apply_expressions),
+        );
+    }
+}
+
+fn set_vowels(expressions: &mut VrmExpressions, weights: VowelWeights) {
+    for (expression, weight) in Expression::VOWELS.into_iter().zip(weights) {
+        expressions.set(expression, weight);
+    }
+}
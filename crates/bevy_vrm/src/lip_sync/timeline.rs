@@ -0,0 +1,308 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    animation::{Interpolation, Track},
+    expressions::{Expression, VrmExpressions},
+};
+
+use super::{set_vowels, VowelWeights};
+
+/// Unlabelled gaps between phonemes longer than this close the mouth, in seconds.
+const PAUSE: f32 = 0.1;
+
+/// A mouth shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Viseme {
+    Closed,
+    A,
+    I,
+    U,
+    E,
+    O,
+}
+
+impl Viseme {
+    /// Maps an [ARPAbet](https://en.wikipedia.org/wiki/ARPABET) phoneme, such as `AA1`,
+    /// or a romaji vowel, such as `a`, to its mouth shape.
+    /// Consonants that don't close the lips have no shape of their own.
+    pub fn from_phoneme(phoneme: &str) -> Option<Self> {
+        let phoneme = phoneme.trim_end_matches(|c: char| c.is_ascii_digit());
+
+        let viseme = match phoneme.to_uppercase().as_str() {
+            "" | "SIL" | "SP" | "PAU" | "M" | "B" | "P" => Self::Closed,
+            "AA" | "AE" | "AH" | "AW" | "AY" => Self::A,
+            "IY" | "IH" => Self::I,
+            "UW" | "UH" | "W" => Self::U,
+            "EH" | "EY" | "ER" => Self::E,
+            "AO" | "OW" | "OY" => Self::O,
+            // Romaji vowels are lowercase, unlike ARPAbet.
+            _ => match phoneme {
+                "a" => Self::A,
+                "i" => Self::I,
+                "u" => Self::U,
+                "e" => Self::E,
+                "o" => Self::O,
+                _ => return None,
+            },
+        };
+
+        Some(viseme)
+    }
+
+    fn weights(self) -> VowelWeights {
+        let mut weights = VowelWeights::default();
+
+        let index = match self {
+            Self::Closed => return weights,
+            Self::A => 0,
+            Self::I => 1,
+            Self::U => 2,
+            Self::E => 3,
+            Self::O => 4,
+        };
+
+        weights[index] = 1.0;
+        weights
+    }
+}
+
+/// A phoneme spoken from `start` to `end`, in seconds.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimedPhoneme {
+    pub phoneme: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl TimedPhoneme {
+    pub fn new(phoneme: &str, start: f32, end: f32) -> Self {
+        Self {
+            phoneme: phoneme.to_string(),
+            start,
+            end,
+        }
+    }
+}
+
+/// Mouth shapes over time, peaking in the middle of each phoneme.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VisemeTimeline {
+    /// Time of each mouth shape, in seconds, in order.
+    pub keys: Vec<(f32, Viseme)>,
+}
+
+impl VisemeTimeline {
+    /// Builds a timeline from phonemes, closing the mouth at the start, at the end and in pauses.
+    pub fn from_phonemes(phonemes: &[TimedPhoneme]) -> Self {
+        let mut keys = Vec::new();
+        let mut previous_end = None;
+
+        for phoneme in phonemes {
+            match previous_end {
+                None => keys.push((phoneme.start, Viseme::Closed)),
+                Some(end) if phoneme.start - end > PAUSE => {
+                    keys.push((end, Viseme::Closed));
+                    keys.push((phoneme.start, Viseme::Closed));
+                }
+                _ => {}
+            }
+
+            if let Some(viseme) = Viseme::from_phoneme(&phoneme.phoneme) {
+                keys.push(((phoneme.start + phoneme.end) / 2.0, viseme));
+            }
+
+            previous_end = Some(phoneme.end);
+        }
+
+        if let Some(end) = previous_end {
+            keys.push((end, Viseme::Closed));
+        }
+
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Self { keys }
+    }
+
+    /// Builds a timeline from text spoken from `start` to `end`, with simple grapheme rules.
+    /// Each letter gets the same time, punctuation pauses twice as long, and runs
+    /// of vowels within a word are spoken as the first one.
+    pub fn from_text(text: &str, start: f32, end: f32) -> Self {
+        let mut units = Vec::<(&str, f32)>::new();
+        let mut previous = ' ';
+
+        for c in text.chars() {
+            let lower = c.to_ascii_lowercase();
+
+            let phoneme = match lower {
+                'a' | 'i' | 'u' | 'e' | 'o' if is_vowel(previous) => None,
+                'y' if previous.is_alphabetic() && !is_vowel(previous) => Some("i"),
+                'a' => Some("a"),
+                'i' => Some("i"),
+                'u' => Some("u"),
+                'e' => Some("e"),
+                'o' => Some("o"),
+                'm' | 'b' | 'p' => Some("M"),
+                c if c.is_alphabetic() => Some("-"),
+                '.' | ',' | '!' | '?' | ';' | ':' => {
+                    units.push(("sil", 2.0));
+                    None
+                }
+                _ => None,
+            };
+
+            if let Some(phoneme) = phoneme {
+                units.push((phoneme, 1.0));
+            }
+
+            previous = lower;
+        }
+
+        let total = units.iter().map(|(_, length)| length).sum::<f32>();
+        if total <= 0.0 {
+            return Self::default();
+        }
+
+        let step = (end - start) / total;
+        let mut time = start;
+
+        let phonemes = units
+            .into_iter()
+            .map(|(phoneme, length)| {
+                let phoneme = TimedPhoneme::new(phoneme, time, time + length * step);
+                time = phoneme.end;
+                phoneme
+            })
+            .collect::<Vec<_>>();
+
+        Self::from_phonemes(&phonemes)
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keys.last().map(|(time, _)| *time).unwrap_or_default()
+    }
+
+    /// A, I, U, E and O keyframes, as in
+    /// [`VrmAnimation::expressions`](crate::animation::VrmAnimation::expressions).
+    pub fn expressions(&self) -> HashMap<Expression, Track<f32>> {
+        let times = self.keys.iter().map(|(time, _)| *time).collect::<Vec<_>>();
+        let weights = self
+            .keys
+            .iter()
+            .map(|(_, viseme)| viseme.weights())
+            .collect::<Vec<_>>();
+
+        Expression::VOWELS
+            .into_iter()
+            .enumerate()
+            .map(|(i, expression)| {
+                let values = weights.iter().map(|w| w[i]).collect();
+                let mut track = Track::new(times.clone(), values, Interpolation::Linear);
+                track.reduce(0.0);
+                (expression, track)
+            })
+            .collect()
+    }
+}
+
+/// Plays a [`VisemeTimeline`] on the avatar's [`VrmExpressions`], see
+/// [`LipSyncPlugin`](super::LipSyncPlugin).
+#[derive(Component, Clone, Debug)]
+pub struct VisemePlayer {
+    expressions: HashMap<Expression, Track<f32>>,
+    duration: f32,
+    /// Current time in seconds.
+    pub elapsed: f32,
+    pub speed: f32,
+    pub paused: bool,
+}
+
+impl VisemePlayer {
+    pub fn new(timeline: &VisemeTimeline) -> Self {
+        Self {
+            expressions: timeline.expressions(),
+            duration: timeline.duration(),
+            elapsed: 0.0,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    fn weights(&self) -> VowelWeights {
+        Expression::VOWELS.map(|expression| {
+            self.expressions
+                .get(&expression)
+                .and_then(|track| track.sample(self.elapsed))
+                .unwrap_or_default()
+        })
+    }
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'i' | 'u' | 'e' | 'o')
+}
+
+pub(crate) fn play_visemes(
+    time: Res<Time>,
+    mut avatars: Query<(&mut VisemePlayer, &mut VrmExpressions)>,
+) {
+    for (mut player, mut expressions) in avatars.iter_mut() {
+        if !player.paused {
+            player.elapsed += time.delta_seconds() * player.speed;
+        }
+
+        player.elapsed = player.elapsed.clamp(0.0, player.duration);
+
+        set_vowels(&mut expressions, player.weights());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phonemes_become_vowel_keyframes() {
+        let timeline = VisemeTimeline::from_phonemes(&[
+            TimedPhoneme::new("HH", 0.0, 0.1),
+            TimedPhoneme::new("EH1", 0.1, 0.3),
+            TimedPhoneme::new("L", 0.3, 0.4),
+            TimedPhoneme::new("OW0", 0.4, 0.6),
+            TimedPhoneme::new("a", 1.0, 1.2),
+        ]);
+
+        let mut player = VisemePlayer::new(&timeline);
+        let mut sample = |time| {
+            player.elapsed = time;
+            player.weights()
+        };
+
+        assert_eq!(sample(0.0), [0.0; 5]);
+        assert_eq!(sample(0.2), [0.0, 0.0, 0.0, 1.0, 0.0]);
+        assert_eq!(sample(0.5), [0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert_eq!(sample(0.8), [0.0; 5]);
+        assert_eq!(sample(1.1), [1.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(sample(1.2), [0.0; 5]);
+
+        let text = VisemeTimeline::from_text("Papa, boo!", 0.0, 1.0);
+        let visemes = text.keys.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        assert_eq!(
+            visemes,
+            [
+                Viseme::Closed,
+                Viseme::Closed,
+                Viseme::A,
+                Viseme::Closed,
+                Viseme::A,
+                Viseme::Closed,
+                Viseme::Closed,
+                Viseme::O,
+                Viseme::Closed,
+                Viseme::Closed,
+            ]
+        );
+    }
+}
@@ -9,15 +9,46 @@ use bevy_gltf_kun::import::gltf::{
     loader::{GltfError, GltfLoader},
     GltfKun,
 };
+use gltf_kun::graph::{
+    gltf::{GltfDocument, GltfWeight},
+    ByteNode, Extensions, Weight,
+};
 use thiserror::Error;
 
-use crate::extensions::VrmExtensions;
+use crate::{extensions::VrmExtensions, license::VrmLicense};
 
 #[derive(Asset, TypePath, Debug)]
 pub struct Vrm {
     pub gltf: GltfKun,
 }
 
+impl Vrm {
+    /// Usage permissions from the VRM 0.0 meta, if the file has a VRM 0.0 extension.
+    /// VRM 1.0 files are not imported yet, their `VRMC_vrm` meta can be converted
+    /// with [`VrmLicense::from`].
+    pub fn license(&self) -> Option<VrmLicense> {
+        Some(VrmLicense::from(&self.vrm0()?.meta))
+    }
+
+    /// Humanoid settings such as the twist distribution, if the file has a VRM extension.
+    pub fn humanoid(&self) -> Option<gltf_kun_vrm::vrm0::weight::Humanoid> {
+        Some(self.vrm0()?.humanoid)
+    }
+
+    fn vrm0(&self) -> Option<gltf_kun_vrm::vrm0::weight::VrmWeight> {
+        let graph = &self.gltf.graph;
+
+        let doc = graph.node_indices().find(|n| {
+            let weight = graph.node_weight(*n);
+            matches!(weight, Some(Weight::Gltf(GltfWeight::Document)))
+        })?;
+
+        let ext = GltfDocument(doc).get_extension::<gltf_kun_vrm::vrm0::Vrm>(graph)?;
+
+        Some(ext.read(graph))
+    }
+}
+
 #[derive(Default)]
 pub struct VrmLoader(pub GltfLoader<VrmExtensions>);
 
@@ -0,0 +1,15 @@
+use bevy::{prelude::*, scene::InstanceId, utils::HashMap};
+
+use crate::GltfNodeIndex;
+
+/// Maps glTF node indices to the entities spawned for them in a single scene instance.
+pub fn instance_node_entities(
+    scene_manager: &SceneSpawner,
+    instance: InstanceId,
+    node_indices: &Query<&GltfNodeIndex>,
+) -> HashMap<usize, Entity> {
+    scene_manager
+        .iter_instance_entities(instance)
+        .filter_map(|entity| node_indices.get(entity).ok().map(|index| (index.0, entity)))
+        .collect()
+}
@@ -0,0 +1,289 @@
+//! Layering several [`HumanPose`]s on an avatar before IK.
+
+use bevy::{animation::animation_player, prelude::*, utils::HashSet};
+use serde_vrm::vrm0::BoneName;
+
+use crate::ik::{IkSet, JointLimits};
+
+use super::{HumanPose, HumanPoses};
+
+/// Evaluates [`PoseStack`]s after animation and before IK.
+pub struct PoseStackPlugin;
+
+impl Plugin for PoseStackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            evaluate_pose_stacks
+                .after(animation_player)
+                .before(IkSet::Body),
+        );
+    }
+}
+
+/// The humanoid bones a [`PoseLayer`] affects.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BoneMask {
+    /// Only these bones.
+    Only(HashSet<BoneName>),
+    /// Every bone except these.
+    Except(HashSet<BoneName>),
+}
+
+impl BoneMask {
+    /// The hips, legs and feet.
+    pub fn lower_body() -> Self {
+        Self::Only(LOWER_BODY.into_iter().collect())
+    }
+
+    /// Everything from the spine up, including the arms and fingers.
+    pub fn upper_body() -> Self {
+        Self::Except(LOWER_BODY.into_iter().collect())
+    }
+
+    pub fn contains(&self, bone: &BoneName) -> bool {
+        match self {
+            Self::Only(bones) => bones.contains(bone),
+            Self::Except(bones) => !bones.contains(bone),
+        }
+    }
+}
+
+impl FromIterator<BoneName> for BoneMask {
+    fn from_iter<T: IntoIterator<Item = BoneName>>(iter: T) -> Self {
+        Self::Only(iter.into_iter().collect())
+    }
+}
+
+const LOWER_BODY: [BoneName; 9] = [
+    BoneName::Hips,
+    BoneName::LeftUpperLeg,
+    BoneName::LeftLowerLeg,
+    BoneName::LeftFoot,
+    BoneName::LeftToes,
+    BoneName::RightUpperLeg,
+    BoneName::RightLowerLeg,
+    BoneName::RightFoot,
+    BoneName::RightToes,
+];
+
+/// How a [`PoseLayer`] combines with the layers below it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Blends towards the layer's pose.
+    #[default]
+    Override,
+    /// Adds the layer's rotations on top, where the T-pose adds nothing.
+    /// See [`HumanPose::difference`].
+    Additive,
+}
+
+#[derive(Clone, Debug)]
+pub struct PoseLayer {
+    pub pose: HumanPose,
+    /// Influence of the layer, from 0 to 1.
+    pub weight: f32,
+    pub mode: BlendMode,
+    /// Bones affected by the layer, or all of them.
+    pub mask: Option<BoneMask>,
+}
+
+impl PoseLayer {
+    pub fn new(pose: HumanPose, mode: BlendMode) -> Self {
+        Self {
+            pose,
+            weight: 1.0,
+            mode,
+            mask: None,
+        }
+    }
+
+    pub fn with_weight(mut self, weight: f32) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    pub fn with_mask(mut self, mask: BoneMask) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+}
+
+/// Poses blended in order onto an avatar each frame, then clamped to its [`JointLimits`].
+/// See [`PoseStackPlugin`].
+#[derive(Component, Clone, Debug, Default)]
+pub struct PoseStack {
+    /// The pose the layers are applied on, such as locomotion.
+    /// Without one, the animated pose is used.
+    pub base: Option<HumanPose>,
+    /// Layers from the bottom up.
+    pub layers: Vec<PoseLayer>,
+}
+
+impl HumanPose {
+    /// The additive pose that turns `reference` into this pose.
+    pub fn difference(&self, reference: &HumanPose) -> HumanPose {
+        let rotations = self
+            .rotations
+            .iter()
+            .map(|(bone, rotation)| {
+                let reference = reference
+                    .rotations
+                    .get(bone)
+                    .copied()
+                    .unwrap_or(Quat::IDENTITY);
+                (bone.clone(), (reference.inverse() * *rotation).normalize())
+            })
+            .collect();
+
+        HumanPose {
+            hips_position: self.hips_position - reference.hips_position,
+            rotations,
+        }
+    }
+
+    /// Blends towards `other` by `weight`, for bones in `mask`.
+    /// Bones missing from this pose blend from the T-pose.
+    pub fn blend(&mut self, other: &HumanPose, weight: f32, mask: Option<&BoneMask>) {
+        let weight = weight.clamp(0.0, 1.0);
+        let masked = |bone: &BoneName| mask.is_none_or(|mask| mask.contains(bone));
+
+        if masked(&BoneName::Hips) {
+            self.hips_position = self.hips_position.lerp(other.hips_position, weight);
+        }
+
+        for (bone, rotation) in other.rotations.iter().filter(|(bone, _)| masked(bone)) {
+            let current = self.rotations.entry(bone.clone()).or_insert(Quat::IDENTITY);
+            *current = current.slerp(*rotation, weight);
+        }
+    }
+
+    /// Adds the `additive` pose by `weight`, for bones in `mask`.
+    pub fn add(&mut self, additive: &HumanPose, weight: f32, mask: Option<&BoneMask>) {
+        let weight = weight.clamp(0.0, 1.0);
+        let masked = |bone: &BoneName| mask.is_none_or(|mask| mask.contains(bone));
+
+        if masked(&BoneName::Hips) {
+            self.hips_position += additive.hips_position * weight;
+        }
+
+        for (bone, rotation) in additive.rotations.iter().filter(|(bone, _)| masked(bone)) {
+            let current = self.rotations.entry(bone.clone()).or_insert(Quat::IDENTITY);
+            *current = (*current * Quat::IDENTITY.slerp(*rotation, weight)).normalize();
+        }
+    }
+}
+
+impl PoseStack {
+    /// Blends the layers onto `base`.
+    pub fn evaluate(&self, base: HumanPose) -> HumanPose {
+        let mut pose = base;
+
+        for layer in self.layers.iter() {
+            match layer.mode {
+                BlendMode::Override => pose.blend(&layer.pose, layer.weight, layer.mask.as_ref()),
+                BlendMode::Additive => pose.add(&layer.pose, layer.weight, layer.mask.as_ref()),
+            }
+        }
+
+        pose
+    }
+}
+
+pub(crate) fn evaluate_pose_stacks(
+    mut poses: HumanPoses,
+    stacks: Query<(Entity, &PoseStack, Option<&JointLimits>)>,
+) {
+    for (entity, stack, limits) in stacks.iter() {
+        if stack.base.is_none() && stack.layers.is_empty() {
+            continue;
+        }
+
+        let base = match &stack.base {
+            Some(base) => base.clone(),
+            None => match poses.read_pose(entity) {
+                Some(pose) => pose,
+                None => continue,
+            },
+        };
+
+        let mut pose = stack.evaluate(base);
+
+        if let Some(limits) = limits {
+            poses.clamp_pose(entity, &mut pose, limits);
+        }
+
+        poses.apply_pose(entity, &pose);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::utils::HashMap;
+
+    use super::*;
+
+    fn pose(rotations: &[(BoneName, Quat)]) -> HumanPose {
+        HumanPose {
+            hips_position: Vec3::Y,
+            rotations: rotations.iter().cloned().collect::<HashMap<_, _>>(),
+        }
+    }
+
+    #[test]
+    fn masked_override_only_changes_masked_bones() {
+        let base = pose(&[
+            (BoneName::Spine, Quat::IDENTITY),
+            (BoneName::LeftUpperLeg, Quat::IDENTITY),
+        ]);
+        let wave = pose(&[
+            (BoneName::Spine, Quat::from_rotation_x(1.0)),
+            (BoneName::LeftUpperLeg, Quat::from_rotation_x(1.0)),
+        ]);
+
+        let stack = PoseStack {
+            base: None,
+            layers: vec![PoseLayer::new(wave, BlendMode::Override)
+                .with_weight(0.5)
+                .with_mask(BoneMask::upper_body())],
+        };
+
+        let result = stack.evaluate(base);
+
+        assert!(
+            result.rotations[&BoneName::Spine].angle_between(Quat::from_rotation_x(0.5)) < 0.001
+        );
+        assert_eq!(result.rotations[&BoneName::LeftUpperLeg], Quat::IDENTITY);
+    }
+
+    #[test]
+    fn missing_bones_blend_from_the_t_pose() {
+        let mut base = pose(&[(BoneName::Spine, Quat::IDENTITY)]);
+        let wave = pose(&[
+            (BoneName::Spine, Quat::from_rotation_x(1.0)),
+            (BoneName::LeftUpperArm, Quat::from_rotation_z(1.0)),
+        ]);
+
+        base.blend(&wave, 0.25, None);
+
+        for (bone, expected) in [
+            (BoneName::Spine, Quat::from_rotation_x(0.25)),
+            (BoneName::LeftUpperArm, Quat::from_rotation_z(0.25)),
+        ] {
+            assert!(base.rotations[&bone].angle_between(expected) < 0.001);
+        }
+    }
+
+    #[test]
+    fn additive_difference_restores_pose() {
+        let reference = pose(&[(BoneName::Head, Quat::from_rotation_y(0.3))]);
+        let target = pose(&[(BoneName::Head, Quat::from_rotation_y(0.8))]);
+
+        let mut result = reference.clone();
+        result.add(&target.difference(&reference), 1.0, None);
+
+        assert!(
+            result.rotations[&BoneName::Head].angle_between(Quat::from_rotation_y(0.8)) < 0.001
+        );
+    }
+}
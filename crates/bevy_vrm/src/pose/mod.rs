@@ -0,0 +1,291 @@
+//! Poses in the normalized humanoid space, shared between any VRM avatar.
+//!
+//! Rotations are relative to the avatar's T-pose, with every bone treated as if it had
+//! no rotation at rest. A pose read from one avatar can be applied to any other,
+//! regardless of how their bones are oriented.
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    animation::leg_length,
+    ik::{rest_pose, BoneRest, JointLimits},
+    retargeting::VrmRetargetingInitialized,
+    HumanoidBones,
+};
+
+mod blend;
+
+pub(crate) use blend::evaluate_pose_stacks;
+pub use blend::{BlendMode, BoneMask, PoseLayer, PoseStack, PoseStackPlugin};
+
+/// A pose of the humanoid bones, relative to the normalized T-pose.
+/// Everything is in avatar space, with +Y up and the avatar facing +Z.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HumanPose {
+    /// Position of the hips relative to the avatar root.
+    pub hips_position: Vec3,
+    /// Rotation of each bone relative to its humanoid parent, or to the avatar for the hips.
+    /// Bones without a rotation keep their current pose when applied.
+    pub rotations: HashMap<BoneName, Quat>,
+}
+
+/// Reads and applies [`HumanPose`]s on avatars.
+/// Avatars must be retargeted and have their [`BoneRest`]s.
+#[derive(SystemParam)]
+pub struct HumanPoses<'w, 's> {
+    avatars: Query<'w, 's, &'static HumanoidBones, With<VrmRetargetingInitialized>>,
+    parents: Query<'w, 's, &'static Parent>,
+    bone_rests: Query<'w, 's, &'static BoneRest>,
+    transforms: Query<'w, 's, &'static mut Transform>,
+}
+
+impl HumanPoses<'_, '_> {
+    /// The current pose of `avatar`, or [`None`] if it is not ready.
+    pub fn read_pose(&self, avatar: Entity) -> Option<HumanPose> {
+        let bones = self.avatars.get(avatar).ok()?;
+        let names = bone_names(bones);
+
+        let hips = bones.0.get(&BoneName::Hips)?;
+        let mut pose = HumanPose {
+            hips_position: self.current_pose(*hips, avatar)?.translation,
+            rotations: HashMap::new(),
+        };
+
+        for (name, entity) in bones.0.iter() {
+            let Some(normalized) = self.normalized(*entity, avatar) else {
+                continue;
+            };
+
+            let parent = match self.humanoid_parent(*entity, avatar, &names) {
+                Some(parent) => match self.normalized(parent, avatar) {
+                    Some(parent) => parent,
+                    None => continue,
+                },
+                None => Quat::IDENTITY,
+            };
+
+            pose.rotations
+                .insert(name.clone(), (parent.inverse() * normalized).normalize());
+        }
+
+        Some(pose)
+    }
+
+    /// Position of the hips relative to the avatar root in the rest pose.
+    pub fn rest_hips_position(&self, avatar: Entity) -> Option<Vec3> {
+        let bones = self.avatars.get(avatar).ok()?;
+        let hips = bones.0.get(&BoneName::Hips)?;
+        Some(rest_pose(*hips, avatar, &self.parents, &self.bone_rests)?.translation)
+    }
+
+    /// Length of the left leg in the rest pose, from the upper leg to the foot.
+    pub fn rest_leg_length(&self, avatar: Entity) -> Option<f32> {
+        let bones = self.avatars.get(avatar).ok()?;
+        rest_leg_length(avatar, bones, &self.parents, &self.bone_rests)
+    }
+
+    /// Poses `avatar` as `pose`. Bones missing from either are left unchanged.
+    pub fn apply_pose(&mut self, avatar: Entity, pose: &HumanPose) {
+        let Ok(bones) = self.avatars.get(avatar) else {
+            return;
+        };
+        let names = bone_names(bones);
+
+        // Parents are posed first, so their children are placed relative to the new pose.
+        let mut ordered = bones
+            .0
+            .iter()
+            .filter_map(|(name, entity)| Some((name, *entity, pose.rotations.get(name)?)))
+            .map(|(name, entity, rotation)| (self.depth(entity, avatar), name, entity, *rotation))
+            .collect::<Vec<_>>();
+        ordered.sort_by_key(|(depth, ..)| *depth);
+
+        for (_, name, entity, rotation) in ordered {
+            let parent = match self.humanoid_parent(entity, avatar, &names) {
+                Some(parent) => match self.normalized(parent, avatar) {
+                    Some(parent) => parent,
+                    None => continue,
+                },
+                None => Quat::IDENTITY,
+            };
+
+            let (Some(rest), Ok(parent_entity)) = (
+                rest_pose(entity, avatar, &self.parents, &self.bone_rests),
+                self.parents.get(entity).map(|parent| parent.get()),
+            ) else {
+                continue;
+            };
+
+            let Some(parent_pose) = self.current_pose(parent_entity, avatar) else {
+                continue;
+            };
+
+            let global = parent * rotation * rest.rotation;
+
+            let Ok(mut transform) = self.transforms.get_mut(entity) else {
+                continue;
+            };
+
+            transform.rotation = (parent_pose.rotation.inverse() * global).normalize();
+
+            if *name == BoneName::Hips {
+                transform.translation =
+                    Transform::from_matrix(parent_pose.compute_matrix().inverse())
+                        .transform_point(pose.hips_position);
+            }
+        }
+    }
+
+    /// Clamps the rotations of `pose` to `limits`, using the rest pose of `avatar`.
+    pub fn clamp_pose(&self, avatar: Entity, pose: &mut HumanPose, limits: &JointLimits) {
+        let Ok(bones) = self.avatars.get(avatar) else {
+            return;
+        };
+
+        for (bone, rotation) in pose.rotations.iter_mut() {
+            let Some(rest) = bones
+                .0
+                .get(bone)
+                .and_then(|entity| rest_pose(*entity, avatar, &self.parents, &self.bone_rests))
+            else {
+                continue;
+            };
+
+            // The limits are relative to the bone's own rest frame.
+            let local = rest.rotation.inverse() * *rotation * rest.rotation;
+            let clamped = limits.clamp(bone, local, Quat::IDENTITY, Vec3::Y);
+            *rotation = rest.rotation * clamped * rest.rotation.inverse();
+        }
+    }
+
+    /// Current pose of `entity` relative to `avatar`.
+    fn current_pose(&self, entity: Entity, avatar: Entity) -> Option<Transform> {
+        let mut pose = Transform::IDENTITY;
+        let mut current = entity;
+
+        while current != avatar {
+            pose = *self.transforms.get(current).ok()? * pose;
+            current = self.parents.get(current).ok()?.get();
+        }
+
+        Some(pose)
+    }
+
+    /// Rotation of `entity` relative to `avatar`, with the rest rotation removed.
+    fn normalized(&self, entity: Entity, avatar: Entity) -> Option<Quat> {
+        let current = self.current_pose(entity, avatar)?;
+        let rest = rest_pose(entity, avatar, &self.parents, &self.bone_rests)?;
+
+        Some(current.rotation * rest.rotation.inverse())
+    }
+
+    /// The closest ancestor of `entity` that is a humanoid bone.
+    fn humanoid_parent(
+        &self,
+        entity: Entity,
+        avatar: Entity,
+        names: &HashMap<Entity, BoneName>,
+    ) -> Option<Entity> {
+        self.parents
+            .iter_ancestors(entity)
+            .take_while(|ancestor| *ancestor != avatar)
+            .find(|ancestor| names.contains_key(ancestor))
+    }
+
+    fn depth(&self, entity: Entity, avatar: Entity) -> usize {
+        self.parents
+            .iter_ancestors(entity)
+            .take_while(|ancestor| *ancestor != avatar)
+            .count()
+    }
+}
+
+/// Length of the left leg of `avatar` in the rest pose.
+pub(crate) fn rest_leg_length(
+    avatar: Entity,
+    bones: &HumanoidBones,
+    parents: &Query<&Parent>,
+    bone_rests: &Query<&BoneRest>,
+) -> Option<f32> {
+    let [upper_leg, lower_leg, foot] = [
+        BoneName::LeftUpperLeg,
+        BoneName::LeftLowerLeg,
+        BoneName::LeftFoot,
+    ]
+    .map(|bone| {
+        bones.0.get(&bone).and_then(|entity| {
+            rest_pose(*entity, avatar, parents, bone_rests).map(|rest| rest.translation)
+        })
+    });
+
+    Some(leg_length(upper_leg?, lower_leg?, foot?))
+}
+
+fn bone_names(bones: &HumanoidBones) -> HashMap<Entity, BoneName> {
+    bones
+        .0
+        .iter()
+        .map(|(name, entity)| (*entity, name.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::SystemState;
+
+    use super::*;
+
+    /// An avatar with hips and a spine, where the spine is rolled at rest.
+    fn avatar(world: &mut World, spine_rest: Quat) -> Entity {
+        let hips_rest = Transform::from_xyz(0.0, 1.0, 0.0);
+        let spine_rest = Transform::from_xyz(0.0, 0.1, 0.0).with_rotation(spine_rest);
+
+        let hips = world.spawn((hips_rest, BoneRest(hips_rest))).id();
+        let spine = world.spawn((spine_rest, BoneRest(spine_rest))).id();
+        world.entity_mut(hips).add_child(spine);
+
+        let mut bones = HumanoidBones::default();
+        bones.0.insert(BoneName::Hips, hips);
+        bones.0.insert(BoneName::Spine, spine);
+
+        let avatar = world
+            .spawn((Transform::IDENTITY, bones, VrmRetargetingInitialized))
+            .id();
+        world.entity_mut(avatar).add_child(hips);
+
+        avatar
+    }
+
+    #[test]
+    fn pose_transfers_between_bone_orientations() {
+        let mut world = World::new();
+        let source = avatar(&mut world, Quat::IDENTITY);
+        let target = avatar(&mut world, Quat::from_rotation_y(1.0));
+
+        let mut state = SystemState::<HumanPoses>::new(&mut world);
+
+        let mut pose = HumanPose {
+            hips_position: Vec3::new(0.1, 0.9, 0.0),
+            rotations: HashMap::new(),
+        };
+        pose.rotations
+            .insert(BoneName::Hips, Quat::from_rotation_y(0.5));
+        pose.rotations
+            .insert(BoneName::Spine, Quat::from_rotation_x(0.3));
+
+        let mut poses = state.get_mut(&mut world);
+        poses.apply_pose(source, &pose);
+        poses.apply_pose(target, &pose);
+
+        for avatar in [source, target] {
+            let read = poses.read_pose(avatar).unwrap();
+
+            assert!(read.hips_position.distance(pose.hips_position) < 0.0001);
+
+            for (bone, rotation) in pose.rotations.iter() {
+                assert!(read.rotations[bone].angle_between(*rotation) < 0.0001);
+            }
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use crate::humanoid_bones::HumanoidBonesInitialized;
 
-use crate::HumanoidBones;
+use crate::{HumanoidBones, VrmSet};
 use bevy::math::Affine3A;
 
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
 use bevy::render::mesh::skinning::{SkinnedMesh, SkinnedMeshInverseBindposes};
 
-use bevy::utils::HashMap;
+use bevy::utils::{HashMap, HashSet};
 
 use serde_vrm::vrm0::BoneName;
 
@@ -21,30 +22,63 @@ impl Plugin for VrmRetargetingPlugin {
             Update,
             (
                 retarget_vrm,
-                flip_global_transforms,
-                bevy::transform::systems::propagate_transforms,
-                bevy::transform::systems::sync_simple_transforms,
-                flip_meshes,
+                instance_skins,
+                flip_vrm,
                 bevy::transform::systems::propagate_transforms,
                 bevy::transform::systems::sync_simple_transforms,
             )
-                .chain(),
+                .chain()
+                .in_set(VrmSet::Retarget),
         );
+        app.init_resource::<VrmRetargetingEnabled>();
         app.add_event::<RunBoneRestEvent>();
         app.add_event::<ReadyForNext>();
     }
 }
 
+/// Present when [`VrmRetargetingPlugin`] is added.
+/// Avatars are then only ready once they have been retargeted.
+#[derive(Resource, Default)]
+pub struct VrmRetargetingEnabled;
+
 #[derive(Component)]
 pub struct VrmRetargetingInitialized;
 
+/// Frames to wait after the humanoid bones are mapped before flipping an avatar.
+const FLIP_DELAY_FRAMES: u32 = 80;
+
+#[derive(Component, Default)]
+pub struct VrmFlipTimer(u32);
+
+#[derive(Component)]
+pub struct VrmFlipped;
+
+/// Sent once an avatar has been retargeted, to record the rest pose of its bones.
 #[derive(Event)]
-pub struct RunBoneRestEvent;
+pub struct RunBoneRestEvent(pub Entity);
+
+/// Limb bones rotated to match [`SkeletonProfileHumanoid`], parents before children.
+const RETARGETED_BONES: [BoneName; 14] = [
+    BoneName::LeftShoulder,
+    BoneName::LeftUpperArm,
+    BoneName::LeftLowerArm,
+    BoneName::LeftHand,
+    BoneName::RightShoulder,
+    BoneName::RightUpperArm,
+    BoneName::RightLowerArm,
+    BoneName::RightHand,
+    BoneName::LeftUpperLeg,
+    BoneName::LeftLowerLeg,
+    BoneName::LeftFoot,
+    BoneName::RightUpperLeg,
+    BoneName::RightLowerLeg,
+    BoneName::RightFoot,
+];
 
 pub fn retarget_vrm(
     mut commands: Commands,
-    mut vrm: Query<
-        (Entity, &HumanoidBones),
+    vrm: Query<
+        &HumanoidBones,
         (
             Without<VrmRetargetingInitialized>,
             With<HumanoidBonesInitialized>,
@@ -57,98 +91,140 @@ pub fn retarget_vrm(
     mut event_writer: EventWriter<RunBoneRestEvent>,
     mut event_reader: EventReader<ReadyForNext>,
 ) {
-    if event_reader.is_empty() {
-        return;
-    } else {
-        for _ in event_reader.read() {}
-    }
-    println!("awa");
-    for (entity, humanoid_bones) in vrm.iter_mut() {
-        let left_shoulder = *humanoid_bones.0.get(&BoneName::LeftShoulder).unwrap();
-        let left_upper_arm = *humanoid_bones.0.get(&BoneName::LeftUpperArm).unwrap();
-        let left_lower_arm = *humanoid_bones.0.get(&BoneName::LeftLowerArm).unwrap();
-        let left_hand = *humanoid_bones.0.get(&BoneName::LeftHand).unwrap();
-
-        let shoulder_transform = Transform::from_rotation(Quat::from_xyzw(0.5, 0.5, 0.5, -0.5));
-        let upper_arm_transform = Transform::from_rotation(Quat::from_xyzw(0.0, 1.0, 0.0, 0.0));
-        let lower_arm_transform =
-            Transform::from_rotation(Quat::from_xyzw(0.0, -0.708, 0.0, 0.707));
-        let hand_transform = Transform::from_rotation(Quat::from_xyzw(0.0, 0.708, 0.0, 0.707));
-        retarget_entity(
-            shoulder_transform.rotation,
-            left_shoulder,
-            &skinned_meshes,
-            &mut skinned_mesh_inverse_bindposes,
-            &children,
-            &mut local_transforms,
-        );
-        retarget_entity(
-            upper_arm_transform.rotation,
-            left_upper_arm,
-            &skinned_meshes,
-            &mut skinned_mesh_inverse_bindposes,
-            &children,
-            &mut local_transforms,
-        );
-        retarget_entity(
-            lower_arm_transform.rotation,
-            left_lower_arm,
-            &skinned_meshes,
-            &mut skinned_mesh_inverse_bindposes,
-            &children,
-            &mut local_transforms,
-        );
-        retarget_entity(
-            hand_transform.rotation,
-            left_hand,
-            &skinned_meshes,
-            &mut skinned_mesh_inverse_bindposes,
-            &children,
-            &mut local_transforms,
-        );
+    for ReadyForNext(entity) in event_reader.read() {
+        let entity = *entity;
+
+        let Ok(humanoid_bones) = vrm.get(entity) else {
+            continue;
+        };
+
+        let profile = SkeletonProfileHumanoid::default();
+
+        for bone in RETARGETED_BONES {
+            let (Some(entity), Some(rest)) = (humanoid_bones.0.get(&bone), profile.get(&bone))
+            else {
+                continue;
+            };
+
+            retarget_entity(
+                rest.rotation,
+                *entity,
+                &skinned_meshes,
+                &mut skinned_mesh_inverse_bindposes,
+                &children,
+                &mut local_transforms,
+            );
+        }
 
         commands.entity(entity).insert(VrmRetargetingInitialized);
-        event_writer.send(RunBoneRestEvent);
+        event_writer.send(RunBoneRestEvent(entity));
     }
 }
 
+/// Sent once an avatar has been flipped and is ready to be retargeted.
 #[derive(Event)]
-pub struct ReadyForNext;
+pub struct ReadyForNext(pub Entity);
+
+/// Meshes and skins of the avatars, edited in place when flipping them.
+#[derive(SystemParam)]
+struct AvatarSkins<'w, 's> {
+    skinned_meshes: Query<'w, 's, &'static SkinnedMesh>,
+    inverse_bindposes: ResMut<'w, Assets<SkinnedMeshInverseBindposes>>,
+    mesh_handles: Query<'w, 's, &'static Handle<Mesh>>,
+    meshes: ResMut<'w, Assets<Mesh>>,
+}
 
-fn flip_global_transforms(
+/// Gives each avatar its own copy of its meshes and inverse bindposes before it is flipped,
+/// so avatars spawned from the same [`Vrm`](crate::loader::Vrm) are only flipped once.
+fn instance_skins(
+    mut commands: Commands,
+    avatars: Query<
+        Entity,
+        (
+            With<HumanoidBonesInitialized>,
+            Without<VrmFlipTimer>,
+            Without<VrmFlipped>,
+        ),
+    >,
+    children: Query<&Children>,
+    mut mesh_handles: Query<&mut Handle<Mesh>>,
+    mut skinned_meshes: Query<&mut SkinnedMesh>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut inverse_bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
+) {
+    for entity in avatars.iter() {
+        let mut cloned_meshes = HashMap::new();
+        let mut cloned_bindposes = HashMap::new();
+
+        for child in children.iter_descendants(entity) {
+            if let Ok(mut handle) = mesh_handles.get_mut(child) {
+                let cloned = cloned_meshes
+                    .entry(handle.id())
+                    .or_insert_with(|| meshes.get(&*handle).cloned().map(|m| meshes.add(m)));
+
+                if let Some(cloned) = cloned {
+                    *handle = cloned.clone();
+                }
+            }
+
+            if let Ok(mut skinned_mesh) = skinned_meshes.get_mut(child) {
+                let cloned = cloned_bindposes
+                    .entry(skinned_mesh.inverse_bindposes.id())
+                    .or_insert_with(|| {
+                        let bindposes = inverse_bindposes.get(&skinned_mesh.inverse_bindposes)?;
+                        let bindposes = SkinnedMeshInverseBindposes::from(bindposes.to_vec());
+                        Some(inverse_bindposes.add(bindposes))
+                    });
+
+                if let Some(cloned) = cloned {
+                    skinned_mesh.inverse_bindposes = cloned.clone();
+                }
+            }
+        }
+
+        commands.entity(entity).insert(VrmFlipTimer::default());
+    }
+}
+
+fn flip_vrm(
+    mut commands: Commands,
+    mut avatars: Query<(Entity, &HumanoidBones, &mut VrmFlipTimer), Without<VrmFlipped>>,
     mut query: Query<&mut Transform>,
     children: Query<&Children>,
-    humanoid_bones: Query<&HumanoidBones>,
-    mut local: Local<i32>,
+    mut skins: AvatarSkins,
     mut event_writer: EventWriter<ReadyForNext>,
-    skinned_meshes: Query<&SkinnedMesh>,
-    mut skinned_mesh_inverse_bindposes: ResMut<Assets<SkinnedMeshInverseBindposes>>,
-    names: Query<&Name>,
 ) {
-    if *local != 80 {
-        *local += 1;
-        return;
-    }
-    *local += 1;
-    let hips = humanoid_bones.single().0.get(&BoneName::Hips).unwrap();
-
-    set_things(
-        *hips,
-        &mut query,
-        &skinned_meshes,
-        &mut skinned_mesh_inverse_bindposes,
-        &names,
-    );
-    for child in children.iter_descendants(*hips) {
+    for (entity, humanoid_bones, mut timer) in avatars.iter_mut() {
+        if timer.0 < FLIP_DELAY_FRAMES {
+            timer.0 += 1;
+            continue;
+        }
+
+        commands.entity(entity).insert(VrmFlipped);
+
+        let Some(hips) = humanoid_bones.0.get(&BoneName::Hips) else {
+            continue;
+        };
+
         set_things(
-            child,
+            *hips,
             &mut query,
-            &skinned_meshes,
-            &mut skinned_mesh_inverse_bindposes,
-            &names,
+            &skins.skinned_meshes,
+            &mut skins.inverse_bindposes,
         );
+        for child in children.iter_descendants(*hips) {
+            set_things(
+                child,
+                &mut query,
+                &skins.skinned_meshes,
+                &mut skins.inverse_bindposes,
+            );
+        }
+
+        flip_meshes(entity, &children, &skins.mesh_handles, &mut skins.meshes);
+
+        event_writer.send(ReadyForNext(entity));
     }
-    event_writer.send(ReadyForNext);
 }
 
 fn set_things(
@@ -156,7 +232,6 @@ fn set_things(
     transforms: &mut Query<&mut Transform>,
     skinned_meshes: &Query<&SkinnedMesh>,
     skinned_mesh_inverse_bindposes: &mut Assets<SkinnedMeshInverseBindposes>,
-    _names: &Query<&Name>,
 ) {
     let one_eighty = Mat4::from_cols_slice(&[
         -1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
@@ -174,23 +249,23 @@ fn set_things(
 }
 
 fn flip_meshes(
-    humanoid_bones: Query<Entity, With<HumanoidBones>>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    query: Query<&Handle<Mesh>>,
-    mut local: Local<i32>,
-    _event_writer: EventWriter<ReadyForNext>,
-    children: Query<&Children>,
+    entity: Entity,
+    children: &Query<&Children>,
+    query: &Query<&Handle<Mesh>>,
+    meshes: &mut Assets<Mesh>,
 ) {
-    if *local != 80 {
-        *local += 1;
-        return;
-    }
-    *local += 1;
+    let mut flipped = HashSet::new();
 
-    for child in children.iter_descendants(humanoid_bones.single()) {
+    for child in children.iter_descendants(entity) {
         let Ok(handle) = query.get(child) else {
             continue;
         };
+
+        // Meshes can be shared by several nodes of the avatar.
+        if !flipped.insert(handle.id()) {
+            continue;
+        }
+
         if let Some(mesh) = meshes.get_mut(handle) {
             if let Some(VertexAttributeValues::Float32x3(ref mut positions)) =
                 mesh.attribute_mut(Mesh::ATTRIBUTE_POSITION)
@@ -260,10 +335,12 @@ fn retarget_entity(
     local_transforms: &mut Query<&mut Transform>,
 ) {
     // setup
+    let Some(old_bind) = get_skinned_mesh(entity, skinned_meshes, skinned_mesh_inverse_bindposes)
+    else {
+        return;
+    };
     let mut this_transform = local_transforms.get_mut(entity).unwrap();
     let old_rot = this_transform.rotation;
-    let old_bind =
-        get_skinned_mesh(entity, skinned_meshes, skinned_mesh_inverse_bindposes).unwrap();
     // this should be the rotation that maps the old rotation to the new rotation
     let comp_rot = (old_rot.inverse() * new_rot).normalize();
     // set the bindpose
@@ -4,8 +4,9 @@ use gltf_kun::graph::{
     ByteNode, Extensions, Weight,
 };
 
-use crate::retargeting::VrmRetargetingInitialized;
-use crate::{loader::Vrm, SpringBone, SpringBones};
+use crate::humanoid_bones::HumanoidBonesInitialized;
+use crate::retargeting::{VrmRetargetingEnabled, VrmRetargetingInitialized};
+use crate::{loader::Vrm, nodes::instance_node_entities, GltfNodeIndex, SpringBone, SpringBones};
 
 #[derive(Component)]
 pub struct SpringBonesInitialized;
@@ -13,21 +14,33 @@ pub struct SpringBonesInitialized;
 pub fn set_spring_bones(
     mut commands: Commands,
     mut vrm: Query<
-        (Entity, &mut SpringBones, &Handle<Vrm>, &SceneInstance),
+        (
+            Entity,
+            &mut SpringBones,
+            &Handle<Vrm>,
+            &SceneInstance,
+            Has<VrmRetargetingInitialized>,
+        ),
         (
             Without<SpringBonesInitialized>,
-            With<VrmRetargetingInitialized>,
+            With<HumanoidBonesInitialized>,
         ),
     >,
-    names: Query<(Entity, &Name)>,
+    retargeting: Option<Res<VrmRetargetingEnabled>>,
+    node_indices: Query<&GltfNodeIndex>,
     scene_manager: Res<SceneSpawner>,
     vrms: Res<Assets<Vrm>>,
 ) {
-    for (entity, mut spring_bones, handle, instance) in vrm.iter_mut() {
+    for (entity, mut spring_bones, handle, instance, retargeted) in vrm.iter_mut() {
         if !scene_manager.instance_is_ready(**instance) {
             continue;
         }
 
+        // Spring bones are set up against the retargeted skeleton.
+        if retargeting.is_some() && !retargeted {
+            continue;
+        }
+
         if let Some(vrm) = vrms.get(handle) {
             commands.entity(entity).insert(SpringBonesInitialized);
 
@@ -46,33 +59,16 @@ pub fn set_spring_bones(
                 None => continue,
             };
 
+            let node_entities = instance_node_entities(&scene_manager, **instance, &node_indices);
+
             for bone_group in ext.bone_groups(graph) {
                 let bones = bone_group
                     .bones(graph)
                     .into_iter()
                     .filter_map(|node| {
-                        let node_handle = vrm.gltf.node_handles.get(&node).unwrap();
-
-                        let node_name = vrm.gltf.named_nodes.iter().find_map(|(name, node)| {
-                            if node == node_handle {
-                                Some(name.clone())
-                            } else {
-                                None
-                            }
-                        });
-
-                        let node_name = match node_name {
-                            Some(name) => name,
-                            None => return None,
-                        };
-
-                        names.iter().find_map(|(entity, name)| {
-                            if name.as_str() == node_name.as_str() {
-                                Some(entity)
-                            } else {
-                                None
-                            }
-                        })
+                        doc.node_index(graph, node)
+                            .and_then(|index| node_entities.get(&index))
+                            .copied()
                     })
                     .collect::<Vec<_>>();
 
@@ -0,0 +1,95 @@
+//! Skeletons shared by the posing and IK tests.
+
+use bevy::{
+    ecs::system::RunSystemOnce,
+    prelude::*,
+    tasks::{ComputeTaskPool, TaskPool},
+    transform::systems::{propagate_transforms, sync_simple_transforms},
+    utils::HashMap,
+};
+
+use crate::{ik::BoneRest, retargeting::VrmRetargetingInitialized, BoneName, HumanoidBones};
+
+/// Spawns an avatar root, without bones.
+pub fn spawn_root(world: &mut World) -> Entity {
+    world
+        .spawn((TransformBundle::default(), VrmRetargetingInitialized))
+        .id()
+}
+
+/// Spawns a bone at its `rest` pose under `parent`.
+pub fn spawn_bone(world: &mut World, parent: Entity, rest: Transform) -> Entity {
+    let entity = world
+        .spawn((TransformBundle::from_transform(rest), BoneRest(rest)))
+        .id();
+    world.entity_mut(parent).add_child(entity);
+    entity
+}
+
+/// Spawns an avatar standing at the origin with its hips 1 high, a spine, a head
+/// at 1.55 and legs ending in feet at 0.05. Bones are at rest and propagated.
+pub fn spawn_skeleton(world: &mut World) -> Entity {
+    let bones = [
+        (BoneName::Hips, None, Vec3::Y),
+        (BoneName::Spine, Some(BoneName::Hips), Vec3::Y * 0.1),
+        (BoneName::Chest, Some(BoneName::Spine), Vec3::Y * 0.15),
+        (BoneName::Neck, Some(BoneName::Chest), Vec3::Y * 0.2),
+        (BoneName::Head, Some(BoneName::Neck), Vec3::Y * 0.1),
+        (
+            BoneName::LeftUpperLeg,
+            Some(BoneName::Hips),
+            Vec3::new(0.1, -0.05, 0.0),
+        ),
+        (
+            BoneName::LeftLowerLeg,
+            Some(BoneName::LeftUpperLeg),
+            Vec3::NEG_Y * 0.45,
+        ),
+        (
+            BoneName::LeftFoot,
+            Some(BoneName::LeftLowerLeg),
+            Vec3::NEG_Y * 0.45,
+        ),
+        (
+            BoneName::RightUpperLeg,
+            Some(BoneName::Hips),
+            Vec3::new(-0.1, -0.05, 0.0),
+        ),
+        (
+            BoneName::RightLowerLeg,
+            Some(BoneName::RightUpperLeg),
+            Vec3::NEG_Y * 0.45,
+        ),
+        (
+            BoneName::RightFoot,
+            Some(BoneName::RightLowerLeg),
+            Vec3::NEG_Y * 0.45,
+        ),
+    ];
+
+    let root = spawn_root(world);
+    let mut entities = HashMap::new();
+
+    for (bone, parent, translation) in bones {
+        let parent = parent.map_or(root, |parent| entities[&parent]);
+        let entity = spawn_bone(world, parent, Transform::from_translation(translation));
+        entities.insert(bone, entity);
+    }
+
+    world.entity_mut(root).insert(HumanoidBones(entities));
+    propagate(world);
+
+    root
+}
+
+pub fn propagate(world: &mut World) {
+    // Propagation runs in parallel.
+    ComputeTaskPool::get_or_init(TaskPool::default);
+
+    world.run_system_once(sync_simple_transforms);
+    world.run_system_once(propagate_transforms);
+}
+
+pub fn bone(world: &World, root: Entity, bone: BoneName) -> Entity {
+    world.get::<HumanoidBones>(root).unwrap().0[&bone]
+}
@@ -0,0 +1,130 @@
+//! The [VMC protocol](https://protocol.vmc.info/english), used by motion capture apps
+//! to stream humanoid poses and blend shapes as OSC messages over UDP.
+//!
+//! VMC uses Unity's left-handed coordinates, with bone rotations relative to a T-pose.
+
+use bevy::prelude::*;
+use serde_vrm::vrm0::{BoneName, PresetName};
+
+use crate::expressions::Expression;
+
+mod osc;
+mod receiver;
+mod sender;
+
+pub(crate) use receiver::receive_vmc;
+pub use receiver::{VmcReceiver, VmcReceiverPlugin};
+pub use sender::{VmcSender, VmcSenderPlugin};
+
+/// The port VMC apps send to by default.
+pub const VMC_PORT: u16 = 39539;
+
+/// Mirrors a position between Unity's coordinates and the avatar's.
+fn convert_position(position: Vec3) -> Vec3 {
+    Vec3::new(-position.x, position.y, position.z)
+}
+
+/// Mirrors a rotation between Unity's coordinates and the avatar's.
+fn convert_rotation(rotation: Quat) -> Quat {
+    Quat::from_xyzw(rotation.x, -rotation.y, -rotation.z, rotation.w)
+}
+
+/// Maps a Unity `HumanBodyBones` name, such as `LeftUpperArm`, to its bone.
+fn bone_from_vmc(name: &str) -> Option<BoneName> {
+    let mut chars = name.chars();
+    let first = chars.next()?.to_ascii_lowercase();
+    let name = std::iter::once(first).chain(chars).collect::<String>();
+
+    serde_json::from_value(serde_json::Value::String(name)).ok()
+}
+
+/// The Unity `HumanBodyBones` name of a bone.
+fn bone_to_vmc(bone: &BoneName) -> Option<String> {
+    let serde_json::Value::String(name) = serde_json::to_value(bone).ok()? else {
+        return None;
+    };
+
+    let mut chars = name.chars();
+    let first = chars.next()?.to_ascii_uppercase();
+    Some(std::iter::once(first).chain(chars).collect())
+}
+
+/// Maps a Unity VRM `BlendShapePreset` name, such as `Blink_L`, to its expression.
+/// VRM 1.0 preset names are also accepted.
+fn expression_from_vmc(name: &str) -> Expression {
+    let preset = match name.to_lowercase().as_str() {
+        "neutral" => PresetName::Neutral,
+        "a" => PresetName::A,
+        "i" => PresetName::I,
+        "u" => PresetName::U,
+        "e" => PresetName::E,
+        "o" => PresetName::O,
+        "blink" => PresetName::Blink,
+        "blink_l" => PresetName::BlinkLeft,
+        "blink_r" => PresetName::BlinkRight,
+        "joy" => PresetName::Joy,
+        "angry" => PresetName::Angry,
+        "sorrow" => PresetName::Sorrow,
+        "fun" => PresetName::Fun,
+        "lookup" => PresetName::LookUp,
+        "lookdown" => PresetName::LookDown,
+        "lookleft" => PresetName::LookLeft,
+        "lookright" => PresetName::LookRight,
+        _ => return Expression::from_vrm1_preset(name),
+    };
+
+    Expression::Preset(preset)
+}
+
+/// The Unity VRM `BlendShapePreset` name of an expression, or the name of a custom one.
+fn expression_to_vmc(expression: &Expression) -> String {
+    let preset = match expression {
+        Expression::Preset(preset) => preset,
+        Expression::Custom(name) => return name.clone(),
+    };
+
+    let name = match preset {
+        PresetName::Unknown => "Unknown",
+        PresetName::Neutral => "Neutral",
+        PresetName::A => "A",
+        PresetName::I => "I",
+        PresetName::U => "U",
+        PresetName::E => "E",
+        PresetName::O => "O",
+        PresetName::Blink => "Blink",
+        PresetName::BlinkLeft => "Blink_L",
+        PresetName::BlinkRight => "Blink_R",
+        PresetName::Joy => "Joy",
+        PresetName::Angry => "Angry",
+        PresetName::Sorrow => "Sorrow",
+        PresetName::Fun => "Fun",
+        PresetName::LookUp => "LookUp",
+        PresetName::LookDown => "LookDown",
+        PresetName::LookLeft => "LookLeft",
+        PresetName::LookRight => "LookRight",
+    };
+
+    name.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::VmcReceiver;
+
+    /// A receiver bound to a free loopback port.
+    pub(super) fn loopback_receiver() -> VmcReceiver {
+        VmcReceiver::bind("127.0.0.1:0").unwrap()
+    }
+
+    /// Receives packets until `done` or a second has passed.
+    pub(super) fn receive_until(receiver: &mut VmcReceiver, done: impl Fn(&VmcReceiver) -> bool) {
+        let start = Instant::now();
+
+        while !done(receiver) && start.elapsed() < Duration::from_secs(1) {
+            receiver.receive();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+}
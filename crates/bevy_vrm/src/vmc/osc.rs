@@ -0,0 +1,207 @@
+//! Minimal [OSC 1.0](https://opensoundcontrol.stanford.edu/spec-1_0.html) encoding,
+//! covering the argument types used by the VMC protocol.
+
+use thiserror::Error;
+
+const BUNDLE_TAG: &[u8] = b"#bundle\0";
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum OscArg {
+    Int(i32),
+    Float(f32),
+    String(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+impl OscMessage {
+    pub fn new(address: &str, args: Vec<OscArg>) -> Self {
+        Self {
+            address: address.to_string(),
+            args,
+        }
+    }
+
+    pub fn string(&self, index: usize) -> Option<&str> {
+        match self.args.get(index)? {
+            OscArg::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn float(&self, index: usize) -> Option<f32> {
+        match self.args.get(index)? {
+            OscArg::Float(value) => Some(*value),
+            OscArg::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub(crate) enum OscError {
+    #[error("Unexpected end of packet")]
+    UnexpectedEnd,
+    #[error("Invalid string")]
+    InvalidString,
+    #[error("Missing type tags")]
+    MissingTypeTags,
+    #[error("Unsupported argument type: {0}")]
+    UnsupportedType(char),
+}
+
+/// Decodes a packet, flattening any bundles into their messages.
+pub(crate) fn decode_packet(bytes: &[u8]) -> Result<Vec<OscMessage>, OscError> {
+    let mut messages = Vec::new();
+    decode_into(bytes, &mut messages)?;
+    Ok(messages)
+}
+
+fn decode_into(bytes: &[u8], messages: &mut Vec<OscMessage>) -> Result<(), OscError> {
+    let Some(mut rest) = bytes.strip_prefix(BUNDLE_TAG) else {
+        messages.push(decode_message(bytes)?);
+        return Ok(());
+    };
+
+    // Bundles are played back immediately, so the time tag is ignored.
+    take(&mut rest, 8)?;
+
+    while !rest.is_empty() {
+        let size = read_i32(&mut rest)?.max(0) as usize;
+        let element = take(&mut rest, size)?;
+        decode_into(element, messages)?;
+    }
+
+    Ok(())
+}
+
+fn decode_message(mut bytes: &[u8]) -> Result<OscMessage, OscError> {
+    let address = read_string(&mut bytes)?;
+
+    // Old implementations may omit the type tags of messages without arguments.
+    if bytes.is_empty() {
+        return Ok(OscMessage::new(&address, Vec::new()));
+    }
+
+    let tags = read_string(&mut bytes)?;
+    let tags = tags.strip_prefix(',').ok_or(OscError::MissingTypeTags)?;
+
+    let mut args = Vec::new();
+
+    for tag in tags.chars() {
+        let arg = match tag {
+            'i' => OscArg::Int(read_i32(&mut bytes)?),
+            'f' => OscArg::Float(f32::from_bits(read_i32(&mut bytes)? as u32)),
+            's' => OscArg::String(read_string(&mut bytes)?),
+            // Booleans, nil and impulses carry no data.
+            'T' | 'F' | 'N' | 'I' => continue,
+            tag => return Err(OscError::UnsupportedType(tag)),
+        };
+        args.push(arg);
+    }
+
+    Ok(OscMessage { address, args })
+}
+
+fn take<'a>(bytes: &mut &'a [u8], len: usize) -> Result<&'a [u8], OscError> {
+    if bytes.len() < len {
+        return Err(OscError::UnexpectedEnd);
+    }
+
+    let (taken, rest) = bytes.split_at(len);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn read_i32(bytes: &mut &[u8]) -> Result<i32, OscError> {
+    let value = take(bytes, 4)?;
+    Ok(i32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+/// A null-terminated string, padded to a multiple of 4 bytes.
+fn read_string(bytes: &mut &[u8]) -> Result<String, OscError> {
+    let len = bytes
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(OscError::UnexpectedEnd)?;
+    let value = take(bytes, padded_len(len + 1))?;
+
+    String::from_utf8(value[..len].to_vec()).map_err(|_| OscError::InvalidString)
+}
+
+fn padded_len(len: usize) -> usize {
+    len.div_ceil(4) * 4
+}
+
+pub(crate) fn encode_message(message: &OscMessage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    write_string(&mut bytes, &message.address);
+
+    let tags = message
+        .args
+        .iter()
+        .map(|arg| match arg {
+            OscArg::Int(_) => 'i',
+            OscArg::Float(_) => 'f',
+            OscArg::String(_) => 's',
+        })
+        .collect::<String>();
+    write_string(&mut bytes, &format!(",{}", tags));
+
+    for arg in message.args.iter() {
+        match arg {
+            OscArg::Int(value) => bytes.extend(value.to_be_bytes()),
+            OscArg::Float(value) => bytes.extend(value.to_be_bytes()),
+            OscArg::String(value) => write_string(&mut bytes, value),
+        }
+    }
+
+    bytes
+}
+
+/// Encodes `messages` into a bundle to be processed immediately.
+pub(crate) fn encode_bundle(messages: &[OscMessage]) -> Vec<u8> {
+    let mut bytes = BUNDLE_TAG.to_vec();
+    bytes.extend(1u64.to_be_bytes());
+
+    for message in messages {
+        let encoded = encode_message(message);
+        bytes.extend((encoded.len() as i32).to_be_bytes());
+        bytes.extend(encoded);
+    }
+
+    bytes
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    let start = bytes.len();
+    bytes.extend(value.as_bytes());
+    bytes.resize(start + padded_len(value.len() + 1), 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundles_round_trip() {
+        let messages = vec![
+            OscMessage::new(
+                "/VMC/Ext/Blend/Val",
+                vec![OscArg::String("Joy".to_string()), OscArg::Float(0.5)],
+            ),
+            OscMessage::new("/VMC/Ext/OK", vec![OscArg::Int(1)]),
+            OscMessage::new("/VMC/Ext/Blend/Apply", Vec::new()),
+        ];
+
+        let bytes = encode_bundle(&messages);
+        assert!(bytes.len().is_multiple_of(4));
+        assert_eq!(decode_packet(&bytes).unwrap(), messages);
+
+        assert!(decode_packet(&bytes[..bytes.len() - 4]).is_err());
+    }
+}
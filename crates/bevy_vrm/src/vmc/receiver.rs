@@ -0,0 +1,215 @@
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use bevy::{animation::animation_player, prelude::*, utils::HashMap};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    animation::play_vrm_animations,
+    expressions::{apply_expressions, Expression, VrmExpressions},
+    ik::IkSet,
+    pose::{evaluate_pose_stacks, HumanPose, HumanPoses},
+};
+
+use super::{
+    bone_from_vmc, convert_position, convert_rotation, expression_from_vmc,
+    osc::{decode_packet, OscMessage},
+};
+
+/// Drives avatars with a [`VmcReceiver`] from the VMC messages they receive.
+pub struct VmcReceiverPlugin;
+
+impl Plugin for VmcReceiverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            receive_vmc
+                .after(animation_player)
+                .after(play_vrm_animations)
+                .before(evaluate_pose_stacks)
+                .before(apply_expressions)
+                .before(IkSet::Body),
+        );
+    }
+}
+
+/// Listens for VMC messages on a UDP socket and applies them to the avatar.
+/// Bones, blend shapes and the root keep their last received value.
+#[derive(Component, Debug)]
+pub struct VmcReceiver {
+    socket: UdpSocket,
+    /// Whether to move the avatar to the sender's root, relative to its parent.
+    pub apply_root: bool,
+    pub(super) rotations: HashMap<BoneName, Quat>,
+    hips_position: Option<Vec3>,
+    root: Option<Transform>,
+    pub(super) expressions: HashMap<Expression, f32>,
+    /// Blend shape values waiting for `/VMC/Ext/Blend/Apply`.
+    pending_expressions: HashMap<Expression, f32>,
+}
+
+impl VmcReceiver {
+    /// Listens on `addr`, such as `("0.0.0.0", VMC_PORT)`.
+    pub fn bind(addr: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            apply_root: false,
+            rotations: HashMap::new(),
+            hips_position: None,
+            root: None,
+            expressions: HashMap::new(),
+            pending_expressions: HashMap::new(),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Handles every packet waiting on the socket.
+    pub(super) fn receive(&mut self) {
+        let mut buffer = [0; u16::MAX as usize];
+
+        loop {
+            let len = match self.socket.recv(&mut buffer) {
+                Ok(len) => len,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    warn!("Failed to receive VMC packet: {}", e);
+                    return;
+                }
+            };
+
+            match decode_packet(&buffer[..len]) {
+                Ok(messages) => messages.iter().for_each(|m| self.handle(m)),
+                Err(e) => warn!("Invalid VMC packet: {}", e),
+            }
+        }
+    }
+
+    fn handle(&mut self, message: &OscMessage) {
+        let transform = || {
+            let [px, py, pz, qx, qy, qz, qw] = [1, 2, 3, 4, 5, 6, 7].map(|i| message.float(i));
+            Some(Transform {
+                translation: convert_position(Vec3::new(px?, py?, pz?)),
+                rotation: convert_rotation(Quat::from_xyzw(qx?, qy?, qz?, qw?).normalize()),
+                scale: Vec3::ONE,
+            })
+        };
+
+        match message.address.as_str() {
+            "/VMC/Ext/Root/Pos" => {
+                if let Some(transform) = transform() {
+                    self.root = Some(transform);
+                }
+            }
+            "/VMC/Ext/Bone/Pos" => {
+                let (Some(bone), Some(transform)) =
+                    (message.string(0).and_then(bone_from_vmc), transform())
+                else {
+                    return;
+                };
+
+                if bone == BoneName::Hips {
+                    self.hips_position = Some(transform.translation);
+                }
+
+                self.rotations.insert(bone, transform.rotation);
+            }
+            "/VMC/Ext/Blend/Val" => {
+                if let (Some(name), Some(value)) = (message.string(0), message.float(1)) {
+                    self.pending_expressions
+                        .insert(expression_from_vmc(name), value);
+                }
+            }
+            "/VMC/Ext/Blend/Apply" => {
+                self.expressions.extend(self.pending_expressions.drain());
+            }
+            _ => {}
+        }
+    }
+}
+
+pub(crate) fn receive_vmc(
+    mut receivers: Query<(Entity, &mut VmcReceiver, Option<&mut VrmExpressions>)>,
+    mut params: ParamSet<(HumanPoses, Query<&mut Transform>)>,
+) {
+    for (entity, mut receiver, expressions) in receivers.iter_mut() {
+        receiver.receive();
+
+        if !receiver.rotations.is_empty() {
+            let hips_position = receiver
+                .hips_position
+                .or_else(|| params.p0().rest_hips_position(entity));
+
+            if let Some(hips_position) = hips_position {
+                let pose = HumanPose {
+                    hips_position,
+                    rotations: receiver.rotations.clone(),
+                };
+                params.p0().apply_pose(entity, &pose);
+            }
+        }
+
+        if let Some(mut expressions) = expressions {
+            for (expression, weight) in receiver.expressions.iter() {
+                expressions.set(expression.clone(), *weight);
+            }
+        }
+
+        if let (true, Some(root)) = (receiver.apply_root, receiver.root) {
+            if let Ok(mut transform) = params.p1().get_mut(entity) {
+                transform.translation = root.translation;
+                transform.rotation = root.rotation;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vmc::{
+        osc::{encode_bundle, OscArg},
+        tests::{loopback_receiver, receive_until},
+    };
+
+    #[test]
+    fn receives_loopback_packets() {
+        let mut receiver = loopback_receiver();
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let rotation = Quat::from_rotation_z(0.5);
+        let mut bone = vec![OscArg::String("LeftUpperArm".to_string())];
+        bone.extend(
+            [
+                0.0, 0.0, 0.0, rotation.x, rotation.y, rotation.z, rotation.w,
+            ]
+            .map(OscArg::Float),
+        );
+
+        let packet = encode_bundle(&[
+            OscMessage::new("/VMC/Ext/Bone/Pos", bone),
+            OscMessage::new(
+                "/VMC/Ext/Blend/Val",
+                vec![OscArg::String("Blink_L".to_string()), OscArg::Float(0.75)],
+            ),
+            OscMessage::new("/VMC/Ext/Blend/Apply", Vec::new()),
+        ]);
+        sender
+            .send_to(&packet, receiver.local_addr().unwrap())
+            .unwrap();
+
+        receive_until(&mut receiver, |receiver| !receiver.rotations.is_empty());
+
+        // Unity's Z rotations turn the other way.
+        let received = receiver.rotations[&BoneName::LeftUpperArm];
+        assert!(received.angle_between(Quat::from_rotation_z(-0.5)) < 0.001);
+        assert_eq!(receiver.expressions[&Expression::BLINK_LEFT], 0.75);
+    }
+}
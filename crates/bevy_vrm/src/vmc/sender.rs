@@ -0,0 +1,250 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use bevy::{prelude::*, transform::TransformSystem};
+use serde_vrm::vrm0::BoneName;
+
+use crate::{
+    expressions::{apply_expressions, VrmExpressions},
+    ik::IkSet,
+    pose::{HumanPose, HumanPoses},
+};
+
+use super::{
+    bone_to_vmc, convert_position, convert_rotation, expression_to_vmc,
+    osc::{encode_bundle, OscArg, OscMessage},
+};
+
+/// Streams the pose of avatars with a [`VmcSender`] as VMC messages.
+pub struct VmcSenderPlugin;
+
+impl Plugin for VmcSenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PostUpdate,
+            send_vmc
+                .after(IkSet::Limbs)
+                .after(apply_expressions)
+                .before(TransformSystem::TransformPropagate),
+        );
+    }
+}
+
+/// Sends the avatar's bones, root and [`VrmExpressions`] to a VMC receiver.
+/// Bones are sent as rotations from the T-pose. Only the hips have a position,
+/// as receivers keep the proportions of their own avatar.
+#[derive(Component, Debug)]
+pub struct VmcSender {
+    socket: UdpSocket,
+    target: SocketAddr,
+    /// Packets sent per second.
+    pub rate: f32,
+    elapsed: f32,
+    since_sent: f32,
+}
+
+impl VmcSender {
+    /// Sends to `target`, such as `("127.0.0.1", VMC_PORT)`, at 60 packets per second.
+    pub fn new(target: impl ToSocketAddrs) -> std::io::Result<Self> {
+        let target = target.to_socket_addrs()?.next().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "No target address")
+        })?;
+
+        let bind: SocketAddr = if target.is_ipv4() {
+            ([0, 0, 0, 0], 0).into()
+        } else {
+            ([0; 16], 0).into()
+        };
+
+        let socket = UdpSocket::bind(bind)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self {
+            socket,
+            target,
+            rate: 60.0,
+            elapsed: 0.0,
+            since_sent: f32::INFINITY,
+        })
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate;
+        self
+    }
+
+    fn send(&self, messages: &[OscMessage]) {
+        if let Err(e) = self.socket.send_to(&encode_bundle(messages), self.target) {
+            warn!("Failed to send VMC packet: {}", e);
+        }
+    }
+}
+
+pub(crate) fn send_vmc(
+    time: Res<Time>,
+    poses: HumanPoses,
+    mut senders: Query<(
+        Entity,
+        &mut VmcSender,
+        &GlobalTransform,
+        Option<&VrmExpressions>,
+    )>,
+) {
+    for (entity, mut sender, global_transform, expressions) in senders.iter_mut() {
+        sender.elapsed += time.delta_seconds();
+        sender.since_sent += time.delta_seconds();
+
+        let interval = 1.0 / sender.rate;
+
+        if sender.rate <= 0.0 || sender.since_sent < interval {
+            continue;
+        }
+
+        let Some(pose) = poses.read_pose(entity) else {
+            continue;
+        };
+
+        // The leftover time carries over so the rate holds between frame rates,
+        // but not after a stall, which would send a burst.
+        let leftover = sender.since_sent - interval;
+        sender.since_sent = if leftover < interval { leftover } else { 0.0 };
+        // The local transforms are borrowed by `poses`.
+        let transform = global_transform.compute_transform();
+        sender.send(&messages(sender.elapsed, &transform, &pose, expressions));
+    }
+}
+
+/// The messages of one frame.
+fn messages(
+    time: f32,
+    root: &Transform,
+    pose: &HumanPose,
+    expressions: Option<&VrmExpressions>,
+) -> Vec<OscMessage> {
+    let mut messages = vec![
+        OscMessage::new("/VMC/Ext/OK", vec![OscArg::Int(1)]),
+        OscMessage::new("/VMC/Ext/T", vec![OscArg::Float(time)]),
+        transform_message("/VMC/Ext/Root/Pos", "root", root.translation, root.rotation),
+    ];
+
+    for (bone, rotation) in pose.rotations.iter() {
+        let Some(name) = bone_to_vmc(bone) else {
+            continue;
+        };
+
+        let position = match bone {
+            BoneName::Hips => pose.hips_position,
+            _ => Vec3::ZERO,
+        };
+
+        messages.push(transform_message(
+            "/VMC/Ext/Bone/Pos",
+            &name,
+            position,
+            *rotation,
+        ));
+    }
+
+    if let Some(expressions) = expressions {
+        for (expression, weight) in expressions.0.iter() {
+            messages.push(OscMessage::new(
+                "/VMC/Ext/Blend/Val",
+                vec![
+                    OscArg::String(expression_to_vmc(expression)),
+                    OscArg::Float(*weight),
+                ],
+            ));
+        }
+
+        messages.push(OscMessage::new("/VMC/Ext/Blend/Apply", Vec::new()));
+    }
+
+    messages
+}
+
+fn transform_message(address: &str, name: &str, position: Vec3, rotation: Quat) -> OscMessage {
+    let position = convert_position(position);
+    let rotation = convert_rotation(rotation);
+
+    let mut args = vec![OscArg::String(name.to_string())];
+    args.extend(position.to_array().map(OscArg::Float));
+    args.extend(rotation.to_array().map(OscArg::Float));
+
+    OscMessage::new(address, args)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use bevy::{ecs::system::RunSystemOnce, utils::HashMap};
+
+    use super::*;
+    use crate::{
+        expressions::Expression,
+        test_utils::spawn_skeleton,
+        vmc::tests::{loopback_receiver, receive_until},
+    };
+
+    #[test]
+    fn sent_pose_is_received() {
+        let mut receiver = loopback_receiver();
+        let sender = VmcSender::new(receiver.local_addr().unwrap()).unwrap();
+
+        let mut pose = HumanPose {
+            hips_position: Vec3::new(0.1, 0.9, 0.0),
+            rotations: HashMap::new(),
+        };
+        pose.rotations
+            .insert(BoneName::Hips, Quat::from_rotation_y(0.5));
+        pose.rotations
+            .insert(BoneName::LeftThumbProximal, Quat::from_rotation_x(0.3));
+
+        let mut expressions = VrmExpressions::default();
+        expressions.set(Expression::JOY, 0.6);
+        expressions.set(Expression::Custom("Smirk".to_string()), 0.2);
+
+        sender.send(&messages(
+            0.0,
+            &Transform::IDENTITY,
+            &pose,
+            Some(&expressions),
+        ));
+
+        receive_until(&mut receiver, |receiver| !receiver.expressions.is_empty());
+
+        for (bone, rotation) in pose.rotations.iter() {
+            assert!(receiver.rotations[bone].angle_between(*rotation) < 0.0001);
+        }
+        assert_eq!(receiver.expressions, expressions.0);
+    }
+
+    #[test]
+    fn packets_are_sent_at_the_rate() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+
+        let mut world = World::new();
+        world.init_resource::<Time>();
+        let root = spawn_skeleton(&mut world);
+        let sender = VmcSender::new(socket.local_addr().unwrap()).unwrap();
+        world.entity_mut(root).insert(sender);
+
+        // A second at 100 fps, which is not a multiple of the rate.
+        for _ in 0..100 {
+            world
+                .resource_mut::<Time>()
+                .advance_by(Duration::from_millis(10));
+            world.run_system_once(send_vmc);
+        }
+
+        let mut buffer = [0; 4096];
+        let mut count = 0;
+        while socket.recv(&mut buffer).is_ok() {
+            count += 1;
+        }
+
+        assert!((59..=61).contains(&count), "{count}");
+    }
+}
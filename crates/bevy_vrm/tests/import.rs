@@ -0,0 +1,261 @@
+//! Imports the bundled avatars in a headless app, without a GPU.
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::LoadState,
+    ecs::system::SystemState,
+    pbr::PbrPlugin,
+    prelude::*,
+    render::{
+        settings::{RenderCreation, WgpuSettings},
+        RenderPlugin,
+    },
+    scene::ScenePlugin,
+    utils::HashSet,
+};
+use bevy_gltf_kun::import::gltf::mesh::GltfMesh;
+use bevy_vrm::{
+    expressions::{Expression, ExpressionBinds},
+    license::Usage,
+    loader::Vrm,
+    mtoon::MtoonMaterial,
+    retargeting::VrmRetargetingPlugin,
+    BoneName, HumanoidBones, SpringBones, VrmBundle, VrmPlugin, VrmReady,
+};
+use gltf_kun::graph::{
+    gltf::{GltfDocument, GltfWeight},
+    ByteNode, Extensions, Weight,
+};
+use gltf_kun_vrm::vrm0::weight::Meta;
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+fn headless_app() -> App {
+    let mut app = App::new();
+
+    app.add_plugins((
+        MinimalPlugins,
+        AssetPlugin {
+            file_path: "../../assets".to_string(),
+            ..default()
+        },
+        WindowPlugin {
+            primary_window: None,
+            ..default()
+        },
+        TransformPlugin,
+        HierarchyPlugin,
+        // Registers the mesh, skin and shader assets the importer and the MToon material
+        // create. Without backends it never requests a GPU.
+        RenderPlugin {
+            render_creation: RenderCreation::Automatic(WgpuSettings {
+                backends: None,
+                ..default()
+            }),
+            ..default()
+        },
+        ImagePlugin::default(),
+        PbrPlugin::default(),
+        ScenePlugin,
+        AnimationPlugin,
+        VrmPlugin,
+    ));
+
+    app
+}
+
+/// Spawns the avatar at `path` and updates the app until it is ready.
+fn load_vrm(path: &'static str) -> (App, Entity) {
+    let (app, entities) = load_vrms(headless_app(), path, 1);
+    (app, entities[0])
+}
+
+/// Spawns `count` avatars from the same `path` and updates the app until they are all ready.
+fn load_vrms(mut app: App, path: &'static str, count: usize) -> (App, Vec<Entity>) {
+    let handle = app.world.resource::<AssetServer>().load::<Vrm>(path);
+    let entities = (0..count)
+        .map(|_| {
+            app.world
+                .spawn(VrmBundle {
+                    vrm: handle.clone(),
+                    ..default()
+                })
+                .id()
+        })
+        .collect::<Vec<_>>();
+
+    let mut ready = HashSet::new();
+    let start = Instant::now();
+
+    while start.elapsed() < TIMEOUT {
+        app.update();
+
+        if let Some(LoadState::Failed) = app.world.resource::<AssetServer>().get_load_state(&handle)
+        {
+            panic!("failed to load {}", path);
+        }
+
+        let events = app.world.resource::<Events<VrmReady>>();
+        ready.extend(events.get_reader().read(events).map(|event| event.entity));
+
+        if entities.iter().all(|entity| ready.contains(entity)) {
+            return (app, entities);
+        }
+    }
+
+    panic!("{} was not ready after {:?}", path, TIMEOUT);
+}
+
+fn meta(app: &App, entity: Entity) -> Meta {
+    let handle = app.world.get::<Handle<Vrm>>(entity).unwrap();
+    let vrm = app.world.resource::<Assets<Vrm>>().get(handle).unwrap();
+    let graph = &vrm.gltf.graph;
+
+    let doc = graph
+        .node_indices()
+        .find(|n| {
+            let weight = graph.node_weight(*n);
+            matches!(weight, Some(Weight::Gltf(GltfWeight::Document)))
+        })
+        .map(GltfDocument)
+        .unwrap();
+
+    let ext = doc.get_extension::<gltf_kun_vrm::vrm0::Vrm>(graph).unwrap();
+
+    ext.read(graph).meta
+}
+
+fn license(app: &App, entity: Entity) -> bevy_vrm::license::VrmLicense {
+    let handle = app.world.get::<Handle<Vrm>>(entity).unwrap();
+    let vrm = app.world.resource::<Assets<Vrm>>().get(handle).unwrap();
+    vrm.license().unwrap()
+}
+
+#[test]
+fn import_catbot() {
+    let (app, entity) = load_vrm("catbot.vrm");
+
+    let humanoid_bones = app.world.get::<HumanoidBones>(entity).unwrap();
+    assert_eq!(humanoid_bones.0.len(), 54);
+    assert!(humanoid_bones.0.contains_key(&BoneName::Hips));
+    assert!(humanoid_bones.0.contains_key(&BoneName::LeftHand));
+
+    let spring_bones = app.world.get::<SpringBones>(entity).unwrap();
+    assert_eq!(spring_bones.0.len(), 1);
+    assert_eq!(spring_bones.0[0].drag_force, 0.4);
+    assert_eq!(spring_bones.0[0].gravity_dir, Vec3::NEG_Y);
+
+    let binds = app.world.get::<ExpressionBinds>(entity).unwrap();
+    assert_eq!(binds.expressions().count(), 18);
+    assert!(binds.contains(&Expression::A));
+    assert!(binds.contains(&Expression::JOY));
+
+    assert_eq!(app.world.resource::<Assets<MtoonMaterial>>().len(), 1);
+
+    let meta = meta(&app, entity);
+    assert_eq!(meta.title.as_deref(), Some("フリット 256fes ver."));
+    assert_eq!(meta.version.as_deref(), Some("1.0.0"));
+    assert_eq!(
+        meta.contact_information.as_deref(),
+        Some("https://tabimal.booth.pm/items/4943875")
+    );
+    assert_eq!(meta.license_name.as_deref(), Some("Other"));
+
+    let license = license(&app, entity);
+    assert!(license.permits(Usage::Personation));
+    assert!(license.permits(Usage::ExcessivelyViolent));
+    assert!(!license.permits(Usage::Commercial));
+}
+
+#[test]
+fn import_cool_loops() {
+    let (app, entity) = load_vrm("cool_loops.vrm");
+
+    let humanoid_bones = app.world.get::<HumanoidBones>(entity).unwrap();
+    assert_eq!(humanoid_bones.0.len(), 50);
+    assert!(humanoid_bones.0.contains_key(&BoneName::Hips));
+    assert!(humanoid_bones.0.contains_key(&BoneName::Head));
+
+    let spring_bones = app.world.get::<SpringBones>(entity).unwrap();
+    assert_eq!(spring_bones.0.len(), 1);
+    assert_eq!(spring_bones.0[0].hit_radius, 0.02);
+
+    assert_eq!(app.world.resource::<Assets<MtoonMaterial>>().len(), 1);
+
+    let meta = meta(&app, entity);
+    assert_eq!(meta.title.as_deref(), Some("Cool_loops"));
+    assert_eq!(meta.version.as_deref(), Some("1"));
+    assert_eq!(meta.author.as_deref(), Some("Polygonal Mind"));
+    assert_eq!(
+        meta.contact_information.as_deref(),
+        Some("www.polygonalmind.com")
+    );
+
+    let license = license(&app, entity);
+    assert!(license.permits(Usage::Personation));
+    assert!(license.permits(Usage::Commercial));
+    assert!(license.permits(Usage::ExcessivelySexual));
+}
+
+fn avatar_meshes(app: &mut App, avatar: Entity) -> Vec<Handle<Mesh>> {
+    let mut state = SystemState::<(Query<&Children>, Query<&Handle<Mesh>>)>::new(&mut app.world);
+    let (children, meshes) = state.get(&app.world);
+
+    children
+        .iter_descendants(avatar)
+        .filter_map(|entity| meshes.get(entity).ok().cloned())
+        .collect()
+}
+
+/// Sum of the vertex positions of each distinct mesh in `handles`.
+fn position_sum<'a>(app: &App, handles: impl Iterator<Item = &'a Handle<Mesh>>) -> Vec3 {
+    let meshes = app.world.resource::<Assets<Mesh>>();
+    let mut seen = HashSet::new();
+
+    handles
+        .filter(|handle| seen.insert(handle.id()))
+        .filter_map(|handle| meshes.get(handle)?.attribute(Mesh::ATTRIBUTE_POSITION))
+        .filter_map(|positions| positions.as_float3())
+        .flatten()
+        .map(|position| Vec3::from_array(*position))
+        .sum()
+}
+
+#[test]
+fn avatars_from_the_same_asset_are_flipped_once() {
+    let mut app = headless_app();
+    app.add_plugins(VrmRetargetingPlugin);
+
+    let (mut app, entities) = load_vrms(app, "catbot.vrm", 2);
+
+    let vrm = app.world.get::<Handle<Vrm>>(entities[0]).unwrap();
+    let vrm = app.world.resource::<Assets<Vrm>>().get(vrm).unwrap();
+    let gltf_meshes = app.world.resource::<Assets<GltfMesh>>();
+    let originals = vrm
+        .gltf
+        .meshes
+        .iter()
+        .filter_map(|handle| gltf_meshes.get(handle))
+        .flat_map(|mesh| {
+            mesh.primitives
+                .iter()
+                .map(|primitive| primitive.mesh.clone())
+        })
+        .collect::<Vec<_>>();
+
+    let first = avatar_meshes(&mut app, entities[0]);
+    let second = avatar_meshes(&mut app, entities[1]);
+
+    // Each avatar edits its own copy of the meshes.
+    assert!(!first.is_empty());
+    assert!(first.iter().all(|handle| !second.contains(handle)));
+    assert!(first.iter().all(|handle| !originals.contains(handle)));
+
+    let original = position_sum(&app, originals.iter());
+    let flipped = Vec3::new(-original.x, original.y, -original.z);
+
+    for meshes in [first, second] {
+        assert!(position_sum(&app, meshes.iter()).distance(flipped) < 0.01);
+    }
+}
@@ -1,10 +1,10 @@
-use gltf_kun::graph::{gltf::Primitive, ByteNode, Graph, NodeIndex, OtherEdgeHelpers, Weight};
+use gltf_kun::graph::{gltf::Mesh, ByteNode, Graph, NodeIndex, OtherEdgeHelpers, Weight};
 use serde::{Deserialize, Serialize};
 
 #[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
 pub enum BindEdges {
-    #[serde(rename = "VRM/Bind/Primitive")]
-    Primitive,
+    #[serde(rename = "VRM/Bind/Mesh")]
+    Mesh,
 }
 
 impl ToString for BindEdges {
@@ -15,6 +15,9 @@ impl ToString for BindEdges {
 
 #[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct BindWeight {
+    /// Index of the morph target in the mesh.
+    pub index: Option<u32>,
+    /// Weight of the morph target at full expression, from 0 to 100.
     pub weight: Option<f32>,
 }
 
@@ -57,10 +60,10 @@ impl Bind {
         Self(graph.add_node(Weight::Bytes(weight.into())))
     }
 
-    pub fn primitive(&self, graph: &Graph) -> Option<Primitive> {
-        self.find_property(graph, &BindEdges::Primitive.to_string())
+    pub fn mesh(&self, graph: &Graph) -> Option<Mesh> {
+        self.find_property(graph, &BindEdges::Mesh.to_string())
     }
-    pub fn set_primitive(&self, graph: &mut Graph, primitive: Option<Primitive>) {
-        self.set_property(graph, BindEdges::Primitive.to_string(), primitive);
+    pub fn set_mesh(&self, graph: &mut Graph, mesh: Option<Mesh>) {
+        self.set_property(graph, BindEdges::Mesh.to_string(), mesh);
     }
 }
@@ -232,23 +232,22 @@ impl ExtensionImport<GltfDocument, GltfFormat> for Vrm {
 
             for group_json in blend_shape_groups {
                 let group = BlendShapeGroup::new(graph);
+                vrm.add_blend_shape_group(graph, group);
 
                 let binds = group_json.binds.unwrap_or_default();
 
                 for bind_json in binds {
                     let bind = Bind::new(graph);
+                    group.add_bind(graph, bind);
 
                     if let Some(mesh_idx) = bind_json.mesh {
                         if let Some(mesh) = doc.meshes(graph).get(mesh_idx as usize) {
-                            let index = bind_json.index.unwrap_or_default();
-
-                            if let Some(primitive) = mesh.primitives(graph).get(index as usize) {
-                                bind.set_primitive(graph, Some(*primitive));
-                            }
+                            bind.set_mesh(graph, Some(*mesh));
                         }
                     }
 
                     let weight = BindWeight {
+                        index: bind_json.index,
                         weight: bind_json.weight,
                     };
 
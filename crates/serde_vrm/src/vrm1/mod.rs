@@ -2,3 +2,4 @@
 
 pub mod vrmc_materials_mtoon;
 pub mod vrmc_vrm;
+pub mod vrmc_vrm_animation;
@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct VrmcVrmAnimation {
+    #[serde(rename = "specVersion")]
+    pub spec_version: String,
+    pub humanoid: Option<Humanoid>,
+    pub expressions: Option<Expressions>,
+    #[serde(rename = "lookAt")]
+    pub look_at: Option<LookAt>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Humanoid {
+    /// Nodes animated as each humanoid bone, by VRM 1.0 bone name.
+    #[serde(rename = "humanBones")]
+    pub human_bones: HashMap<String, HumanBone>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct HumanBone {
+    pub node: u32,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Expressions {
+    /// Preset expressions, by VRM 1.0 preset name.
+    pub preset: Option<HashMap<String, Expression>>,
+    pub custom: Option<HashMap<String, Expression>>,
+}
+
+/// A node whose X translation holds the expression weight.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Expression {
+    pub node: u32,
+}
+
+/// A node whose translation is the look-at target.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LookAt {
+    pub node: u32,
+    #[serde(rename = "offsetFromHeadBone")]
+    pub offset_from_head_bone: Option<[f32; 3]>,
+}